@@ -12,10 +12,10 @@ import {
 import {Links, Meta, Scripts, useLoaderData, useRouteError} from "@remix-run/react";
 import type {Consumption, EnrichedRoomData, PriceInfo} from "./types";
 import {PriceLevel} from "./types";
-import {useState} from "react";
+import {useEffect, useState} from "react";
 import ConsumptionGraph from "~/components/consumptionGraph";
 import type {LiveConsumptionChange, LiveConsumptionData} from "~/routes/liveData";
-import {fromSseEvent} from "~/routes/liveData";
+import {addSseSample, fromSseEvent, setSseWindow} from "~/routes/liveData";
 import {ClientOnly} from "remix-utils/client-only";
 import {formatPriceInfo} from "~/utils/formattingUtils";
 import {formatDistanceToNow} from 'date-fns';
@@ -28,7 +28,6 @@ import {Tabs, TabsContent, TabsList, TabsTrigger} from "~/components/ui/tabs";
 import {Switch} from "~/components/ui/switch";
 import {Theme, useTheme} from "remix-themes";
 import {getErrorComponent} from "~/components/error";
-import {useEventSource} from "remix-utils/sse/react";
 import FrontPageRooms from "~/components/frontPageRooms";
 import {isWithinIntervalInSeconds} from "~/utils/time";
 
@@ -68,11 +67,20 @@ export default function Index() {
     const data = useLoaderData<ResponseData>();
     const [hideUnscheduledRooms, setHideUnscheduledRooms] = useState(true);
 
-    const liveConsumptionSseData = useEventSource("sse/liveConsumption")
-    const liveConsumptionData = (liveConsumptionSseData ?
-        fromSseEvent(liveConsumptionSseData)
-        : null)
-        ?? data.liveConsumption;
+    const [liveConsumptionData, setLiveConsumptionData] = useState<LiveConsumptionData>(data.liveConsumption);
+    useEffect(() => {
+        const eventSource = new EventSource("sse/liveConsumption");
+        eventSource.addEventListener("window", (e: MessageEvent) => {
+            setLiveConsumptionData((current) => setSseWindow(current, e.data));
+        });
+        eventSource.addEventListener("message", (e: MessageEvent) => {
+            setLiveConsumptionData((current) => fromSseEvent(current, e.data));
+        });
+        eventSource.addEventListener("sample", (e: MessageEvent) => {
+            setLiveConsumptionData((current) => addSseSample(current, e.data));
+        });
+        return () => eventSource.close();
+    }, []);
 
     const roomsToRender = (rooms: EnrichedRoomData[]) => {
         if (hideUnscheduledRooms) {
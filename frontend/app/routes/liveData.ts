@@ -3,6 +3,8 @@ import type {LiveConsumption} from "~/routes/types";
 export interface LiveConsumptionData {
     liveConsumption: LiveConsumption[]
     liveConsumptionStats: LiveConsumptionStats
+    // Sent by the server when the stream starts, samples older than this are dropped
+    windowMinutes: number | null
 }
 
 export type LiveConsumptionChange = 'UP' | 'NONE' | 'DOWN'
@@ -24,16 +26,34 @@ export const getLiveConsumptionStats = (data: LiveConsumption[]): LiveConsumptio
     }
 };
 
-export const fromLiveConsumption = (data: LiveConsumption[]): LiveConsumptionData => {
+export const fromLiveConsumption = (data: LiveConsumption[], windowMinutes: number | null = null): LiveConsumptionData => {
     return {
         liveConsumption: [...data].reverse(),
         liveConsumptionStats: getLiveConsumptionStats([...data]),
+        windowMinutes,
     };
 }
-export const fromSseEvent = (sseEvent: string) => {
+
+// The stream starts with a "window" event, then the whole window, then a "sample" event for each new sample
+export const setSseWindow = (current: LiveConsumptionData, sseEvent: string): LiveConsumptionData => {
+    const window: { window_minutes: number } = JSON.parse(sseEvent);
+    return { ...current, windowMinutes: window.window_minutes };
+}
+
+export const fromSseEvent = (current: LiveConsumptionData, sseEvent: string) => {
     const data: LiveConsumption[] = JSON.parse(sseEvent);
+    return fromLiveConsumption(data, current.windowMinutes);
+}
+
+export const addSseSample = (current: LiveConsumptionData, sseEvent: string): LiveConsumptionData => {
+    const sample: LiveConsumption = JSON.parse(sseEvent);
+    const windowMinutes = current.windowMinutes;
+    const from = new Date(sample.timestamp).getTime() - (windowMinutes ?? 0) * 60 * 1000;
+    const liveConsumption = [...current.liveConsumption, sample]
+        .filter((s) => windowMinutes === null || new Date(s.timestamp).getTime() > from);
     return {
-        liveConsumption: [...data].reverse(),
-        liveConsumptionStats: getLiveConsumptionStats([...data]),
+        liveConsumption,
+        liveConsumptionStats: getLiveConsumptionStats([...liveConsumption].reverse()),
+        windowMinutes,
     };
 }
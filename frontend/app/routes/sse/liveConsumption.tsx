@@ -9,6 +9,13 @@ export async function loader({ request }: LoaderFunctionArgs) {
     return eventStream(request.signal, function setup(send) {
         const url = BASE_URL + apiRoutes.prices.live_consumption_sse;
         const eventSource = new EventSource(url);
+        eventSource.addEventListener(
+            "window",
+            function (e: MessageEvent) {
+                send({ event: "window", data: e.data });
+            },
+            false
+        );
         eventSource.addEventListener(
             "message",
             function (e: MessageEvent) {
@@ -16,6 +23,13 @@ export async function loader({ request }: LoaderFunctionArgs) {
             },
             false
         );
+        eventSource.addEventListener(
+            "sample",
+            function (e: MessageEvent) {
+                send({ event: "sample", data: e.data });
+            },
+            false
+        );
 
         return function clear() {
             eventSource.close();
@@ -4,7 +4,7 @@ import type {Room} from "~/routes/rooms/types";
 export interface PriceInfo {
     amount: number,
     currency: string,
-    ext_price_level: PriceLevel
+    ext_price_level: PriceLevel | null
     price_level: PriceLevel | null
     starts_at: string;
 }
@@ -29,7 +29,7 @@ export const formatCurrency = (num: number, currency: string) => {
 
 
 export const formatPriceInfo = (priceInfo: PriceInfo) => {
-    return `${priceInfo.amount.toFixed(2)} ${priceInfo.currency} - ${priceInfo.price_level ? displayPriceLevel(priceInfo.price_level) : `${displayPriceLevel(priceInfo.ext_price_level)} - ext.`}`;
+    return `${priceInfo.amount.toFixed(2)} ${priceInfo.currency} - ${priceInfo.price_level ? displayPriceLevel(priceInfo.price_level) : `${displayPriceLevel(priceInfo.ext_price_level ?? PriceLevel.Normal)} - ext.`}`;
 };
 
 export const formatNumber = (num: number, maximumFractionDigits?: number, minimumFractionDigits?: number) => {
@@ -0,0 +1,31 @@
+-- Add migration script here
+BEGIN;
+
+CREATE TABLE schedule_profiles
+(
+    id   UUID NOT NULL,
+    PRIMARY KEY (id),
+    name TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO schedule_profiles (id, name)
+VALUES (gen_random_uuid(), 'Normal');
+
+ALTER TABLE schedules
+ADD COLUMN profile_id UUID REFERENCES schedule_profiles (id);
+UPDATE schedules SET profile_id = (SELECT id FROM schedule_profiles WHERE name = 'Normal');
+ALTER TABLE schedules ALTER COLUMN profile_id SET NOT NULL;
+
+CREATE TABLE active_schedule_profile
+(
+    id                  INT GENERATED ALWAYS AS (1) STORED UNIQUE,
+    profile_id          UUID REFERENCES schedule_profiles (id) NOT NULL,
+    starts_at           TIMESTAMP,
+    ends_at             TIMESTAMP,
+    fallback_profile_id UUID REFERENCES schedule_profiles (id)
+);
+
+INSERT INTO active_schedule_profile (profile_id)
+SELECT id FROM schedule_profiles WHERE name = 'Normal';
+
+COMMIT;
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE recurring_temp_actions
+(
+    id            UUID   NOT NULL,
+    PRIMARY KEY (id),
+    room_ids      UUID[] NOT NULL,
+    action        TEXT   NOT NULL,
+    temp          DECIMAL,
+    days          TEXT[] NOT NULL,
+    from_time     TIME   NOT NULL,
+    to_time       TIME   NOT NULL,
+    ends_on       DATE,
+    skipped_dates DATE[] NOT NULL DEFAULT '{}'
+);
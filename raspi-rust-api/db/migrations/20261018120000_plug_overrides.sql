@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE plug_overrides
+(
+    id         UUID      NOT NULL,
+    PRIMARY KEY (id),
+    plug_ids   UUID[]    NOT NULL,
+    action     TEXT      NOT NULL,
+    starts_at  TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE temp_actions
+    ADD COLUMN priority   INT       NOT NULL DEFAULT 0,
+    ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+
+ALTER TABLE recurring_temp_actions
+    ADD COLUMN priority   INT       NOT NULL DEFAULT 0,
+    ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
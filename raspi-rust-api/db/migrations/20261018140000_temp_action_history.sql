@@ -0,0 +1,22 @@
+-- Add migration script here
+ALTER TABLE temp_actions
+    ADD COLUMN created_by TEXT NOT NULL DEFAULT 'API';
+
+CREATE TABLE temp_action_history
+(
+    id             UUID      NOT NULL DEFAULT gen_random_uuid(),
+    PRIMARY KEY (id),
+    temp_action_id UUID      NOT NULL,
+    room_ids       UUID[]    NOT NULL,
+    action         TEXT      NOT NULL,
+    temp           DECIMAL,
+    starts_at      TIMESTAMP,
+    expires_at     TIMESTAMP NOT NULL,
+    priority       INT       NOT NULL,
+    created_at     TIMESTAMP NOT NULL,
+    created_by     TEXT      NOT NULL,
+    ended_at       TIMESTAMP NOT NULL,
+    end_reason     TEXT      NOT NULL
+);
+
+CREATE INDEX temp_action_history_ended_at_idx ON temp_action_history (ended_at);
@@ -0,0 +1,23 @@
+-- Add migration script here
+CREATE TABLE price_settings
+(
+    id              int GENERATED ALWAYS AS (1) STORED UNIQUE,
+    electricity_tax DECIMAL NOT NULL,
+    vat_percent     DECIMAL NOT NULL,
+    use_total_price BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE grid_tariffs
+(
+    id        UUID    NOT NULL,
+    PRIMARY KEY (id),
+    name      TEXT    NOT NULL,
+    amount    DECIMAL NOT NULL,
+    months    INT[]   NOT NULL DEFAULT '{}',
+    days      TEXT[]  NOT NULL DEFAULT '{}',
+    from_time TIME    NOT NULL,
+    to_time   TIME    NOT NULL
+);
+
+ALTER TABLE prices
+    ADD COLUMN total DECIMAL;
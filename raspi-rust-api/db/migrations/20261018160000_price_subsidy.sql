@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE price_settings
+    ADD COLUMN subsidy_threshold DECIMAL,
+    ADD COLUMN subsidy_percent   DECIMAL NOT NULL DEFAULT 90;
+
+ALTER TABLE prices
+    ADD COLUMN subsidy DECIMAL;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE prices
+    ADD COLUMN duration_minutes INT NOT NULL DEFAULT 60;
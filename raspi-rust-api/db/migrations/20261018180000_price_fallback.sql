@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE price_settings
+    ADD COLUMN fallback_price_level TEXT;
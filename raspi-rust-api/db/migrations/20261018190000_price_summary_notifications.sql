@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE notification_settings
+    ADD COLUMN price_summary_time        TIME,
+    ADD COLUMN price_summary_block_hours INT     NOT NULL DEFAULT 3,
+    ADD COLUMN price_alert_max           DECIMAL,
+    ADD COLUMN price_alert_min           DECIMAL NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE rooms ADD COLUMN max_temp DECIMAL;
+ALTER TABLE plugs ADD COLUMN opportunistic BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE price_settings ADD COLUMN negative_price_mode BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE price_settings ADD COLUMN negative_price_threshold DECIMAL NOT NULL DEFAULT 0;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE live_consumption (
+    timestamp TIMESTAMP PRIMARY KEY,
+    power BIGINT NOT NULL
+);
+
+CREATE TABLE live_consumption_aggregates (
+    resolution TEXT NOT NULL,
+    starts_at TIMESTAMP NOT NULL,
+    min_power BIGINT NOT NULL,
+    avg_power DECIMAL NOT NULL,
+    max_power BIGINT NOT NULL,
+    samples BIGINT NOT NULL,
+    PRIMARY KEY (resolution, starts_at)
+);
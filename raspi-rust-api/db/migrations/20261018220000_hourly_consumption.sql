@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE hourly_consumption (
+    starts_at TIMESTAMP PRIMARY KEY,
+    ends_at TIMESTAMP NOT NULL,
+    kwh DECIMAL NOT NULL,
+    cost DECIMAL NOT NULL
+);
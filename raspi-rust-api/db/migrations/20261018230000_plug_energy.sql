@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE plug_energy (
+    plug_id UUID NOT NULL REFERENCES plugs(id) ON DELETE CASCADE,
+    time TIMESTAMP NOT NULL,
+    -- Energy counter of the plug in watt-minutes, reset when the plug restarts
+    counter BIGINT NOT NULL,
+    -- Energy since the previous reading
+    energy_wh DECIMAL NOT NULL,
+    power DECIMAL NOT NULL,
+    PRIMARY KEY (plug_id, time)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE plugs ADD COLUMN rated_watts INT;
+
+ALTER TABLE plug_energy ADD COLUMN since TIMESTAMP;
+ALTER TABLE plug_energy ADD COLUMN is_on BOOLEAN;
+ALTER TABLE plug_energy ADD COLUMN estimated BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE plug_energy ALTER COLUMN counter DROP NOT NULL;
+ALTER TABLE plug_energy ALTER COLUMN power DROP NOT NULL;
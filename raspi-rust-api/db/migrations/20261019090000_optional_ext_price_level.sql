@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE prices ALTER COLUMN ext_price_level DROP NOT NULL;
@@ -14,18 +14,22 @@ use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::clients::price_provider::PriceProvider;
 use crate::clients::shelly_client::ShellyClient;
-use crate::clients::tibber_client::TibberClient;
 use crate::domain::{ActionType, WorkMessage};
 use crate::routes;
 use crate::service::consumption_cache::ConsumptionCache;
+use crate::service::live_events::LiveEvents;
+use crate::service::price_fetching::PriceFetchStatus;
 
 // This function initializes all the services that our application provides
 pub async fn start(
     sender: Sender<WorkMessage>,
-    tibber_client: Arc<TibberClient>,
+    price_provider: Arc<dyn PriceProvider>,
     shelly_client: Arc<ShellyClient>,
     consumption_cache: Arc<RwLock<ConsumptionCache>>,
+    fetch_status: Arc<RwLock<PriceFetchStatus>>,
+    live_events: LiveEvents,
     pool: Arc<PgPool>,
 ) -> Router {
     Router::new()
@@ -33,26 +37,48 @@ pub async fn start(
         .route("/trigger_refresh", get(refresh))
         .route("/trigger_button/:button_id/:action", get(trigger_button))
         .nest("/buttons", routes::buttons::buttons_router(pool.clone()))
+        .nest(
+            "/events",
+            routes::events::events_router(live_events.clone()),
+        )
         .nest(
             "/notification_settings",
             routes::notification_settings::notification_settings_router(pool.clone()),
         )
+        .nest(
+            "/plug_overrides",
+            routes::plug_overrides::plug_overrides_router(pool.clone()),
+        )
         .nest(
             "/plugs",
             routes::plugs::plugs_router(pool.clone(), shelly_client.clone()),
         )
+        .nest(
+            "/price_settings",
+            routes::price_settings::price_settings_router(pool.clone()),
+        )
         .nest(
             "/prices",
-            routes::prices::prices_router(pool.clone(), tibber_client.clone(), consumption_cache),
+            routes::prices::prices_router(
+                pool.clone(),
+                price_provider.clone(),
+                consumption_cache,
+                fetch_status,
+            ),
         )
+        .nest("/reports", routes::reports::reports_router(pool.clone()))
         .nest("/rooms", routes::rooms::room_routes(pool.clone()))
+        .nest(
+            "/schedule_profiles",
+            routes::schedule_profiles::schedule_profiles_router(pool.clone()),
+        )
         .nest(
             "/schedules",
-            routes::schedules::schedules_router(pool.clone(), tibber_client.clone()),
+            routes::schedules::schedules_router(pool.clone(), price_provider),
         )
         .nest(
             "/temp_actions",
-            routes::temp_actions::temp_actions_router(pool.clone()),
+            routes::temp_actions::temp_actions_router(pool.clone(), live_events),
         )
         .nest(
             "/temp_sensors",
@@ -1,5 +1,7 @@
+pub mod entsoe_client;
 pub mod mqtt;
 pub mod ntfy;
+pub mod price_provider;
 pub mod shelly_client;
 pub mod tibber_client;
 pub mod tibber_subscriber;
@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::ops::Add;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use reqwest::Client;
+use thiserror::Error;
+
+use crate::clients::price_provider::{PriceProvider, PriceProviderError};
+use crate::domain::{DailyPrice, PriceInfo};
+use crate::{now, to_local, to_utc};
+
+pub const ENTSOE_BASE_URL: &str = "https://web-api.tp.entsoe.eu/api";
+
+const DAY_AHEAD_PRICES_DOCUMENT: &str = "A44";
+const TIME_FORMAT: &str = "%Y%m%d%H%M";
+const INTERVAL_FORMAT: &str = "%Y-%m-%dT%H:%MZ";
+const DAILY_PRICE_DAYS: i64 = 100;
+
+#[derive(Error, Debug)]
+pub enum EntsoeClientError {
+    #[error("ENTSOE_API_TOKEN is not set")]
+    MissingApiToken,
+    #[error("Unknown bidding zone: {0}")]
+    UnknownBiddingZone(String),
+    #[error("Reqwest client failure {0}")]
+    ReqwestClientFailure(#[from] reqwest::Error),
+    #[error("Request failed with status {0}")]
+    RequestFailure(reqwest::StatusCode),
+    #[error("Failed to parse XML: {0}")]
+    XmlError(#[from] roxmltree::Error),
+    #[error("Invalid document: {0}")]
+    InvalidDocument(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayAheadPrice {
+    pub starts_at: NaiveDateTime,
+    pub duration_minutes: i32,
+    pub amount: f64,
+}
+
+// Day-ahead prices from the ENTSO-E transparency platform, which publishes the Nord Pool results
+#[derive(Clone)]
+pub struct EntsoeClient {
+    api_token: String,
+    base_url: String,
+    bidding_zone: String,
+    domain: String,
+    currency: String,
+    // Prices are published in EUR/MWh
+    exchange_rate: f64,
+    client: Client,
+}
+
+impl EntsoeClient {
+    pub fn new(
+        api_token: String,
+        base_url: String,
+        bidding_zone: &str,
+        currency: Option<String>,
+        exchange_rate: Option<f64>,
+    ) -> Result<Self, EntsoeClientError> {
+        let domain = bidding_zone_domain(bidding_zone).ok_or(
+            EntsoeClientError::UnknownBiddingZone(bidding_zone.to_string()),
+        )?;
+        Ok(Self {
+            api_token,
+            base_url,
+            bidding_zone: bidding_zone.to_string(),
+            domain: domain.to_string(),
+            currency: currency.unwrap_or("EUR".to_string()),
+            exchange_rate: exchange_rate.unwrap_or(1.0),
+            client: Client::builder()
+                .timeout(Duration::from_secs(20))
+                .build()
+                .expect("Failed to build client"),
+        })
+    }
+
+    pub fn bidding_zone(&self) -> &str {
+        &self.bidding_zone
+    }
+
+    // Prices per kWh in the configured currency for the local period, with local start times
+    pub async fn get_day_ahead_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<DayAheadPrice>, EntsoeClientError> {
+        let response = self
+            .client
+            .get(&self.base_url)
+            .query(&[
+                ("securityToken", self.api_token.as_str()),
+                ("documentType", DAY_AHEAD_PRICES_DOCUMENT),
+                ("in_Domain", self.domain.as_str()),
+                ("out_Domain", self.domain.as_str()),
+                ("periodStart", &to_utc(from).format(TIME_FORMAT).to_string()),
+                ("periodEnd", &to_utc(to).format(TIME_FORMAT).to_string()),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        if is_acknowledgement(&body) {
+            return Ok(vec![]);
+        }
+        if !status.is_success() {
+            return Err(EntsoeClientError::RequestFailure(status));
+        }
+
+        Ok(parse_day_ahead_prices(&body)?
+            .into_iter()
+            .map(|price| DayAheadPrice {
+                starts_at: to_local(&price.starts_at),
+                duration_minutes: price.duration_minutes,
+                amount: price.amount / 1000.0 * self.exchange_rate,
+            })
+            .collect())
+    }
+
+    // ENTSO-E has no price levels, so they are left entirely to the price level strategy
+    fn to_price_infos(&self, prices: Vec<DayAheadPrice>) -> Vec<PriceInfo> {
+        prices
+            .into_iter()
+            .map(|price| PriceInfo {
+                amount: price.amount,
+                total: None,
+                subsidy: None,
+                currency: self.currency.clone(),
+                ext_price_level: None,
+                price_level: None,
+                starts_at: price.starts_at,
+                duration_minutes: price.duration_minutes,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PriceProvider for EntsoeClient {
+    fn name(&self) -> &str {
+        "ENTSO-E"
+    }
+
+    async fn get_current_price(&self) -> Result<PriceInfo, PriceProviderError> {
+        let time = now();
+        self.get_prices()
+            .await?
+            .into_iter()
+            .find(|price| price.contains(&time))
+            .ok_or(PriceProviderError::NoPrices(self.name().to_string()))
+    }
+
+    async fn get_prices(&self) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        let today = now().date().and_hms(0, 0, 0);
+        let prices = self
+            .get_day_ahead_prices(&today, &today.add(chrono::Duration::days(2)))
+            .await?;
+        Ok(self.to_price_infos(prices))
+    }
+
+    async fn get_daily_prices(&self) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        let today = now().date().and_hms(0, 0, 0);
+        let prices = self
+            .get_day_ahead_prices(
+                &today.add(chrono::Duration::days(-DAILY_PRICE_DAYS)),
+                &today,
+            )
+            .await?;
+        Ok(daily_averages(&prices)
+            .into_iter()
+            .map(|(date, total)| DailyPrice {
+                total,
+                starts_at: date.and_hms(0, 0, 0),
+            })
+            .collect())
+    }
+
+    async fn get_historical_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        let prices = self
+            .get_day_ahead_prices(
+                &from.date().and_hms(0, 0, 0),
+                &(to.date() + chrono::Duration::days(1)).and_hms(0, 0, 0),
+            )
+            .await?;
+        Ok(self
+            .to_price_infos(prices)
+            .into_iter()
+            .filter(|price| *from <= price.starts_at && price.starts_at < *to)
+            .collect())
+    }
+}
+
+// EIC codes from https://transparency.entsoe.eu/content/static_content/Static%20content/web%20api/Guide.html#_areas
+fn bidding_zone_domain(bidding_zone: &str) -> Option<&'static str> {
+    match bidding_zone.to_uppercase().as_str() {
+        "NO1" => Some("10YNO-1--------2"),
+        "NO2" => Some("10YNO-2--------T"),
+        "NO3" => Some("10YNO-3--------J"),
+        "NO4" => Some("10YNO-4--------9"),
+        "NO5" => Some("10Y1001A1001A48H"),
+        "SE1" => Some("10Y1001A1001A44P"),
+        "SE2" => Some("10Y1001A1001A45N"),
+        "SE3" => Some("10Y1001A1001A46L"),
+        "SE4" => Some("10Y1001A1001A47J"),
+        "DK1" => Some("10YDK-1--------W"),
+        "DK2" => Some("10YDK-2--------M"),
+        "FI" => Some("10YFI-1--------U"),
+        _ => None,
+    }
+}
+
+// Returned instead of a price document when there is no data for the period
+fn is_acknowledgement(body: &str) -> bool {
+    roxmltree::Document::parse(body)
+        .map(|doc| doc.root_element().tag_name().name() == "Acknowledgement_MarketDocument")
+        .unwrap_or(false)
+}
+
+// Prices in EUR/MWh with UTC start times, in the resolution of the document. Positions left out
+// of a period repeat the previous price
+pub fn parse_day_ahead_prices(xml: &str) -> Result<Vec<DayAheadPrice>, EntsoeClientError> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let mut prices: BTreeMap<NaiveDateTime, DayAheadPrice> = BTreeMap::new();
+
+    for period in doc.descendants().filter(|node| node.has_tag_name("Period")) {
+        let start = parse_time(child_text(&period, &["timeInterval", "start"])?)?;
+        let end = parse_time(child_text(&period, &["timeInterval", "end"])?)?;
+        let resolution = parse_resolution(child_text(&period, &["resolution"])?)?;
+
+        let mut points: BTreeMap<i64, f64> = BTreeMap::new();
+        for point in period.children().filter(|node| node.has_tag_name("Point")) {
+            let position = child_text(&point, &["position"])?
+                .parse::<i64>()
+                .map_err(|e| EntsoeClientError::InvalidDocument(e.to_string()))?;
+            let amount = child_text(&point, &["price.amount"])?
+                .parse::<f64>()
+                .map_err(|e| EntsoeClientError::InvalidDocument(e.to_string()))?;
+            points.insert(position, amount);
+        }
+
+        let intervals = (end - start).num_minutes() / resolution.num_minutes();
+        let mut last_amount = None;
+        for position in 1..=intervals {
+            last_amount = points.get(&position).copied().or(last_amount);
+            if let Some(amount) = last_amount {
+                let starts_at = start.add(resolution * (position - 1) as i32);
+                prices.insert(
+                    starts_at,
+                    DayAheadPrice {
+                        starts_at,
+                        duration_minutes: resolution.num_minutes() as i32,
+                        amount,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(prices.into_values().collect())
+}
+
+// Weighted by the length of each interval
+fn daily_averages(prices: &[DayAheadPrice]) -> BTreeMap<NaiveDate, f64> {
+    prices
+        .iter()
+        .group_by(|price| price.starts_at.date())
+        .into_iter()
+        .map(|(date, group)| {
+            let (sum, minutes) = group.fold((0.0, 0), |(sum, minutes), price| {
+                (
+                    sum + price.amount * price.duration_minutes as f64,
+                    minutes + price.duration_minutes,
+                )
+            });
+            (date, sum / minutes as f64)
+        })
+        .collect()
+}
+
+fn child_text<'a>(
+    node: &roxmltree::Node<'a, '_>,
+    path: &[&str],
+) -> Result<&'a str, EntsoeClientError> {
+    let mut current = *node;
+    for name in path {
+        current = current
+            .children()
+            .find(|child| child.has_tag_name(*name))
+            .ok_or(EntsoeClientError::InvalidDocument(format!(
+                "Missing element {}",
+                name
+            )))?;
+    }
+    current
+        .text()
+        .map(|text| text.trim())
+        .ok_or(EntsoeClientError::InvalidDocument(format!(
+            "Empty element {}",
+            path.join(".")
+        )))
+}
+
+fn parse_time(value: &str) -> Result<NaiveDateTime, EntsoeClientError> {
+    NaiveDateTime::parse_from_str(value, INTERVAL_FORMAT)
+        .map_err(|e| EntsoeClientError::InvalidDocument(format!("{}: {}", value, e)))
+}
+
+fn parse_resolution(value: &str) -> Result<chrono::Duration, EntsoeClientError> {
+    match value {
+        "PT15M" => Ok(chrono::Duration::minutes(15)),
+        "PT30M" => Ok(chrono::Duration::minutes(30)),
+        "PT60M" => Ok(chrono::Duration::minutes(60)),
+        _ => Err(EntsoeClientError::InvalidDocument(format!(
+            "Unsupported resolution {}",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use itertools::Itertools;
+
+    use super::{is_acknowledgement, parse_day_ahead_prices, DayAheadPrice};
+
+    fn document(resolution: &str, end: &str, points: &[(i64, f64)]) -> String {
+        let points = points
+            .iter()
+            .map(|(position, amount)| {
+                format!(
+                    "<Point><position>{}</position><price.amount>{}</price.amount></Point>",
+                    position, amount
+                )
+            })
+            .join("");
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Publication_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-3:publicationdocument:7:3">
+  <TimeSeries>
+    <Period>
+      <timeInterval>
+        <start>2023-01-01T23:00Z</start>
+        <end>{}</end>
+      </timeInterval>
+      <resolution>{}</resolution>
+      {}
+    </Period>
+  </TimeSeries>
+</Publication_MarketDocument>"#,
+            end, resolution, points
+        )
+    }
+
+    fn price(minutes: i64, duration_minutes: i32, amount: f64) -> DayAheadPrice {
+        DayAheadPrice {
+            starts_at: NaiveDate::from_ymd(2023, 1, 1).and_hms(23, 0, 0)
+                + chrono::Duration::minutes(minutes),
+            duration_minutes,
+            amount,
+        }
+    }
+
+    #[test]
+    fn repeats_previous_price_for_missing_positions() {
+        let xml = document("PT60M", "2023-01-02T03:00Z", &[(1, 100.0), (3, 50.0)]);
+        let prices = parse_day_ahead_prices(&xml).expect("Failed to parse");
+        assert_eq!(
+            prices,
+            vec![
+                price(0, 60, 100.0),
+                price(60, 60, 100.0),
+                price(120, 60, 50.0),
+                price(180, 60, 50.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_quarter_hour_resolution() {
+        let xml = document("PT15M", "2023-01-01T23:45Z", &[(1, 10.0), (3, 30.0)]);
+        let prices = parse_day_ahead_prices(&xml).expect("Failed to parse");
+        assert_eq!(
+            prices,
+            vec![price(0, 15, 10.0), price(15, 15, 10.0), price(30, 15, 30.0)]
+        );
+    }
+
+    #[test]
+    fn detects_acknowledgement() {
+        let xml = r#"<Acknowledgement_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-1:acknowledgementdocument:7:0">
+  <Reason><code>999</code><text>No matching data found</text></Reason>
+</Acknowledgement_MarketDocument>"#;
+        assert!(is_acknowledgement(xml));
+        assert!(!is_acknowledgement(&document(
+            "PT60M",
+            "2023-01-02T00:00Z",
+            &[]
+        )));
+    }
+}
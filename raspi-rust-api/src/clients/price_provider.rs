@@ -0,0 +1,372 @@
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use log::{error, warn};
+use thiserror::Error;
+
+use crate::clients::entsoe_client::{EntsoeClient, EntsoeClientError, ENTSOE_BASE_URL};
+use crate::clients::tibber_client::{TibberClient, TibberClientError, TIBBER_BASE_URL};
+use crate::configuration::PriceProviderSettings;
+use crate::domain::{DailyPrice, PriceInfo};
+use crate::env_var;
+
+#[derive(Error, Debug)]
+pub enum PriceProviderError {
+    #[error("Tibber client error: {0}")]
+    TibberClientError(#[from] TibberClientError),
+    #[error("ENTSO-E client error: {0}")]
+    EntsoeClientError(#[from] EntsoeClientError),
+    #[error("No prices available from {0}")]
+    NoPrices(String),
+    #[error("No price providers configured")]
+    NoProviders,
+}
+
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn get_current_price(&self) -> Result<PriceInfo, PriceProviderError>;
+
+    // Hourly prices for today, and for tomorrow once they are published
+    async fn get_prices(&self) -> Result<Vec<PriceInfo>, PriceProviderError>;
+
+    // Daily prices used as the reference when calculating price levels
+    async fn get_daily_prices(&self) -> Result<Vec<DailyPrice>, PriceProviderError>;
+
+    // Prices starting in a past period, used to fill gaps in the stored prices
+    async fn get_historical_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<PriceInfo>, PriceProviderError>;
+
+    // The prices together with the daily prices they should be compared against
+    async fn get_prices_with_daily(
+        &self,
+    ) -> Result<(Vec<PriceInfo>, Vec<DailyPrice>), PriceProviderError> {
+        Ok((self.get_prices().await?, self.get_daily_prices().await?))
+    }
+
+    async fn get_historical_prices_with_daily(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<(Vec<PriceInfo>, Vec<DailyPrice>), PriceProviderError> {
+        Ok((
+            self.get_historical_prices(from, to).await?,
+            self.get_daily_prices().await?,
+        ))
+    }
+}
+
+// Asks each provider in turn, and returns the first successful answer
+pub struct FallbackPriceProvider {
+    providers: Vec<Arc<dyn PriceProvider>>,
+}
+
+impl FallbackPriceProvider {
+    pub fn new(providers: Vec<Arc<dyn PriceProvider>>) -> Self {
+        Self { providers }
+    }
+
+    async fn first_success<'a, T, F, Fut>(
+        &'a self,
+        description: &str,
+        fetch: F,
+    ) -> Result<T, PriceProviderError>
+    where
+        F: Fn(&'a dyn PriceProvider) -> Fut,
+        Fut: Future<Output = Result<T, PriceProviderError>>,
+    {
+        let mut last_error = PriceProviderError::NoProviders;
+        for provider in &self.providers {
+            match fetch(provider.as_ref()).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    warn!(
+                        "Failed to get {} from {}: {}",
+                        description,
+                        provider.name(),
+                        e
+                    );
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+fn non_empty(
+    provider: &dyn PriceProvider,
+    prices: Vec<PriceInfo>,
+) -> Result<Vec<PriceInfo>, PriceProviderError> {
+    match prices.is_empty() {
+        true => Err(PriceProviderError::NoPrices(provider.name().to_string())),
+        false => Ok(prices),
+    }
+}
+
+#[async_trait]
+impl PriceProvider for FallbackPriceProvider {
+    fn name(&self) -> &str {
+        "Fallback"
+    }
+
+    async fn get_current_price(&self) -> Result<PriceInfo, PriceProviderError> {
+        self.first_success("current price", |provider| provider.get_current_price())
+            .await
+    }
+
+    async fn get_prices(&self) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        self.first_success("prices", |provider| async move {
+            non_empty(provider, provider.get_prices().await?)
+        })
+        .await
+    }
+
+    async fn get_daily_prices(&self) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        self.first_success("daily prices", |provider| provider.get_daily_prices())
+            .await
+    }
+
+    async fn get_historical_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        self.first_success("historical prices", |provider| async move {
+            non_empty(provider, provider.get_historical_prices(from, to).await?)
+        })
+        .await
+    }
+
+    // Both come from the same provider, so the price levels use a matching reference
+    async fn get_prices_with_daily(
+        &self,
+    ) -> Result<(Vec<PriceInfo>, Vec<DailyPrice>), PriceProviderError> {
+        self.first_success("prices", |provider| async move {
+            let prices = non_empty(provider, provider.get_prices().await?)?;
+            Ok((prices, provider.get_daily_prices().await?))
+        })
+        .await
+    }
+
+    async fn get_historical_prices_with_daily(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<(Vec<PriceInfo>, Vec<DailyPrice>), PriceProviderError> {
+        self.first_success("historical prices", |provider| async move {
+            let prices = non_empty(provider, provider.get_historical_prices(from, to).await?)?;
+            Ok((prices, provider.get_daily_prices().await?))
+        })
+        .await
+    }
+}
+
+// Providers that can't be created are left out, so that the others can still be used
+pub fn price_provider_from_settings(settings: &[PriceProviderSettings]) -> Arc<dyn PriceProvider> {
+    let mut providers: Vec<Arc<dyn PriceProvider>> = settings
+        .iter()
+        .filter_map(|provider| match provider {
+            PriceProviderSettings::Tibber { base_url, home_id } => {
+                Some(Arc::new(tibber_client(base_url, home_id)) as Arc<dyn PriceProvider>)
+            }
+            PriceProviderSettings::Entsoe {
+                base_url,
+                bidding_zone,
+                currency,
+                exchange_rate,
+            } => match entsoe_client(base_url, bidding_zone, currency, exchange_rate) {
+                Ok(client) => Some(Arc::new(client) as Arc<dyn PriceProvider>),
+                Err(e) => {
+                    error!("Skipping ENTSO-E price provider: {}", e);
+                    None
+                }
+            },
+        })
+        .collect();
+
+    match providers.len() {
+        1 => providers.remove(0),
+        _ => Arc::new(FallbackPriceProvider::new(providers)),
+    }
+}
+
+fn entsoe_client(
+    base_url: &Option<String>,
+    bidding_zone: &str,
+    currency: &Option<String>,
+    exchange_rate: &Option<f64>,
+) -> Result<EntsoeClient, EntsoeClientError> {
+    EntsoeClient::new(
+        env::var("ENTSOE_API_TOKEN").map_err(|_| EntsoeClientError::MissingApiToken)?,
+        base_url.clone().unwrap_or(ENTSOE_BASE_URL.to_string()),
+        bidding_zone,
+        currency.clone(),
+        *exchange_rate,
+    )
+}
+
+// The consumption is read from the home of the first Tibber price provider
+pub fn tibber_client_from_settings(settings: &[PriceProviderSettings]) -> TibberClient {
+    settings
+        .iter()
+        .find_map(|provider| match provider {
+            PriceProviderSettings::Tibber { base_url, home_id } => {
+                Some(tibber_client(base_url, home_id))
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| tibber_client(&None, &None))
+}
+
+fn tibber_client(base_url: &Option<String>, home_id: &Option<String>) -> TibberClient {
+    TibberClient::new_with_base_url(
+        env_var("TIBBER_API_TOKEN"),
+        base_url.clone().unwrap_or(TIBBER_BASE_URL.to_string()),
+        home_id.clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use chrono::NaiveDateTime;
+
+    use crate::configuration::PriceProviderSettings;
+    use crate::domain::{DailyPrice, PriceInfo, PriceLevel};
+
+    use super::{
+        price_provider_from_settings, FallbackPriceProvider, PriceProvider, PriceProviderError,
+    };
+
+    struct FakeProvider {
+        name: String,
+        prices: Option<Vec<PriceInfo>>,
+    }
+
+    #[async_trait]
+    impl PriceProvider for FakeProvider {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn get_current_price(&self) -> Result<PriceInfo, PriceProviderError> {
+            self.prices
+                .as_ref()
+                .and_then(|prices| prices.first().cloned())
+                .ok_or(PriceProviderError::NoPrices(self.name.clone()))
+        }
+
+        async fn get_prices(&self) -> Result<Vec<PriceInfo>, PriceProviderError> {
+            self.prices
+                .clone()
+                .ok_or(PriceProviderError::NoPrices(self.name.clone()))
+        }
+
+        async fn get_daily_prices(&self) -> Result<Vec<DailyPrice>, PriceProviderError> {
+            self.prices
+                .as_ref()
+                .map(|prices| prices.iter().map(daily).collect())
+                .ok_or(PriceProviderError::NoPrices(self.name.clone()))
+        }
+
+        async fn get_historical_prices(
+            &self,
+            _from: &NaiveDateTime,
+            _to: &NaiveDateTime,
+        ) -> Result<Vec<PriceInfo>, PriceProviderError> {
+            self.get_prices().await
+        }
+    }
+
+    fn price(amount: f64) -> PriceInfo {
+        PriceInfo {
+            amount,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: NaiveDateTime::from_timestamp(1666291743, 0),
+            duration_minutes: 60,
+        }
+    }
+
+    fn daily(price: &PriceInfo) -> DailyPrice {
+        DailyPrice {
+            total: price.amount,
+            starts_at: price.starts_at,
+        }
+    }
+
+    fn provider(name: &str, prices: Option<Vec<PriceInfo>>) -> Arc<dyn PriceProvider> {
+        Arc::new(FakeProvider {
+            name: name.to_string(),
+            prices,
+        })
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_next_provider() {
+        let chain = FallbackPriceProvider::new(vec![
+            provider("failing", None),
+            provider("empty", Some(vec![])),
+            provider("working", Some(vec![price(1.0)])),
+            provider("unused", Some(vec![price(2.0)])),
+        ]);
+
+        assert_eq!(chain.get_current_price().await.unwrap(), price(1.0));
+        assert_eq!(chain.get_prices().await.unwrap(), vec![price(1.0)]);
+    }
+
+    #[tokio::test]
+    async fn takes_daily_prices_from_the_same_provider() {
+        let chain = FallbackPriceProvider::new(vec![
+            provider("empty", Some(vec![])),
+            provider("working", Some(vec![price(1.0)])),
+        ]);
+
+        assert_eq!(
+            chain.get_prices_with_daily().await.unwrap(),
+            (vec![price(1.0)], vec![daily(&price(1.0))])
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_when_all_providers_fail() {
+        let chain = FallbackPriceProvider::new(vec![provider("failing", None)]);
+        assert!(matches!(
+            chain.get_prices().await,
+            Err(PriceProviderError::NoPrices(_))
+        ));
+
+        let empty = FallbackPriceProvider::new(vec![]);
+        assert!(matches!(
+            empty.get_current_price().await,
+            Err(PriceProviderError::NoProviders)
+        ));
+    }
+
+    #[tokio::test]
+    async fn skips_providers_with_invalid_settings() {
+        let provider = price_provider_from_settings(&[PriceProviderSettings::Entsoe {
+            base_url: None,
+            bidding_zone: "XX1".to_string(),
+            currency: None,
+            exchange_rate: None,
+        }]);
+        assert!(matches!(
+            provider.get_prices().await,
+            Err(PriceProviderError::NoProviders)
+        ));
+    }
+}
@@ -1,16 +1,17 @@
 use std::time::Duration;
 
-use chrono::{DateTime, FixedOffset};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use log::warn;
 use reqwest::Client;
 use serde::Deserialize;
 use thiserror::Error;
-use tibber::{HomeId, PriceInfo as TPriceInfo, TibberSession, TimeResolution};
-use tokio::task::JoinError;
 
-use crate::domain::{Consumption, PriceInfo};
-use crate::env_var;
+use crate::clients::price_provider::{PriceProvider, PriceProviderError};
+use crate::domain::{Consumption, DailyPrice, PriceInfo, PriceLevel};
+use crate::now;
 
-const TIBBER_BASE_URL: &str = "https://api.tibber.com/v1-beta/gql";
+pub const TIBBER_BASE_URL: &str = "https://api.tibber.com/v1-beta/gql";
 
 #[derive(Error, Debug)]
 pub enum TibberClientError {
@@ -20,20 +21,27 @@ pub enum TibberClientError {
     RequestFailure,
     #[error("Reqwest client failure {0}")]
     ReqwestClientFailure(#[from] reqwest::Error),
-    #[error("Join error: {0}")]
-    JoinError(#[from] JoinError),
 }
 
 #[derive(Clone)]
 pub struct TibberClient {
     api_token: String,
+    base_url: String,
+    // Uses the first home of the user when not set
+    home_id: Option<String>,
     client: Client,
 }
 
 impl TibberClient {
     pub fn new(api_token: String) -> Self {
+        Self::new_with_base_url(api_token, TIBBER_BASE_URL.to_string(), None)
+    }
+
+    pub fn new_with_base_url(api_token: String, base_url: String, home_id: Option<String>) -> Self {
         Self {
             api_token,
+            base_url,
+            home_id,
             client: Client::builder()
                 .timeout(Duration::from_secs(20))
                 .build()
@@ -41,97 +49,155 @@ impl TibberClient {
         }
     }
 
-    async fn prepare_request(&self) -> Result<(TibberSession, HomeId), TibberClientError> {
-        let token = self.api_token.clone();
-        let res = tokio::task::spawn_blocking(|| {
-            let conn = TibberSession::new(token);
-            let user = match conn.get_user() {
-                Ok(user) => user,
-                Err(_) => return Err(TibberClientError::RequestFailure),
-            };
-
-            if user.homes.is_empty() {
-                return Err(TibberClientError::UserHasNoHome);
-            }
-            Ok(user.homes[0].clone())
-        })
-        .await?;
-        Ok((TibberSession::new(self.api_token.clone()), res?))
+    async fn query_home(&self, query: &str) -> Result<Home, TibberClientError> {
+        let homes = self
+            .client
+            .post(&self.base_url)
+            .bearer_auth(&self.api_token)
+            .json(&serde_json::json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TibberRootResponse>()
+            .await?
+            .data
+            .viewer
+            .homes;
+
+        match &self.home_id {
+            Some(id) => homes.into_iter().find(|home| home.id.as_ref() == Some(id)),
+            None => homes.into_iter().next(),
+        }
+        .ok_or(TibberClientError::UserHasNoHome)
     }
 
     pub async fn get_current_price(&self) -> Result<PriceInfo, TibberClientError> {
-        let (session, home_id) = self.prepare_request().await?;
-        tokio::task::spawn_blocking(move || match session.get_current_price(&home_id) {
-            Ok(price_info) => Ok(price_info.into()),
-            Err(_) => Err(TibberClientError::RequestFailure),
-        })
+        self.query_home(&format!(
+            "{{ viewer {{ homes {{ id currentSubscription {{ priceInfo {{ current {{ {} }} }} }} }} }} }}",
+            PRICE_FIELDS
+        ))
         .await?
+        .current_subscription
+        .price_info
+        .current
+        .map(|price| price.into())
+        .ok_or(TibberClientError::RequestFailure)
     }
 
-    pub async fn get_consumption(&self) -> Result<Vec<Consumption>, TibberClientError> {
-        let (session, home_id) = self.prepare_request().await?;
-        Ok(tokio::task::spawn_blocking(move || {
-            match session.get_consuption(&home_id, TimeResolution::Hourly, 24) {
-                Ok(consumption) => Ok(consumption),
-                Err(_) => Err(TibberClientError::RequestFailure),
-            }
-        })
-        .await??
-        .iter()
-        .map(Consumption::from)
-        .collect())
+    // Hourly consumption for the last hours, hours without a cost are left out
+    pub async fn get_consumption(&self, hours: u32) -> Result<Vec<Consumption>, TibberClientError> {
+        Ok(self
+            .query_home(&format!(
+                "{{ viewer {{ homes {{ id consumption(resolution: HOURLY, last: {}) {{ nodes {{ from to consumption cost }} }} }} }} }}",
+                hours
+            ))
+            .await?
+            .consumption
+            .map(|consumption| consumption.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|node| {
+                Some(Consumption {
+                    from: node.from.naive_local(),
+                    to: node.to.naive_local(),
+                    kwh: node.consumption,
+                    cost: node.cost?,
+                })
+            })
+            .collect())
     }
 
     pub async fn get_hourly_prices(&self) -> Result<Vec<PriceInfo>, TibberClientError> {
-        let (session, home_id) = self.prepare_request().await?;
-        let res = tokio::task::spawn_blocking(move || {
-            match (
-                session.get_prices_today(&home_id),
-                session.get_prices_tomorrow(&home_id),
-            ) {
-                (Ok(today), Ok(tomorrow)) => Ok(today
-                    .into_iter()
-                    .chain(tomorrow.into_iter())
-                    .collect::<Vec<TPriceInfo>>()),
-                _ => Err(TibberClientError::RequestFailure),
-            }
-        })
-        .await?;
-        match res {
-            Ok(v) => Ok(v.into_iter().map(|a| a.into()).collect()),
-            Err(e) => Err(e),
-        }
+        let price_info = self
+            .query_home(&format!(
+                "{{ viewer {{ homes {{ id currentSubscription {{ priceInfo {{ today {{ {0} }} tomorrow {{ {0} }} }} }} }} }} }}",
+                PRICE_FIELDS
+            ))
+            .await?
+            .current_subscription
+            .price_info;
+        Ok(price_info
+            .today
+            .into_iter()
+            .chain(price_info.tomorrow)
+            .map(|price| price.into())
+            .collect())
     }
 
-    pub async fn get_daily_prices(&self) -> Result<Vec<TibberDailyPrice>, TibberClientError> {
-        let body = r#"
-            {"query":"{\n  viewer {\n    homes {\n      currentSubscription{\n        priceInfo{\n          range(resolution: DAILY, last: 100) {\n            nodes {\n              total,\n              startsAt,\n            }\n          }\n        }\n      }\n    }\n  }\n}\n"}
-             "#.to_string();
+    // Tibber only has the hourly prices of the last hours
+    pub async fn get_historical_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<PriceInfo>, TibberClientError> {
+        let hours = (now() - *from).num_hours().max(1);
+        Ok(self
+            .query_home(&format!(
+                "{{ viewer {{ homes {{ id currentSubscription {{ priceInfo {{ hourly: range(resolution: HOURLY, last: {}) {{ nodes {{ {} }} }} }} }} }} }} }}",
+                hours, PRICE_FIELDS
+            ))
+            .await?
+            .current_subscription
+            .price_info
+            .hourly
+            .map(|range| range.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .map(PriceInfo::from)
+            .filter(|price| *from <= price.starts_at && price.starts_at < *to)
+            .collect())
+    }
 
+    pub async fn get_daily_prices(&self) -> Result<Vec<TibberDailyPrice>, TibberClientError> {
         Ok(self
-            .client
-            .post(TIBBER_BASE_URL)
-            .header(
-                "Authorization",
-                format!("Bearer {}", env_var("TIBBER_API_TOKEN")),
+            .query_home(
+                "{ viewer { homes { id currentSubscription { priceInfo { range(resolution: DAILY, last: 100) { nodes { total startsAt } } } } } } }",
             )
-            .header("content-type", "application/json")
-            .body(body.clone())
-            .send()
             .await?
-            .json::<TibberRootResponse>()
-            .await?
-            .data
-            .viewer
-            .homes[0]
             .current_subscription
             .price_info
             .range
-            .nodes
-            .clone())
+            .map(|range| range.nodes)
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl PriceProvider for TibberClient {
+    fn name(&self) -> &str {
+        "Tibber"
+    }
+
+    async fn get_current_price(&self) -> Result<PriceInfo, PriceProviderError> {
+        Ok(TibberClient::get_current_price(self).await?)
+    }
+
+    async fn get_prices(&self) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        Ok(self.get_hourly_prices().await?)
+    }
+
+    async fn get_daily_prices(&self) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        Ok(TibberClient::get_daily_prices(self)
+            .await?
+            .into_iter()
+            .map(|price| DailyPrice {
+                total: price.total,
+                starts_at: price.starts_at.naive_local(),
+            })
+            .collect())
+    }
+
+    async fn get_historical_prices(
+        &self,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Vec<PriceInfo>, PriceProviderError> {
+        Ok(TibberClient::get_historical_prices(self, from, to).await?)
     }
 }
 
+const PRICE_FIELDS: &str = "total currency level startsAt";
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct TibberDailyPrice {
     pub total: f64,
@@ -139,17 +205,69 @@ pub struct TibberDailyPrice {
     pub starts_at: DateTime<FixedOffset>,
 }
 
+#[derive(Deserialize)]
+struct TibberPrice {
+    pub total: f64,
+    pub currency: String,
+    pub level: String,
+    #[serde(rename = "startsAt")]
+    pub starts_at: DateTime<FixedOffset>,
+}
+
+impl From<TibberPrice> for PriceInfo {
+    fn from(value: TibberPrice) -> Self {
+        Self {
+            amount: value.total,
+            total: None,
+            subsidy: None,
+            currency: value.currency,
+            ext_price_level: Some(parse_price_level(&value.level)),
+            price_level: None,
+            starts_at: value.starts_at.naive_local(),
+            duration_minutes: 60,
+        }
+    }
+}
+
+fn parse_price_level(level: &str) -> PriceLevel {
+    match level {
+        "VERY_CHEAP" => PriceLevel::VeryCheap,
+        "CHEAP" => PriceLevel::Cheap,
+        "NORMAL" => PriceLevel::Normal,
+        "EXPENSIVE" => PriceLevel::Expensive,
+        "VERY_EXPENSIVE" => PriceLevel::VeryExpensive,
+        _ => {
+            warn!(
+                "Encountered unexpected Tibber price level: {}, setting to Normal",
+                level
+            );
+            PriceLevel::Normal
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Range {
     pub nodes: Vec<TibberDailyPrice>,
 }
 
 #[derive(Deserialize)]
+struct HourlyRange {
+    pub nodes: Vec<TibberPrice>,
+}
+
+#[derive(Deserialize, Default)]
 struct TibberPrices {
-    pub range: Range,
+    pub current: Option<TibberPrice>,
+    #[serde(default)]
+    pub today: Vec<TibberPrice>,
+    #[serde(default)]
+    pub tomorrow: Vec<TibberPrice>,
+    pub range: Option<Range>,
+    pub hourly: Option<HourlyRange>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 struct CurrentSubscription {
     #[serde(rename = "priceInfo")]
     pub price_info: TibberPrices,
@@ -157,8 +275,23 @@ struct CurrentSubscription {
 
 #[derive(Deserialize)]
 struct Home {
-    #[serde(rename = "currentSubscription")]
+    pub id: Option<String>,
+    #[serde(rename = "currentSubscription", default)]
     pub current_subscription: CurrentSubscription,
+    pub consumption: Option<TibberConsumptionConnection>,
+}
+
+#[derive(Deserialize)]
+struct TibberConsumption {
+    pub from: DateTime<FixedOffset>,
+    pub to: DateTime<FixedOffset>,
+    pub consumption: Option<f64>,
+    pub cost: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct TibberConsumptionConnection {
+    pub nodes: Vec<TibberConsumption>,
 }
 
 #[derive(Deserialize)]
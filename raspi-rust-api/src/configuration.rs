@@ -1,6 +1,7 @@
 use log::info;
 use serde_aux::field_attributes::deserialize_number_from_string;
 
+use crate::domain::RATIO_THRESHOLDS;
 use crate::observability::{get_app_environment, Environment};
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -11,6 +12,15 @@ pub struct Settings {
     pub application_port: u16,
     pub application_host: String,
     pub run_live_consumption_subscriber: bool,
+    // Tried in order, later providers are only used when the earlier ones fail
+    #[serde(default = "default_price_providers")]
+    pub price_providers: Vec<PriceProviderSettings>,
+    #[serde(default)]
+    pub price_level_strategy: PriceLevelStrategy,
+    #[serde(default)]
+    pub consumption_history: ConsumptionHistorySettings,
+    #[serde(default)]
+    pub consumption_cache: ConsumptionCacheSettings,
 }
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct DatabaseSettings {
@@ -30,6 +40,135 @@ pub struct MqttConfig {
     pub id: String,
 }
 
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriceProviderSettings {
+    Tibber {
+        base_url: Option<String>,
+        home_id: Option<String>,
+    },
+    Entsoe {
+        base_url: Option<String>,
+        // Like NO1
+        bidding_zone: String,
+        // Prices are converted from EUR with the exchange rate when set
+        currency: Option<String>,
+        exchange_rate: Option<f64>,
+    },
+}
+
+fn default_price_providers() -> Vec<PriceProviderSettings> {
+    vec![PriceProviderSettings::Tibber {
+        base_url: None,
+        home_id: None,
+    }]
+}
+
+// How the price level of each interval is set when prices are fetched
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriceLevelStrategy {
+    // Keeps the level from the price provider
+    #[serde(alias = "tibber")]
+    Provider,
+    // Ratio to the median of the daily prices, blended with the level from the price provider
+    MedianRatio {
+        #[serde(default = "default_ratio_thresholds")]
+        thresholds: [f64; 4],
+        #[serde(default = "default_provider_weight")]
+        provider_weight: f64,
+    },
+    // Rank of the price among the prices of the same day, from 0 to 1
+    Percentile {
+        #[serde(default = "default_percentile_thresholds")]
+        thresholds: [f64; 4],
+    },
+    // Ratio to the average of the daily prices of the last days
+    Rolling {
+        #[serde(default = "default_rolling_days")]
+        days: usize,
+        #[serde(default = "default_ratio_thresholds")]
+        thresholds: [f64; 4],
+    },
+}
+
+impl Default for PriceLevelStrategy {
+    fn default() -> Self {
+        PriceLevelStrategy::MedianRatio {
+            thresholds: default_ratio_thresholds(),
+            provider_weight: default_provider_weight(),
+        }
+    }
+}
+
+fn default_ratio_thresholds() -> [f64; 4] {
+    RATIO_THRESHOLDS
+}
+
+fn default_provider_weight() -> f64 {
+    1.0 / 3.0
+}
+
+fn default_percentile_thresholds() -> [f64; 4] {
+    [0.2, 0.4, 0.6, 0.8]
+}
+
+fn default_rolling_days() -> usize {
+    30
+}
+
+// How long live consumption is kept at each resolution
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct ConsumptionHistorySettings {
+    #[serde(default = "default_raw_retention_hours")]
+    pub raw_retention_hours: i64,
+    #[serde(default = "default_minute_retention_days")]
+    pub minute_retention_days: i64,
+    #[serde(default = "default_hour_retention_days")]
+    pub hour_retention_days: i64,
+}
+
+impl Default for ConsumptionHistorySettings {
+    fn default() -> Self {
+        ConsumptionHistorySettings {
+            raw_retention_hours: default_raw_retention_hours(),
+            minute_retention_days: default_minute_retention_days(),
+            hour_retention_days: default_hour_retention_days(),
+        }
+    }
+}
+
+fn default_raw_retention_hours() -> i64 {
+    48
+}
+
+fn default_minute_retention_days() -> i64 {
+    30
+}
+
+fn default_hour_retention_days() -> i64 {
+    730
+}
+
+// How much live consumption is kept in memory
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct ConsumptionCacheSettings {
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: i64,
+}
+
+impl Default for ConsumptionCacheSettings {
+    fn default() -> Self {
+        ConsumptionCacheSettings {
+            window_minutes: default_window_minutes(),
+        }
+    }
+}
+
+fn default_window_minutes() -> i64 {
+    15
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -4,29 +4,87 @@ use std::time::Duration;
 use anyhow::anyhow;
 use log::{info, warn};
 use sqlx::PgPool;
+use tokio::sync::RwLock;
 
-use crate::clients::tibber_client::TibberClient;
+use crate::clients::price_provider::PriceProvider;
+use crate::configuration::PriceLevelStrategy;
+use crate::now;
+use crate::service::live_events::{LiveEvent, LiveEvents};
+use crate::service::price_fetching::{self, PriceFetchStatus};
 use crate::service::prices;
 
 pub async fn start(
-    tibber_client: Arc<TibberClient>,
+    price_provider: Arc<dyn PriceProvider>,
+    price_level_strategy: PriceLevelStrategy,
+    fetch_status: Arc<RwLock<PriceFetchStatus>>,
     pool: Arc<PgPool>,
+    live_events: LiveEvents,
 ) -> Result<(), anyhow::Error> {
     let task = tokio::task::spawn(async move {
+        let mut backfilled = false;
         loop {
-            match prices::fetch_and_store_prices(tibber_client.as_ref(), pool.as_ref()).await {
+            let attempt = now();
+            let result = prices::fetch_and_store_prices(
+                price_provider.as_ref(),
+                pool.as_ref(),
+                &price_level_strategy,
+            )
+            .await;
+            if result.is_ok() && !backfilled {
+                match price_fetching::backfill_gaps(
+                    price_provider.as_ref(),
+                    pool.as_ref(),
+                    &price_level_strategy,
+                )
+                .await
+                {
+                    Ok(stored) => info!("Backfilled {} prices", stored),
+                    Err(e) => warn!("Failed to backfill prices: {}", e),
+                }
+                backfilled = true;
+            }
+            let next_fetch = match &result {
                 Ok(_) => {
-                    info!("Prices fetched and saved, waiting for 8 hours.");
-                    tokio::time::sleep(Duration::from_secs(8 * 60 * 60)).await;
+                    let coverage = price_fetching::get_price_coverage(pool.as_ref()).await;
+                    let has_tomorrow = coverage
+                        .as_ref()
+                        .map(|coverage| coverage.has_tomorrow)
+                        .unwrap_or(false);
+                    live_events.publish(LiveEvent::PriceUpdate {
+                        to: coverage.ok().and_then(|coverage| coverage.to),
+                        has_tomorrow,
+                        time: attempt,
+                    });
+                    let next_fetch = price_fetching::next_fetch(&now(), has_tomorrow);
+                    info!("Prices fetched and saved, fetching again at {}", next_fetch);
+                    next_fetch
                 }
                 Err(e) => {
                     warn!(
                         "Failed to fetch prices, trying again in 5 minutes, error: {}",
                         e
                     );
-                    tokio::time::sleep(Duration::from_secs(5 * 60)).await;
+                    now() + chrono::Duration::minutes(5)
+                }
+            };
+            {
+                let mut status = fetch_status.write().await;
+                status.last_attempt = Some(attempt);
+                match result {
+                    Ok(_) => {
+                        status.last_success = Some(attempt);
+                        status.last_error = None;
+                    }
+                    Err(e) => status.last_error = Some(e.to_string()),
                 }
+                status.next_fetch = Some(next_fetch);
             }
+            tokio::time::sleep(
+                (next_fetch - now())
+                    .to_std()
+                    .unwrap_or(Duration::from_secs(0)),
+            )
+            .await;
         }
     });
     match task.await {
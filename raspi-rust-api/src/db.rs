@@ -1,15 +1,23 @@
 use std::time::Duration;
 
+use anyhow::Context;
+use bigdecimal::{BigDecimal, ToPrimitive};
 use log::info;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use thiserror::Error;
 
 pub mod buttons;
+pub mod consumption;
+pub mod live_consumption;
 pub mod notification_settings;
+pub mod plug_energy;
+pub mod plug_overrides;
 pub mod plugs;
+pub mod price_settings;
 pub mod prices;
 pub mod rooms;
+pub mod schedule_profiles;
 pub mod schedules;
 pub mod temp_actions;
 pub mod temp_sensors;
@@ -30,6 +38,13 @@ pub enum DbError {
     ParseError(#[from] anyhow::Error),
 }
 
+// For DECIMAL columns
+pub fn to_f64(value: &BigDecimal) -> Result<f64, DbError> {
+    Ok(value
+        .to_f64()
+        .context(format!("Failed to parse floating point number: {}", value))?)
+}
+
 pub static DB_MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("db/migrations");
 
 impl DbConfig {
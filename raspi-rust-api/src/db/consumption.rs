@@ -0,0 +1,129 @@
+use bigdecimal::{BigDecimal, FromPrimitive};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+
+use crate::db::{to_f64, DbError};
+use crate::domain::{Consumption, ConsumptionPeriod, ConsumptionSummary};
+
+struct ConsumptionEntity {
+    starts_at: NaiveDateTime,
+    ends_at: NaiveDateTime,
+    kwh: BigDecimal,
+    cost: BigDecimal,
+}
+
+struct ConsumptionSummaryEntity {
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    kwh: BigDecimal,
+    cost: BigDecimal,
+    hours: i64,
+}
+
+// Hours without consumption are skipped, and stored hours are updated
+pub async fn upsert_consumption(pool: &PgPool, consumption: &[Consumption]) -> Result<(), DbError> {
+    let hours: Vec<(&Consumption, f64)> = consumption
+        .iter()
+        .filter_map(|c| Some((c, c.kwh?)))
+        .collect();
+    let starts_at: Vec<NaiveDateTime> = hours.iter().map(|(c, _)| c.from).collect();
+    let ends_at: Vec<NaiveDateTime> = hours.iter().map(|(c, _)| c.to).collect();
+    let kwh: Vec<BigDecimal> = hours
+        .iter()
+        .map(|(_, kwh)| BigDecimal::from_f64(*kwh).unwrap())
+        .collect();
+    let cost: Vec<BigDecimal> = hours
+        .iter()
+        .map(|(c, _)| BigDecimal::from_f64(c.cost).unwrap())
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO hourly_consumption (starts_at, ends_at, kwh, cost)
+        SELECT * FROM UNNEST($1::timestamp[], $2::timestamp[], $3::decimal[], $4::decimal[])
+        ON CONFLICT (starts_at) DO UPDATE
+        SET ends_at = EXCLUDED.ends_at, kwh = EXCLUDED.kwh, cost = EXCLUDED.cost
+        "#,
+        &starts_at,
+        &ends_at,
+        &kwh,
+        &cost,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_latest_consumption_time(pool: &PgPool) -> Result<Option<NaiveDateTime>, DbError> {
+    let latest = sqlx::query_scalar!("SELECT MAX(starts_at) FROM hourly_consumption")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(latest)
+}
+
+// Hours starting between from and to
+pub async fn get_consumption(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<Consumption>, DbError> {
+    let entities = sqlx::query_as!(
+        ConsumptionEntity,
+        "SELECT * FROM hourly_consumption WHERE starts_at >= $1 AND starts_at < $2 ORDER BY starts_at",
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities
+        .iter()
+        .map(|entity| {
+            Ok(Consumption {
+                from: entity.starts_at,
+                to: entity.ends_at,
+                kwh: Some(to_f64(&entity.kwh)?),
+                cost: to_f64(&entity.cost)?,
+            })
+        })
+        .collect()
+}
+
+// Periods without consumption are left out
+pub async fn get_consumption_summaries(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<ConsumptionSummary>, DbError> {
+    let entities = sqlx::query_as!(
+        ConsumptionSummaryEntity,
+        r#"
+        SELECT date_trunc($1, starts_at) AS "from!", date_trunc($1, starts_at) + ('1 ' || $1)::interval AS "to!",
+            SUM(kwh) AS "kwh!", SUM(cost) AS "cost!", COUNT(*) AS "hours!"
+        FROM hourly_consumption
+        WHERE starts_at >= $2 AND starts_at < $3
+        GROUP BY date_trunc($1, starts_at)
+        ORDER BY date_trunc($1, starts_at)
+        "#,
+        period.to_string().to_lowercase(),
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities
+        .iter()
+        .map(|entity| {
+            Ok(ConsumptionSummary {
+                from: entity.from,
+                to: entity.to,
+                kwh: to_f64(&entity.kwh)?,
+                cost: to_f64(&entity.cost)?,
+                hours: entity.hours,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,182 @@
+use anyhow::Context;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+
+use crate::db::DbError;
+use crate::domain::{ConsumptionResolution, LiveConsumption, PowerAggregate};
+
+struct PowerAggregateEntity {
+    starts_at: NaiveDateTime,
+    min_power: i64,
+    avg_power: BigDecimal,
+    max_power: i64,
+    samples: i64,
+}
+
+impl TryFrom<PowerAggregateEntity> for PowerAggregate {
+    type Error = DbError;
+
+    fn try_from(entity: PowerAggregateEntity) -> Result<Self, Self::Error> {
+        Ok(PowerAggregate {
+            starts_at: entity.starts_at,
+            min_power: entity.min_power,
+            avg_power: entity.avg_power.to_f64().context(format!(
+                "Failed to parse floating point number: {}",
+                entity.avg_power
+            ))?,
+            max_power: entity.max_power,
+            samples: entity.samples,
+        })
+    }
+}
+
+// Samples that are already stored are skipped
+pub async fn insert_live_consumption(
+    pool: &PgPool,
+    consumption: &[LiveConsumption],
+) -> Result<(), DbError> {
+    let timestamps: Vec<NaiveDateTime> = consumption.iter().map(|c| c.timestamp).collect();
+    let powers: Vec<i64> = consumption.iter().map(|c| c.power).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO live_consumption (timestamp, power)
+        SELECT * FROM UNNEST($1::timestamp[], $2::bigint[])
+        ON CONFLICT (timestamp) DO NOTHING
+        "#,
+        &timestamps,
+        &powers,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_latest_timestamp(pool: &PgPool) -> Result<Option<NaiveDateTime>, DbError> {
+    let latest = sqlx::query_scalar!("SELECT MAX(timestamp) FROM live_consumption")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(latest)
+}
+
+pub async fn get_live_consumption(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<LiveConsumption>, DbError> {
+    let consumption = sqlx::query_as!(
+        LiveConsumption,
+        "SELECT * FROM live_consumption WHERE timestamp >= $1 AND timestamp < $2 ORDER BY timestamp",
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(consumption)
+}
+
+pub async fn get_aggregates(
+    pool: &PgPool,
+    resolution: &ConsumptionResolution,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<PowerAggregate>, DbError> {
+    let entities = sqlx::query_as!(
+        PowerAggregateEntity,
+        r#"
+        SELECT starts_at, min_power, avg_power, max_power, samples FROM live_consumption_aggregates
+        WHERE resolution = $1 AND starts_at >= $2 AND starts_at < $3
+        ORDER BY starts_at
+        "#,
+        resolution.to_string(),
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities.into_iter().map(PowerAggregate::try_from).collect()
+}
+
+// Aggregates the raw samples of each minute between from and to
+pub async fn aggregate_minutes(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO live_consumption_aggregates (resolution, starts_at, min_power, avg_power, max_power, samples)
+        SELECT $1, date_trunc('minute', timestamp), MIN(power), AVG(power), MAX(power), COUNT(*)
+        FROM live_consumption
+        WHERE timestamp >= $2 AND timestamp < $3
+        GROUP BY date_trunc('minute', timestamp)
+        ON CONFLICT (resolution, starts_at) DO UPDATE
+        SET min_power = EXCLUDED.min_power, avg_power = EXCLUDED.avg_power, max_power = EXCLUDED.max_power, samples = EXCLUDED.samples
+        "#,
+        ConsumptionResolution::Minute.to_string(),
+        from,
+        to
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Aggregates the minute aggregates of each hour between from and to
+pub async fn aggregate_hours(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO live_consumption_aggregates (resolution, starts_at, min_power, avg_power, max_power, samples)
+        SELECT $1, date_trunc('hour', starts_at), MIN(min_power), SUM(avg_power * samples) / SUM(samples), MAX(max_power), SUM(samples)
+        FROM live_consumption_aggregates
+        WHERE resolution = $2 AND starts_at >= $3 AND starts_at < $4
+        GROUP BY date_trunc('hour', starts_at)
+        ON CONFLICT (resolution, starts_at) DO UPDATE
+        SET min_power = EXCLUDED.min_power, avg_power = EXCLUDED.avg_power, max_power = EXCLUDED.max_power, samples = EXCLUDED.samples
+        "#,
+        ConsumptionResolution::Hour.to_string(),
+        ConsumptionResolution::Minute.to_string(),
+        from,
+        to
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_live_consumption_before(
+    pool: &PgPool,
+    before: &NaiveDateTime,
+) -> Result<u64, DbError> {
+    let result = sqlx::query!("DELETE FROM live_consumption WHERE timestamp < $1", before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_aggregates_before(
+    pool: &PgPool,
+    resolution: &ConsumptionResolution,
+    before: &NaiveDateTime,
+) -> Result<u64, DbError> {
+    let result = sqlx::query!(
+        "DELETE FROM live_consumption_aggregates WHERE resolution = $1 AND starts_at < $2",
+        resolution.to_string(),
+        before
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -1,17 +1,51 @@
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::NaiveTime;
 use sqlx::PgPool;
 
 use crate::db::DbError;
 use crate::domain::NotificationSettings;
 
+struct NotificationSettingsEntity {
+    id: Option<i32>,
+    max_consumption: Option<i32>,
+    max_consumption_timeout_minutes: i32,
+    ntfy_topic: String,
+    price_summary_time: Option<NaiveTime>,
+    price_summary_block_hours: i32,
+    price_alert_max: Option<BigDecimal>,
+    price_alert_min: BigDecimal,
+}
+
+impl From<NotificationSettingsEntity> for NotificationSettings {
+    fn from(entity: NotificationSettingsEntity) -> Self {
+        Self {
+            id: entity.id,
+            max_consumption: entity.max_consumption,
+            max_consumption_timeout_minutes: entity.max_consumption_timeout_minutes,
+            ntfy_topic: entity.ntfy_topic,
+            price_summary_time: entity.price_summary_time,
+            price_summary_block_hours: entity.price_summary_block_hours,
+            price_alert_max: entity
+                .price_alert_max
+                .map(|max| max.to_f64().expect("Failed to convert to f64")),
+            price_alert_min: entity
+                .price_alert_min
+                .to_f64()
+                .expect("Failed to convert to f64"),
+        }
+    }
+}
+
 pub async fn get_notification_settings(
     pool: &PgPool,
 ) -> Result<Option<NotificationSettings>, DbError> {
     Ok(sqlx::query_as!(
-        NotificationSettings,
+        NotificationSettingsEntity,
         "SELECT * FROM notification_settings LIMIT 1"
     )
     .fetch_optional(pool)
-    .await?)
+    .await?
+    .map(|entity| entity.into()))
 }
 
 pub async fn upsert_notification_settings(
@@ -20,14 +54,20 @@ pub async fn upsert_notification_settings(
 ) -> Result<(), DbError> {
     sqlx::query!(
         r#"
-        INSERT INTO notification_settings (max_consumption, max_consumption_timeout_minutes, ntfy_topic)
-        VALUES ($1, $2, $3)
+        INSERT INTO notification_settings (max_consumption, max_consumption_timeout_minutes, ntfy_topic, price_summary_time, price_summary_block_hours, price_alert_max, price_alert_min)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         ON CONFLICT (id) DO UPDATE
-        SET max_consumption = $1, max_consumption_timeout_minutes = $2, ntfy_topic = $3
+        SET max_consumption = $1, max_consumption_timeout_minutes = $2, ntfy_topic = $3, price_summary_time = $4, price_summary_block_hours = $5, price_alert_max = $6, price_alert_min = $7
         "#,
         settings.max_consumption,
         settings.max_consumption_timeout_minutes,
-        settings.ntfy_topic
+        settings.ntfy_topic,
+        settings.price_summary_time,
+        settings.price_summary_block_hours,
+        settings
+            .price_alert_max
+            .map(|max| BigDecimal::from_f64(max).unwrap()),
+        BigDecimal::from_f64(settings.price_alert_min).unwrap(),
     )
     .execute(pool)
     .await?;
@@ -0,0 +1,207 @@
+use bigdecimal::{BigDecimal, FromPrimitive};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::{to_f64, DbError};
+use crate::domain::{ConsumptionPeriod, MeterReading, PlugEnergy, PlugEnergyReading, RoomEnergy};
+
+struct PlugEnergyReadingEntity {
+    plug_id: Uuid,
+    time: NaiveDateTime,
+    counter: Option<i64>,
+    energy_wh: BigDecimal,
+    power: Option<BigDecimal>,
+    since: Option<NaiveDateTime>,
+    is_on: Option<bool>,
+    estimated: bool,
+}
+
+struct EnergyEntity {
+    id: Uuid,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    energy_wh: BigDecimal,
+}
+
+impl TryFrom<PlugEnergyReadingEntity> for PlugEnergyReading {
+    type Error = DbError;
+
+    fn try_from(entity: PlugEnergyReadingEntity) -> Result<Self, Self::Error> {
+        Ok(PlugEnergyReading {
+            plug_id: entity.plug_id,
+            time: entity.time,
+            since: entity.since,
+            counter: entity.counter,
+            energy_wh: to_f64(&entity.energy_wh)?,
+            estimated: entity.estimated,
+            power: entity.power.as_ref().map(to_f64).transpose()?,
+            is_on: entity.is_on,
+        })
+    }
+}
+
+pub async fn insert_reading(pool: &PgPool, reading: &PlugEnergyReading) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO plug_energy (plug_id, time, counter, energy_wh, power, since, is_on, estimated)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        reading.plug_id,
+        reading.time,
+        reading.counter,
+        BigDecimal::from_f64(reading.energy_wh).unwrap(),
+        reading.power.and_then(BigDecimal::from_f64),
+        reading.since,
+        reading.is_on,
+        reading.estimated,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_latest_reading(
+    pool: &PgPool,
+    plug_id: &Uuid,
+) -> Result<Option<PlugEnergyReading>, DbError> {
+    let entity = sqlx::query_as!(
+        PlugEnergyReadingEntity,
+        r#"
+        SELECT plug_id, time, counter, energy_wh, power, since, is_on, estimated FROM plug_energy
+        WHERE plug_id = $1 ORDER BY time DESC LIMIT 1
+        "#,
+        plug_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    entity.map(PlugEnergyReading::try_from).transpose()
+}
+
+pub async fn get_latest_meter_reading(
+    pool: &PgPool,
+    plug_id: &Uuid,
+) -> Result<Option<MeterReading>, DbError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT metered.counter AS "counter!", COALESCE(SUM(later.energy_wh), 0) AS "energy_wh_since!"
+        FROM (
+            SELECT time, counter FROM plug_energy
+            WHERE plug_id = $1 AND counter IS NOT NULL ORDER BY time DESC LIMIT 1
+        ) metered
+        LEFT JOIN plug_energy later ON later.plug_id = $1 AND later.time > metered.time
+        GROUP BY metered.counter
+        "#,
+        plug_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        Ok(MeterReading {
+            counter: row.counter,
+            energy_wh_since: to_f64(&row.energy_wh_since)?,
+        })
+    })
+    .transpose()
+}
+
+// Readings taken between from and to, with the energy used since the previous reading
+pub async fn get_readings(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<PlugEnergyReading>, DbError> {
+    let entities = sqlx::query_as!(
+        PlugEnergyReadingEntity,
+        r#"
+        SELECT plug_id, time, counter, energy_wh, power, since, is_on, estimated FROM plug_energy
+        WHERE time >= $1 AND time < $2
+        ORDER BY time, plug_id
+        "#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities
+        .into_iter()
+        .map(PlugEnergyReading::try_from)
+        .collect()
+}
+
+pub async fn get_plug_energy(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<PlugEnergy>, DbError> {
+    let entities = sqlx::query_as!(
+        EnergyEntity,
+        r#"
+        SELECT plug_id AS "id!", date_trunc($1, time) AS "from!", date_trunc($1, time) + ('1 ' || $1)::interval AS "to!",
+            SUM(energy_wh) AS "energy_wh!"
+        FROM plug_energy
+        WHERE time >= $2 AND time < $3
+        GROUP BY plug_id, date_trunc($1, time)
+        ORDER BY date_trunc($1, time), plug_id
+        "#,
+        period.to_string().to_lowercase(),
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities
+        .iter()
+        .map(|entity| {
+            Ok(PlugEnergy {
+                plug_id: entity.id,
+                from: entity.from,
+                to: entity.to,
+                kwh: to_f64(&entity.energy_wh)? / 1000.0,
+            })
+        })
+        .collect()
+}
+
+pub async fn get_room_energy(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<RoomEnergy>, DbError> {
+    let entities = sqlx::query_as!(
+        EnergyEntity,
+        r#"
+        SELECT plugs.room_id AS "id!", date_trunc($1, time) AS "from!", date_trunc($1, time) + ('1 ' || $1)::interval AS "to!",
+            SUM(energy_wh) AS "energy_wh!"
+        FROM plug_energy
+        JOIN plugs ON plugs.id = plug_energy.plug_id
+        WHERE time >= $2 AND time < $3
+        GROUP BY plugs.room_id, date_trunc($1, time)
+        ORDER BY date_trunc($1, time), plugs.room_id
+        "#,
+        period.to_string().to_lowercase(),
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    entities
+        .iter()
+        .map(|entity| {
+            Ok(RoomEnergy {
+                room_id: entity.id,
+                from: entity.from,
+                to: entity.to,
+                kwh: to_f64(&entity.energy_wh)? / 1000.0,
+            })
+        })
+        .collect()
+}
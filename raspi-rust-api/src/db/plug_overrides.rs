@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::DbError;
+use crate::domain::{ActionType, PlugOverride};
+
+struct PlugOverrideEntity {
+    id: Uuid,
+    plug_ids: Vec<Uuid>,
+    action: String,
+    starts_at: Option<NaiveDateTime>,
+    expires_at: NaiveDateTime,
+}
+
+pub async fn get_plug_overrides(pool: &PgPool) -> Result<Vec<PlugOverride>, DbError> {
+    let entities: Vec<PlugOverrideEntity> =
+        sqlx::query_as!(PlugOverrideEntity, "SELECT * FROM plug_overrides")
+            .fetch_all(pool)
+            .await?;
+
+    entities
+        .iter()
+        .map(|entity| {
+            Ok(PlugOverride {
+                id: entity.id,
+                plug_ids: entity.plug_ids.clone(),
+                action: ActionType::from_str(&entity.action)
+                    .map_err(|_| anyhow!("Unknown action type: {}", entity.action))?,
+                starts_at: entity.starts_at,
+                expires_at: entity.expires_at,
+            })
+        })
+        .collect()
+}
+
+pub async fn create_plug_override(
+    pool: &PgPool,
+    plug_override: &PlugOverride,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO plug_overrides (id, plug_ids, action, starts_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        plug_override.id,
+        &plug_override.plug_ids,
+        plug_override.action.to_string(),
+        plug_override.starts_at,
+        plug_override.expires_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_plug_override(
+    pool: &PgPool,
+    plug_override: &PlugOverride,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        UPDATE plug_overrides
+        SET plug_ids = $2, action = $3, starts_at = $4, expires_at = $5
+        WHERE id = $1
+        "#,
+        plug_override.id,
+        &plug_override.plug_ids,
+        plug_override.action.to_string(),
+        plug_override.starts_at,
+        plug_override.expires_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_plug_override(pool: &PgPool, id: &Uuid) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        DELETE FROM plug_overrides WHERE id = $1
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -24,8 +24,8 @@ pub async fn get_room_plugs(pool: &PgPool, room_id: &Uuid) -> Result<Vec<Plug>,
 pub async fn create_plug(pool: &PgPool, new_plug: &Plug) -> Result<(), DbError> {
     sqlx::query!(
         r#"
-    INSERT INTO plugs (id, name, ip, username, password, room_id, scheduled)
-    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    INSERT INTO plugs (id, name, ip, username, password, room_id, scheduled, opportunistic, rated_watts)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
     "#,
         new_plug.id,
         new_plug.name,
@@ -34,6 +34,8 @@ pub async fn create_plug(pool: &PgPool, new_plug: &Plug) -> Result<(), DbError>
         new_plug.password,
         new_plug.room_id,
         new_plug.scheduled,
+        new_plug.opportunistic,
+        new_plug.rated_watts,
     )
     .execute(pool)
     .await?;
@@ -58,7 +60,7 @@ pub async fn update_plug(pool: &PgPool, plug: Plug) -> Result<(), DbError> {
     sqlx::query!(
         r#"
         UPDATE plugs
-        SET name = $2, ip = $3, username = $4, password = $5, room_id = $6, scheduled = $7
+        SET name = $2, ip = $3, username = $4, password = $5, room_id = $6, scheduled = $7, opportunistic = $8, rated_watts = $9
         WHERE id = $1
         "#,
         plug.id,
@@ -68,6 +70,8 @@ pub async fn update_plug(pool: &PgPool, plug: Plug) -> Result<(), DbError> {
         plug.password,
         plug.room_id,
         plug.scheduled,
+        plug.opportunistic,
+        plug.rated_watts,
     )
     .execute(pool)
     .await?;
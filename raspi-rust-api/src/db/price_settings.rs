@@ -0,0 +1,186 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{NaiveTime, Weekday};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::DbError;
+use crate::domain::{GridTariff, PriceLevel, PriceSettings};
+
+struct PriceSettingsEntity {
+    electricity_tax: BigDecimal,
+    vat_percent: BigDecimal,
+    use_total_price: bool,
+    subsidy_threshold: Option<BigDecimal>,
+    subsidy_percent: BigDecimal,
+    fallback_price_level: Option<String>,
+    negative_price_mode: bool,
+    negative_price_threshold: BigDecimal,
+}
+
+struct GridTariffEntity {
+    id: Uuid,
+    name: String,
+    amount: BigDecimal,
+    months: Vec<i32>,
+    days: Vec<String>,
+    from_time: NaiveTime,
+    to_time: NaiveTime,
+}
+
+impl GridTariffEntity {
+    fn to_domain(&self) -> Result<GridTariff, anyhow::Error> {
+        Ok(GridTariff {
+            id: self.id,
+            name: self.name.clone(),
+            amount: self.amount.to_f64().expect("Failed to convert to f64"),
+            months: self.months.iter().map(|month| *month as u32).collect(),
+            days: self
+                .days
+                .iter()
+                .map(|day| Weekday::from_str(day).map_err(|_| anyhow!("Unknown weekday: {}", day)))
+                .collect::<Result<Vec<Weekday>, anyhow::Error>>()?,
+            from_time: self.from_time,
+            to_time: self.to_time,
+        })
+    }
+}
+
+pub async fn get_price_settings(pool: &PgPool) -> Result<Option<PriceSettings>, DbError> {
+    let entity = sqlx::query_as!(
+        PriceSettingsEntity,
+        "SELECT electricity_tax, vat_percent, use_total_price, subsidy_threshold, subsidy_percent, fallback_price_level, negative_price_mode, negative_price_threshold FROM price_settings LIMIT 1"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(entity.map(|entity| PriceSettings {
+        electricity_tax: entity
+            .electricity_tax
+            .to_f64()
+            .expect("Failed to convert to f64"),
+        vat_percent: entity
+            .vat_percent
+            .to_f64()
+            .expect("Failed to convert to f64"),
+        use_total_price: entity.use_total_price,
+        subsidy_threshold: entity
+            .subsidy_threshold
+            .map(|threshold| threshold.to_f64().expect("Failed to convert to f64")),
+        subsidy_percent: entity
+            .subsidy_percent
+            .to_f64()
+            .expect("Failed to convert to f64"),
+        fallback_price_level: entity.fallback_price_level.map(|level| {
+            PriceLevel::from_str(&level).expect("Failed to convert string to PriceLevel")
+        }),
+        negative_price_mode: entity.negative_price_mode,
+        negative_price_threshold: entity
+            .negative_price_threshold
+            .to_f64()
+            .expect("Failed to convert to f64"),
+    }))
+}
+
+pub async fn upsert_price_settings(pool: &PgPool, settings: &PriceSettings) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO price_settings (electricity_tax, vat_percent, use_total_price, subsidy_threshold, subsidy_percent, fallback_price_level, negative_price_mode, negative_price_threshold)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (id) DO UPDATE
+        SET electricity_tax = $1, vat_percent = $2, use_total_price = $3, subsidy_threshold = $4, subsidy_percent = $5, fallback_price_level = $6, negative_price_mode = $7, negative_price_threshold = $8
+        "#,
+        BigDecimal::from_f64(settings.electricity_tax).unwrap(),
+        BigDecimal::from_f64(settings.vat_percent).unwrap(),
+        settings.use_total_price,
+        settings
+            .subsidy_threshold
+            .map(|threshold| BigDecimal::from_f64(threshold).unwrap()),
+        BigDecimal::from_f64(settings.subsidy_percent).unwrap(),
+        settings
+            .fallback_price_level
+            .map(|level| level.to_string()),
+        settings.negative_price_mode,
+        BigDecimal::from_f64(settings.negative_price_threshold).unwrap(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_grid_tariffs(pool: &PgPool) -> Result<Vec<GridTariff>, DbError> {
+    let entities: Vec<GridTariffEntity> =
+        sqlx::query_as!(GridTariffEntity, "SELECT * FROM grid_tariffs")
+            .fetch_all(pool)
+            .await?;
+
+    entities
+        .iter()
+        .map(|entity| Ok(entity.to_domain()?))
+        .collect()
+}
+
+pub async fn create_grid_tariff(pool: &PgPool, tariff: &GridTariff) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO grid_tariffs (id, name, amount, months, days, from_time, to_time)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        tariff.id,
+        tariff.name,
+        BigDecimal::from_f64(tariff.amount).unwrap(),
+        &months(tariff),
+        &days(tariff),
+        tariff.from_time,
+        tariff.to_time,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_grid_tariff(pool: &PgPool, tariff: &GridTariff) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        UPDATE grid_tariffs
+        SET name = $2, amount = $3, months = $4, days = $5, from_time = $6, to_time = $7
+        WHERE id = $1
+        "#,
+        tariff.id,
+        tariff.name,
+        BigDecimal::from_f64(tariff.amount).unwrap(),
+        &months(tariff),
+        &days(tariff),
+        tariff.from_time,
+        tariff.to_time,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_grid_tariff(pool: &PgPool, id: &Uuid) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        DELETE FROM grid_tariffs WHERE id = $1
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+fn months(tariff: &GridTariff) -> Vec<i32> {
+    tariff.months.iter().map(|month| *month as i32).collect()
+}
+
+fn days(tariff: &GridTariff) -> Vec<String> {
+    tariff.days.iter().map(|day| day.to_string()).collect()
+}
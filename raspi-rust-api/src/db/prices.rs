@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
-use chrono::{Duration, DurationRound, NaiveDateTime};
+use chrono::NaiveDateTime;
 use sqlx::PgPool;
 
 use crate::db::DbError;
@@ -10,53 +10,73 @@ use crate::domain::{PriceInfo, PriceLevel};
 struct PriceInfoEntity {
     pub amount: BigDecimal,
     pub currency: String,
-    pub ext_price_level: String,
+    pub ext_price_level: Option<String>,
     pub price_level: Option<String>,
     pub starts_at: NaiveDateTime,
+    pub total: Option<BigDecimal>,
+    pub subsidy: Option<BigDecimal>,
+    pub duration_minutes: i32,
 }
 
 impl From<PriceInfoEntity> for PriceInfo {
     fn from(entity: PriceInfoEntity) -> Self {
         Self {
             amount: entity.amount.to_f64().expect("Failed to convert to f64"),
+            total: entity
+                .total
+                .map(|total| total.to_f64().expect("Failed to convert to f64")),
+            subsidy: entity
+                .subsidy
+                .map(|subsidy| subsidy.to_f64().expect("Failed to convert to f64")),
             currency: entity.currency.to_string(),
-            ext_price_level: PriceLevel::from_str(&entity.ext_price_level)
-                .expect("Failed to convert string to PriceLevel"),
+            ext_price_level: entity.ext_price_level.map(|string| {
+                PriceLevel::from_str(&string).expect("Failed to convert string to PriceLevel")
+            }),
             price_level: entity.price_level.map(|string| {
                 PriceLevel::from_str(&string).expect("Failed to convert string to PriceLevel")
             }),
             starts_at: entity.starts_at,
+            duration_minutes: entity.duration_minutes,
         }
     }
 }
 
 pub async fn insert_prices(pool: &PgPool, prices: &Vec<PriceInfo>) -> Result<(), DbError> {
-    let timestamps: Vec<NaiveDateTime> = prices.iter().map(|price| price.starts_at).collect();
-
     let mut tx = pool.begin().await?;
 
-    sqlx::query!(
-        r#"
-        DELETE FROM prices WHERE starts_at = any($1)
+    for price_info in prices {
+        // Replaces every stored interval starting within the new one, in case the resolution changed
+        sqlx::query!(
+            r#"
+        DELETE FROM prices WHERE starts_at >= $1 AND starts_at < $2
         "#,
-        &timestamps
-    )
-    .fetch_all(&mut tx)
-    .await?;
+            price_info.starts_at,
+            price_info.ends_at()
+        )
+        .execute(&mut tx)
+        .await?;
 
-    for price_info in prices {
         sqlx::query!(
             r#"
-        INSERT INTO prices (starts_at, amount, currency, ext_price_level, price_level)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO prices (starts_at, amount, currency, ext_price_level, price_level, total, subsidy, duration_minutes)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         "#,
             price_info.starts_at,
             BigDecimal::from_f64(price_info.amount).unwrap(),
             price_info.currency,
-            price_info.ext_price_level.to_string(),
+            price_info
+                .ext_price_level
+                .map(|price_level| { price_level.to_string() }),
             price_info
                 .price_level
                 .map(|price_level| { price_level.to_string() }),
+            price_info
+                .total
+                .map(|total| BigDecimal::from_f64(total).unwrap()),
+            price_info
+                .subsidy
+                .map(|subsidy| BigDecimal::from_f64(subsidy).unwrap()),
+            price_info.duration_minutes,
         )
         .execute(&mut tx)
         .await?;
@@ -65,12 +85,17 @@ pub async fn insert_prices(pool: &PgPool, prices: &Vec<PriceInfo>) -> Result<(),
     Ok(())
 }
 
+// The price of the interval containing the time
 pub async fn get_price(pool: &PgPool, time: &NaiveDateTime) -> Result<Option<PriceInfo>, DbError> {
     let entity = sqlx::query_as!(
         PriceInfoEntity,
-        "SELECT * FROM prices WHERE starts_at = $1",
-        time.duration_trunc(Duration::hours(1))
-            .expect("Failed to truncate timestamp")
+        r#"
+        SELECT * FROM prices
+        WHERE starts_at <= $1 AND $1 < starts_at + make_interval(mins => duration_minutes)
+        ORDER BY starts_at DESC
+        LIMIT 1
+        "#,
+        time
     )
     .fetch_optional(pool)
     .await?;
@@ -84,7 +109,7 @@ pub async fn get_prices(
 ) -> Result<Vec<PriceInfo>, DbError> {
     let entities = sqlx::query_as!(
         PriceInfoEntity,
-        "SELECT * FROM prices WHERE starts_at > $1 AND starts_at < $2",
+        "SELECT * FROM prices WHERE starts_at > $1 AND starts_at < $2 ORDER BY starts_at",
         from,
         to
     )
@@ -92,3 +117,26 @@ pub async fn get_prices(
     .await?;
     Ok(entities.into_iter().map(|e| e.into()).collect())
 }
+
+// The latest price before the time whose interval covers the same time of day
+pub async fn get_latest_price_at_time_of_day(
+    pool: &PgPool,
+    time: &NaiveDateTime,
+) -> Result<Option<PriceInfo>, DbError> {
+    let entity = sqlx::query_as!(
+        PriceInfoEntity,
+        r#"
+        SELECT * FROM prices
+        WHERE starts_at < $1
+          AND EXTRACT(EPOCH FROM starts_at::time) <= EXTRACT(EPOCH FROM $2::time)
+          AND EXTRACT(EPOCH FROM $2::time) < EXTRACT(EPOCH FROM starts_at::time) + duration_minutes * 60
+        ORDER BY starts_at DESC
+        LIMIT 1
+        "#,
+        time,
+        time.time()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(entity.map(|e| e.into()))
+}
@@ -14,17 +14,24 @@ pub async fn get_rooms(pool: &PgPool) -> Result<Vec<Room>, DbError> {
     Ok(res)
 }
 
-pub async fn create_room(pool: &PgPool, name: &str, min_temp: &Option<f64>) -> Result<(), DbError> {
+pub async fn create_room(
+    pool: &PgPool,
+    name: &str,
+    min_temp: &Option<f64>,
+    max_temp: &Option<f64>,
+) -> Result<(), DbError> {
     let uuid = Uuid::new_v4();
     let min_temp = min_temp.as_ref().map(|temp| BigDecimal::from_f64(*temp).unwrap());
+    let max_temp = max_temp.as_ref().map(|temp| BigDecimal::from_f64(*temp).unwrap());
     sqlx::query!(
         r#"
-        INSERT INTO rooms (id, name, min_temp)
-        VALUES ($1, $2, $3)
+        INSERT INTO rooms (id, name, min_temp, max_temp)
+        VALUES ($1, $2, $3, $4)
         "#,
         uuid,
         name,
-        min_temp
+        min_temp,
+        max_temp
     )
     .execute(pool)
     .await?;
@@ -36,15 +43,19 @@ pub async fn update_room(pool: &PgPool, room: &Room) -> Result<(), DbError> {
     let min_temp = room
         .min_temp
         .map(|temp| BigDecimal::from_f64(temp).unwrap());
+    let max_temp = room
+        .max_temp
+        .map(|temp| BigDecimal::from_f64(temp).unwrap());
     sqlx::query!(
         r#"
         UPDATE rooms
-        SET name = $2, min_temp = $3
+        SET name = $2, min_temp = $3, max_temp = $4
         WHERE id = $1
         "#,
         room.id,
         room.name,
         min_temp,
+        max_temp,
     )
     .execute(pool)
     .await?;
@@ -74,6 +85,10 @@ impl FromRow<'_, PgRow> for Room {
                 None => None,
                 Some(temp) => temp.to_f64(),
             },
+            max_temp: match row.get::<Option<BigDecimal>, &str>("max_temp") {
+                None => None,
+                Some(temp) => temp.to_f64(),
+            },
         })
     }
 }
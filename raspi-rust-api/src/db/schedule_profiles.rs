@@ -0,0 +1,99 @@
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::DbError;
+use crate::domain::{ActiveScheduleProfile, ScheduleProfile};
+
+pub async fn get_schedule_profiles(pool: &PgPool) -> Result<Vec<ScheduleProfile>, DbError> {
+    let profiles = sqlx::query_as!(ScheduleProfile, "SELECT * FROM schedule_profiles")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(profiles)
+}
+
+pub async fn create_schedule_profile(
+    pool: &PgPool,
+    profile: &ScheduleProfile,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO schedule_profiles (id, name)
+        VALUES ($1, $2)
+        "#,
+        profile.id,
+        profile.name,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_schedule_profile(
+    pool: &PgPool,
+    profile: &ScheduleProfile,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        UPDATE schedule_profiles
+        SET name = $2
+        WHERE id = $1
+        "#,
+        profile.id,
+        profile.name,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_schedule_profile(pool: &PgPool, id: &Uuid) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        DELETE FROM schedule_profiles WHERE id = $1
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_active_schedule_profile(pool: &PgPool) -> Result<ActiveScheduleProfile, DbError> {
+    Ok(sqlx::query_as!(
+        ActiveScheduleProfile,
+        "SELECT profile_id, starts_at, ends_at, fallback_profile_id FROM active_schedule_profile LIMIT 1"
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_active_profile_id(pool: &PgPool, time: &NaiveDateTime) -> Result<Uuid, DbError> {
+    Ok(get_active_schedule_profile(pool).await?.profile_id_at(time))
+}
+
+pub async fn set_active_schedule_profile(
+    pool: &PgPool,
+    active: &ActiveScheduleProfile,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO active_schedule_profile (profile_id, starts_at, ends_at, fallback_profile_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (id) DO UPDATE
+        SET profile_id = $1, starts_at = $2, ends_at = $3, fallback_profile_id = $4
+        "#,
+        active.profile_id,
+        active.starts_at,
+        active.ends_at,
+        active.fallback_profile_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
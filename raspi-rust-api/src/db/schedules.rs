@@ -6,7 +6,7 @@ use sqlx::types::BigDecimal;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::db::DbError;
+use crate::db::{schedule_profiles, DbError};
 use crate::domain::{PriceLevel, Schedule};
 
 #[derive(Copy, Clone, Debug)]
@@ -25,6 +25,7 @@ struct ScheduleEntityWrapper {
 struct ScheduleEntity {
     id: Uuid,
     days: Vec<String>,
+    profile_id: Uuid,
 }
 
 impl ScheduleEntity {
@@ -68,6 +69,7 @@ impl ScheduleEntity {
                 .filter(|room_schedule| room_schedule.schedule_id == self.id)
                 .map(|room_schedule| room_schedule.room_id)
                 .collect(),
+            profile_id: self.profile_id,
         }
     }
 }
@@ -95,6 +97,7 @@ fn to_entity(schedule: &Schedule) -> Result<ScheduleEntityWrapper, anyhow::Error
                 .iter()
                 .map(|weekday| weekday.to_string())
                 .collect(),
+            profile_id: schedule.profile_id,
         },
         time_windows: schedule
             .time_windows
@@ -220,11 +223,14 @@ pub async fn get_matching_schedule(
         .map(|r| r.schedule_id)
         .collect();
 
+    let profile_id = schedule_profiles::get_active_profile_id(pool, time).await?;
+
     let entity: Option<ScheduleEntity> = sqlx::query_as!(
         ScheduleEntity,
-        "SELECT * FROM schedules WHERE id = any($1) AND $2 = any(days)",
+        "SELECT * FROM schedules WHERE id = any($1) AND $2 = any(days) AND profile_id = $3",
         &sched_ids,
         time.weekday().to_string(),
+        profile_id,
     )
     .fetch_optional(pool)
     .await?;
@@ -250,11 +256,12 @@ pub async fn create_schedule(pool: &PgPool, new_schedule: Schedule) -> Result<()
     let mut tx = pool.begin().await?;
     sqlx::query!(
         r#"
-    INSERT INTO schedules (id, days)
-    VALUES ($1, $2)
+    INSERT INTO schedules (id, days, profile_id)
+    VALUES ($1, $2, $3)
     "#,
         wrapper.schedule.id,
         &wrapper.schedule.days,
+        wrapper.schedule.profile_id,
     )
     .execute(&mut tx)
     .await?;
@@ -313,11 +320,12 @@ pub async fn update_schedule(pool: &PgPool, schedule: Schedule) -> Result<(), Db
     sqlx::query!(
         r#"
         UPDATE schedules
-        SET days = $2
+        SET days = $2, profile_id = $3
         WHERE id = $1
         "#,
         wrapper.schedule.id,
         &wrapper.schedule.days,
+        wrapper.schedule.profile_id,
     )
     .execute(&mut tx)
     .await?;
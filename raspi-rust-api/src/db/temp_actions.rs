@@ -1,11 +1,16 @@
+use std::str::FromStr;
+
 use anyhow::anyhow;
 use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::db::DbError;
-use crate::domain::{TempAction, TempActionType};
+use crate::domain::{
+    ArchivedTempAction, RecurringTempAction, TempAction, TempActionEndReason, TempActionSource,
+    TempActionType,
+};
 
 struct TempActionEntity {
     id: Uuid,
@@ -14,6 +19,97 @@ struct TempActionEntity {
     temp: Option<BigDecimal>,
     expires_at: NaiveDateTime,
     starts_at: Option<NaiveDateTime>,
+    priority: i32,
+    created_at: NaiveDateTime,
+    created_by: String,
+}
+
+impl TempActionEntity {
+    fn to_domain(&self) -> Result<TempAction, anyhow::Error> {
+        Ok(TempAction {
+            id: self.id,
+            room_ids: self.room_ids.clone(),
+            action_type: parse_action_type(&self.action, &self.temp)?,
+            expires_at: self.expires_at,
+            starts_at: self.starts_at,
+            priority: self.priority,
+            created_at: self.created_at,
+            created_by: TempActionSource::from_str(&self.created_by)
+                .map_err(|_| anyhow!("Unknown temp action source: {}", self.created_by))?,
+        })
+    }
+}
+
+struct TempActionHistoryEntity {
+    temp_action_id: Uuid,
+    room_ids: Vec<Uuid>,
+    action: String,
+    temp: Option<BigDecimal>,
+    expires_at: NaiveDateTime,
+    starts_at: Option<NaiveDateTime>,
+    priority: i32,
+    created_at: NaiveDateTime,
+    created_by: String,
+    ended_at: NaiveDateTime,
+    end_reason: String,
+}
+
+impl TempActionHistoryEntity {
+    fn to_domain(&self) -> Result<ArchivedTempAction, anyhow::Error> {
+        let temp_action = TempActionEntity {
+            id: self.temp_action_id,
+            room_ids: self.room_ids.clone(),
+            action: self.action.clone(),
+            temp: self.temp.clone(),
+            expires_at: self.expires_at,
+            starts_at: self.starts_at,
+            priority: self.priority,
+            created_at: self.created_at,
+            created_by: self.created_by.clone(),
+        }
+        .to_domain()?;
+        Ok(ArchivedTempAction {
+            temp_action,
+            ended_at: self.ended_at,
+            end_reason: TempActionEndReason::from_str(&self.end_reason)
+                .map_err(|_| anyhow!("Unknown end reason: {}", self.end_reason))?,
+        })
+    }
+}
+
+struct RecurringTempActionEntity {
+    id: Uuid,
+    room_ids: Vec<Uuid>,
+    action: String,
+    temp: Option<BigDecimal>,
+    days: Vec<String>,
+    from_time: NaiveTime,
+    to_time: NaiveTime,
+    ends_on: Option<NaiveDate>,
+    skipped_dates: Vec<NaiveDate>,
+    priority: i32,
+    created_at: NaiveDateTime,
+}
+
+impl RecurringTempActionEntity {
+    fn to_domain(&self) -> Result<RecurringTempAction, anyhow::Error> {
+        Ok(RecurringTempAction {
+            id: self.id,
+            room_ids: self.room_ids.clone(),
+            action_type: parse_action_type(&self.action, &self.temp)?,
+            days: self
+                .days
+                .iter()
+                .map(|day| Weekday::from_str(day).map_err(|_| anyhow!("Unknown weekday: {}", day)))
+                .collect::<Result<Vec<Weekday>, anyhow::Error>>()?,
+            from_time: self.from_time,
+            to_time: self.to_time,
+            ends_on: self.ends_on,
+            skipped_dates: self.skipped_dates.clone(),
+            priority: self.priority,
+            created_at: self.created_at,
+        })
+    }
 }
 
 fn parse_action_type(
@@ -45,26 +141,18 @@ pub async fn get_temp_actions(pool: &PgPool) -> Result<Vec<TempAction>, DbError>
             .fetch_all(pool)
             .await?;
 
-    entities
+    Ok(entities
         .iter()
-        .map(|entity| {
-            Ok(TempAction {
-                id: entity.id,
-                room_ids: entity.room_ids.clone(),
-                action_type: parse_action_type(&entity.action, &entity.temp)?,
-                expires_at: entity.expires_at,
-                starts_at: entity.starts_at,
-            })
-        })
-        .collect()
+        .map(|entity| entity.to_domain())
+        .collect::<Result<Vec<TempAction>, anyhow::Error>>()?)
 }
 
 pub async fn create_temp_action(pool: &PgPool, new_temp_action: TempAction) -> Result<(), DbError> {
     let (action_type, temp) = action_type_to_entity(&new_temp_action.action_type);
     sqlx::query!(
         r#"
-    INSERT INTO temp_actions (id, room_ids, action, temp, expires_at, starts_at)
-    VALUES ($1, $2, $3, $4, $5, $6)
+    INSERT INTO temp_actions (id, room_ids, action, temp, expires_at, starts_at, priority, created_at, created_by)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
     "#,
         new_temp_action.id,
         &new_temp_action.room_ids,
@@ -72,6 +160,9 @@ pub async fn create_temp_action(pool: &PgPool, new_temp_action: TempAction) -> R
         temp,
         new_temp_action.expires_at,
         new_temp_action.starts_at,
+        new_temp_action.priority,
+        new_temp_action.created_at,
+        new_temp_action.created_by.to_string(),
     )
     .execute(pool)
     .await?;
@@ -79,21 +170,185 @@ pub async fn create_temp_action(pool: &PgPool, new_temp_action: TempAction) -> R
     Ok(())
 }
 
-pub async fn update_temp_action(pool: &PgPool, temp_action: TempAction) -> Result<(), DbError> {
-    let (action_type, temp) = action_type_to_entity(&temp_action.action_type);
+// Moves the action to the history, so it no longer applies
+pub async fn archive_temp_action(
+    pool: &PgPool,
+    id: &Uuid,
+    ended_at: &NaiveDateTime,
+    end_reason: &TempActionEndReason,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        WITH ended AS (DELETE FROM temp_actions WHERE id = $1 RETURNING *)
+        INSERT INTO temp_action_history (temp_action_id, room_ids, action, temp, starts_at, expires_at, priority, created_at, created_by, ended_at, end_reason)
+        SELECT id, room_ids, action, temp, starts_at, expires_at, priority, created_at, created_by, $2, $3
+        FROM ended
+        "#,
+        id,
+        ended_at,
+        end_reason.to_string(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
 
+// Keeps the previous version of the action in the history
+pub async fn replace_temp_action(
+    pool: &PgPool,
+    temp_action: TempAction,
+    replaced_at: &NaiveDateTime,
+) -> Result<(), DbError> {
+    let mut tx = pool.begin().await?;
     sqlx::query!(
         r#"
-    UPDATE temp_actions
-    SET room_ids = $2, action = $3, temp = $4, expires_at = $5, starts_at = $6
-    WHERE id = $1
-    "#,
+        INSERT INTO temp_action_history (temp_action_id, room_ids, action, temp, starts_at, expires_at, priority, created_at, created_by, ended_at, end_reason)
+        SELECT id, room_ids, action, temp, starts_at, expires_at, priority, created_at, created_by, $2, $3
+        FROM temp_actions WHERE id = $1
+        "#,
+        temp_action.id,
+        replaced_at,
+        TempActionEndReason::REPLACED.to_string(),
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let (action_type, temp) = action_type_to_entity(&temp_action.action_type);
+    sqlx::query!(
+        r#"
+        UPDATE temp_actions
+        SET room_ids = $2, action = $3, temp = $4, expires_at = $5, starts_at = $6, priority = $7
+        WHERE id = $1
+        "#,
         temp_action.id,
         &temp_action.room_ids,
         action_type,
         temp,
         temp_action.expires_at,
-        temp_action.starts_at
+        temp_action.starts_at,
+        temp_action.priority,
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn get_temp_action_history(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<Vec<ArchivedTempAction>, DbError> {
+    let entities: Vec<TempActionHistoryEntity> = sqlx::query_as!(
+        TempActionHistoryEntity,
+        r#"
+        SELECT temp_action_id, room_ids, action, temp, expires_at, starts_at, priority, created_at, created_by, ended_at, end_reason
+        FROM temp_action_history
+        WHERE ended_at >= $1 AND ended_at < $2
+        ORDER BY ended_at
+        "#,
+        from,
+        to,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entities
+        .iter()
+        .map(|entity| entity.to_domain())
+        .collect::<Result<Vec<ArchivedTempAction>, anyhow::Error>>()?)
+}
+
+pub async fn get_recurring_temp_actions(
+    pool: &PgPool,
+) -> Result<Vec<RecurringTempAction>, DbError> {
+    let entities: Vec<RecurringTempActionEntity> = sqlx::query_as!(
+        RecurringTempActionEntity,
+        "SELECT * FROM recurring_temp_actions"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entities
+        .iter()
+        .map(|entity| entity.to_domain())
+        .collect::<Result<Vec<RecurringTempAction>, anyhow::Error>>()?)
+}
+
+pub async fn create_recurring_temp_action(
+    pool: &PgPool,
+    action: &RecurringTempAction,
+) -> Result<(), DbError> {
+    let (action_type, temp) = action_type_to_entity(&action.action_type);
+    let days: Vec<String> = action.days.iter().map(|day| day.to_string()).collect();
+    sqlx::query!(
+        r#"
+    INSERT INTO recurring_temp_actions (id, room_ids, action, temp, days, from_time, to_time, ends_on, skipped_dates, priority, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+    "#,
+        action.id,
+        &action.room_ids,
+        action_type,
+        temp,
+        &days,
+        action.from_time,
+        action.to_time,
+        action.ends_on,
+        &action.skipped_dates,
+        action.priority,
+        action.created_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_recurring_temp_action(
+    pool: &PgPool,
+    action: &RecurringTempAction,
+) -> Result<(), DbError> {
+    let (action_type, temp) = action_type_to_entity(&action.action_type);
+    let days: Vec<String> = action.days.iter().map(|day| day.to_string()).collect();
+    sqlx::query!(
+        r#"
+    UPDATE recurring_temp_actions
+    SET room_ids = $2, action = $3, temp = $4, days = $5, from_time = $6, to_time = $7, ends_on = $8, skipped_dates = $9, priority = $10
+    WHERE id = $1
+    "#,
+        action.id,
+        &action.room_ids,
+        action_type,
+        temp,
+        &days,
+        action.from_time,
+        action.to_time,
+        action.ends_on,
+        &action.skipped_dates,
+        action.priority,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn skip_recurring_temp_action(
+    pool: &PgPool,
+    id: &Uuid,
+    date: &NaiveDate,
+) -> Result<(), DbError> {
+    sqlx::query!(
+        r#"
+        UPDATE recurring_temp_actions
+        SET skipped_dates = array_append(skipped_dates, $2)
+        WHERE id = $1 AND NOT $2 = any(skipped_dates)
+        "#,
+        id,
+        date,
     )
     .execute(pool)
     .await?;
@@ -101,12 +356,25 @@ pub async fn update_temp_action(pool: &PgPool, temp_action: TempAction) -> Resul
     Ok(())
 }
 
-pub async fn delete_temp_action(pool: &PgPool, id: &Uuid) -> Result<(), DbError> {
+// Moves the action to the history like the one-off actions. It is kept as a single action
+// lasting until it ended, since the history has no columns for the schedule.
+pub async fn archive_recurring_temp_action(
+    pool: &PgPool,
+    id: &Uuid,
+    ended_at: &NaiveDateTime,
+    end_reason: &TempActionEndReason,
+) -> Result<(), DbError> {
     sqlx::query!(
         r#"
-        DELETE FROM temp_actions WHERE id = $1
+        WITH ended AS (DELETE FROM recurring_temp_actions WHERE id = $1 RETURNING *)
+        INSERT INTO temp_action_history (temp_action_id, room_ids, action, temp, starts_at, expires_at, priority, created_at, created_by, ended_at, end_reason)
+        SELECT id, room_ids, action, temp, NULL, COALESCE(ends_on + to_time, $2), priority, created_at, $4, $2, $3
+        FROM ended
         "#,
-        id
+        id,
+        ended_at,
+        end_reason.to_string(),
+        TempActionSource::API.to_string(),
     )
     .execute(pool)
     .await?;
@@ -4,15 +4,13 @@ use std::hash::Hash;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Context};
-use chrono::{NaiveDateTime, NaiveTime, Weekday};
-use log::warn;
+use chrono::{
+    Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, SubsecRound, Timelike, Utc, Weekday,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::types::ipnetwork::IpNetwork;
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, EnumString};
-use tibber::{
-    Consumption as TConsumption, EnergyUnits, PriceInfo as TPriceInfo, PriceLevel as TPriceLevel,
-};
 use uuid::Uuid;
 
 #[derive(
@@ -32,8 +30,19 @@ impl PriceLevel {
             .position(|p| p == *self)
             .unwrap_or_else(|| panic!("Couldn't get index of price level: {}", self)) as i32
     }
+
+    // The thresholds are the lower bounds of Cheap, Normal, Expensive and VeryExpensive
+    pub fn from_thresholds(value: f64, thresholds: &[f64; 4]) -> Self {
+        let index = thresholds
+            .iter()
+            .take_while(|threshold| value >= **threshold)
+            .count();
+        PriceLevel::from(index as i32)
+    }
 }
 
+pub const RATIO_THRESHOLDS: [f64; 4] = [0.5, 0.85, 1.15, 1.5];
+
 impl From<i32> for PriceLevel {
     fn from(value: i32) -> Self {
         let indexes: Vec<(i32, PriceLevel)> =
@@ -48,32 +57,20 @@ impl From<i32> for PriceLevel {
     }
 }
 
-impl From<TPriceLevel> for PriceLevel {
-    fn from(value: TPriceLevel) -> Self {
-        match value {
-            TPriceLevel::VeryCheap => PriceLevel::VeryCheap,
-            TPriceLevel::Cheap => PriceLevel::Cheap,
-            TPriceLevel::Normal => PriceLevel::Normal,
-            TPriceLevel::Expensive => PriceLevel::Expensive,
-            TPriceLevel::VeryExpensive => PriceLevel::VeryExpensive,
-            TPriceLevel::Other(_) | TPriceLevel::None => {
-                warn!(
-                    "Encountered unexpected Tibber price level: {:?}, setting to Normal",
-                    value
-                );
-                PriceLevel::Normal
-            }
-        }
-    }
-}
-
-#[derive(Serialize, PartialEq, Debug)]
+#[derive(Serialize, PartialEq, Debug, Clone)]
 pub struct PriceInfo {
     pub amount: f64,
+    // Including grid tariff, electricity tax and VAT, when price settings are configured
+    pub total: Option<f64>,
+    // Per kWh, deducted from both the spot price and the total price
+    pub subsidy: Option<f64>,
     pub currency: String,
-    pub ext_price_level: PriceLevel,
+    // Level set by the provider, None when it has no price levels of its own
+    pub ext_price_level: Option<PriceLevel>,
     pub price_level: Option<PriceLevel>,
     pub starts_at: NaiveDateTime,
+    // Length of the price interval, like 60 for hourly and 15 for quarter-hourly prices
+    pub duration_minutes: i32,
 }
 
 impl Display for PriceInfo {
@@ -82,30 +79,192 @@ impl Display for PriceInfo {
             "Price: {} {} - Level: {}",
             &self.amount,
             &self.currency,
-            &self.ext_price_level.to_string()
+            &self.level().to_string()
         ))
     }
 }
 
-impl From<TPriceInfo> for PriceInfo {
-    fn from(value: TPriceInfo) -> Self {
-        Self {
-            amount: value.total,
-            currency: value.currency,
-            ext_price_level: value.level.into(),
-            price_level: None,
-            starts_at: value.starts_at.naive_local(),
+impl PriceInfo {
+    pub fn ends_at(&self) -> NaiveDateTime {
+        self.starts_at + Duration::minutes(self.duration_minutes as i64)
+    }
+
+    pub fn contains(&self, time: &NaiveDateTime) -> bool {
+        self.starts_at <= *time && *time < self.ends_at()
+    }
+
+    pub fn level(&self) -> PriceLevel {
+        self.price_level
+            .or(self.ext_price_level)
+            .unwrap_or(PriceLevel::Normal)
+    }
+
+    pub fn subsidized_amount(&self) -> f64 {
+        self.amount - self.subsidy.unwrap_or(0.0)
+    }
+
+    // The price used for costs after subsidy, falls back to the spot price when the total is unknown
+    pub fn cost_price(&self, price_settings: Option<&PriceSettings>) -> f64 {
+        let price = match price_settings.filter(|settings| settings.use_total_price) {
+            Some(_) => self.total.unwrap_or(self.amount),
+            None => self.amount,
+        };
+        price - self.subsidy_deduction(price_settings)
+    }
+
+    // The subsidy is stored without VAT, so it includes VAT when deducted from the total price
+    pub fn subsidy_deduction(&self, price_settings: Option<&PriceSettings>) -> f64 {
+        let subsidy = self.subsidy.unwrap_or(0.0);
+        match price_settings
+            .filter(|settings| settings.use_total_price)
+            .zip(self.total)
+        {
+            Some((settings, _)) => subsidy * settings.vat_factor(),
+            None => subsidy,
         }
     }
 }
 
-impl PriceInfo {
-    pub fn level(&self) -> PriceLevel {
-        self.price_level.unwrap_or(self.ext_price_level)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyPrice {
+    pub total: f64,
+    pub starts_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PriceSettings {
+    // Per kWh, excluding VAT
+    pub electricity_tax: f64,
+    pub vat_percent: f64,
+    // Use the total price instead of the spot price for price levels and costs
+    #[serde(default)]
+    pub use_total_price: bool,
+    // In the same unit as the spot price, no subsidy is given when not set
+    #[serde(default)]
+    pub subsidy_threshold: Option<f64>,
+    // Share of the spot price above the threshold that is covered
+    #[serde(default = "default_subsidy_percent")]
+    pub subsidy_percent: f64,
+    // Used by the control loop when no price is available. When not set,
+    // the level of the latest stored price at the same time of day is used
+    #[serde(default)]
+    pub fallback_price_level: Option<PriceLevel>,
+    // Raises rooms to their max temp and turns on opportunistic plugs
+    // when the spot price is at or below the threshold
+    #[serde(default)]
+    pub negative_price_mode: bool,
+    #[serde(default)]
+    pub negative_price_threshold: f64,
+}
+
+fn default_subsidy_percent() -> f64 {
+    90.0
+}
+
+impl PriceSettings {
+    pub fn total_price(&self, spot_price: f64, grid_tariff: f64) -> f64 {
+        (spot_price + grid_tariff + self.electricity_tax) * self.vat_factor()
+    }
+
+    pub fn vat_factor(&self) -> f64 {
+        1.0 + self.vat_percent / 100.0
+    }
+
+    pub fn subsidy(&self, spot_price: f64) -> Option<f64> {
+        self.subsidy_threshold
+            .map(|threshold| (spot_price - threshold).max(0.0) * self.subsidy_percent / 100.0)
+    }
+
+    pub fn is_negative_price(&self, spot_price: f64) -> bool {
+        self.negative_price_mode && spot_price <= self.negative_price_threshold
     }
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GridTariff {
+    pub id: Uuid,
+    pub name: String,
+    // Per kWh, excluding VAT
+    pub amount: f64,
+    // Applies to all months or days when empty
+    pub months: Vec<u32>,
+    pub days: Vec<Weekday>,
+    pub from_time: NaiveTime,
+    // A window ending at or before from_time ends on the following day
+    pub to_time: NaiveTime,
+}
+
+impl GridTariff {
+    pub fn new(
+        name: &str,
+        amount: f64,
+        months: Vec<u32>,
+        days: Vec<Weekday>,
+        time_window: (NaiveTime, NaiveTime),
+    ) -> Result<Self, anyhow::Error> {
+        if let Some(month) = months.iter().find(|m| !(1..=12).contains(*m)) {
+            return Err(anyhow!("Invalid month: {}", month));
+        }
+        Ok(Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            amount,
+            months,
+            days,
+            from_time: time_window.0,
+            to_time: time_window.1,
+        })
+    }
+
+    // Months and days are matched against the hour itself, so a night tariff starting on
+    // Friday evening needs Saturday as well to cover Saturday morning
+    pub fn applies_at(&self, time: &NaiveDateTime) -> bool {
+        let in_window = match self.from_time < self.to_time {
+            true => self.from_time <= time.time() && time.time() < self.to_time,
+            false => self.from_time <= time.time() || time.time() < self.to_time,
+        };
+        (self.months.is_empty() || self.months.contains(&time.month()))
+            && (self.days.is_empty() || self.days.contains(&time.weekday()))
+            && in_window
+    }
+
+    // Overlapping tariffs use the most expensive one
+    pub fn amount_at(tariffs: &[GridTariff], time: &NaiveDateTime) -> f64 {
+        tariffs
+            .iter()
+            .filter(|tariff| tariff.applies_at(time))
+            .map(|tariff| tariff.amount)
+            .fold(0.0, f64::max)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GridTariffRequest {
+    pub name: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub months: Vec<u32>,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub from_time: NaiveTime,
+    pub to_time: NaiveTime,
+}
+
+impl TryFrom<GridTariffRequest> for GridTariff {
+    type Error = anyhow::Error;
+
+    fn try_from(request: GridTariffRequest) -> Result<Self, Self::Error> {
+        GridTariff::new(
+            &request.name,
+            request.amount,
+            request.months,
+            request.days,
+            (request.from_time, request.to_time),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Consumption {
     pub from: NaiveDateTime,
     pub to: NaiveDateTime,
@@ -113,30 +272,191 @@ pub struct Consumption {
     pub cost: f64,
 }
 
-impl From<&TConsumption> for Consumption {
-    fn from(value: &TConsumption) -> Self {
-        Self {
-            from: value.from.naive_local(),
-            to: value.to.naive_local(),
-            kwh: match value.energy {
-                EnergyUnits::kWh(kwh) => Some(kwh),
-                EnergyUnits::None => None,
+#[derive(Debug, EnumString, Display, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsumptionPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl ConsumptionPeriod {
+    // First day of the period the date is in, weeks start on Monday
+    pub fn start_of(&self, date: &NaiveDate) -> NaiveDate {
+        match self {
+            ConsumptionPeriod::Day => *date,
+            ConsumptionPeriod::Week => {
+                *date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            ConsumptionPeriod::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        }
+    }
+
+    // First day of the next period
+    pub fn next(&self, date: &NaiveDate) -> NaiveDate {
+        let start = self.start_of(date);
+        match self {
+            ConsumptionPeriod::Day => start + Duration::days(1),
+            ConsumptionPeriod::Week => start + Duration::weeks(1),
+            ConsumptionPeriod::Month if start.month() == 12 => {
+                NaiveDate::from_ymd(start.year() + 1, 1, 1)
+            }
+            ConsumptionPeriod::Month => NaiveDate::from_ymd(start.year(), start.month() + 1, 1),
+        }
+    }
+}
+
+// Consumption and cost from the price provider over a day, week or month
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConsumptionSummary {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub kwh: f64,
+    pub cost: f64,
+    // Hours with consumption
+    pub hours: i64,
+}
+
+// A reading of the energy counter and relay of a plug
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlugEnergyReading {
+    pub plug_id: Uuid,
+    pub time: NaiveDateTime,
+    // Time of the previous reading
+    pub since: Option<NaiveDateTime>,
+    // Watt-minutes since the plug started, not set for plugs without a meter
+    pub counter: Option<i64>,
+    // Since the previous reading
+    pub energy_wh: f64,
+    // From the rated wattage of the plug and the time it was on
+    pub estimated: bool,
+    pub power: Option<f64>,
+    pub is_on: Option<bool>,
+}
+
+// The latest reading of a plug with a counter
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReading {
+    pub counter: i64,
+    // Energy of the later readings, which were taken without a counter
+    pub energy_wh_since: f64,
+}
+
+impl PlugEnergyReading {
+    // The counter is compared with the latest one, so readings where the meter could not be read
+    // don't lose the energy used in the meantime
+    pub fn new(
+        plug: &Plug,
+        time: &NaiveDateTime,
+        counter: Option<i64>,
+        power: Option<f64>,
+        is_on: Option<bool>,
+        previous: Option<&PlugEnergyReading>,
+        meter: Option<&MeterReading>,
+    ) -> Self {
+        let (energy_wh, estimated) = match (previous, counter, meter) {
+            (None, _, _) => (0.0, false),
+            // The counter is reset when the plug restarts
+            (_, Some(counter), Some(meter)) if counter < meter.counter => {
+                (counter as f64 / 60.0, false)
+            }
+            // Without the energy already estimated since the counter was read
+            (_, Some(counter), Some(meter)) => (
+                ((counter - meter.counter) as f64 / 60.0 - meter.energy_wh_since).max(0.0),
+                false,
+            ),
+            // The plug is assumed to be on until the next reading
+            (Some(previous), _, _) => match (previous.is_on, plug.rated_watts) {
+                (Some(true), Some(watts)) => (
+                    watts as f64 * (*time - previous.time).num_seconds() as f64 / 3600.0,
+                    true,
+                ),
+                _ => (0.0, false),
             },
-            cost: value.cost,
+        };
+        PlugEnergyReading {
+            plug_id: plug.id,
+            time: *time,
+            since: previous.map(|previous| previous.time),
+            counter,
+            energy_wh,
+            estimated,
+            power,
+            is_on,
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlugEnergy {
+    pub plug_id: Uuid,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub kwh: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomEnergy {
+    pub room_id: Uuid,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub kwh: f64,
+}
 #[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize)]
 pub struct LiveConsumption {
     pub timestamp: NaiveDateTime,
     pub power: i64,
 }
 
+#[derive(Debug, EnumString, Display, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsumptionResolution {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl ConsumptionResolution {
+    // Start of the period the time is in
+    pub fn truncate(&self, time: &NaiveDateTime) -> NaiveDateTime {
+        match self {
+            ConsumptionResolution::Raw => *time,
+            ConsumptionResolution::Minute => time.date().and_hms(time.hour(), time.minute(), 0),
+            ConsumptionResolution::Hour => time.date().and_hms(time.hour(), 0, 0),
+        }
+    }
+}
+
+// Power in watts over a period, raw samples have a single sample
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PowerAggregate {
+    pub starts_at: NaiveDateTime,
+    pub min_power: i64,
+    pub avg_power: f64,
+    pub max_power: i64,
+    pub samples: i64,
+}
+
+impl From<&LiveConsumption> for PowerAggregate {
+    fn from(value: &LiveConsumption) -> Self {
+        Self {
+            starts_at: value.timestamp,
+            min_power: value.power,
+            avg_power: value.power as f64,
+            max_power: value.power,
+            samples: 1,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Room {
     pub id: Uuid,
     pub name: String,
     pub min_temp: Option<f64>,
+    // Comfort maximum used in negative price mode
+    #[serde(default)]
+    pub max_temp: Option<f64>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -156,6 +476,10 @@ pub struct Plug {
     pub password: String,
     pub room_id: Uuid,
     pub scheduled: bool,
+    // Turned on in negative price mode, like a water heater or an EV charger
+    pub opportunistic: bool,
+    // Used to estimate the energy of plugs without a meter
+    pub rated_watts: Option<i32>,
 }
 
 impl Plug {
@@ -175,6 +499,8 @@ impl Plug {
             password: password.to_string(),
             room_id: *room_id,
             scheduled: *scheduled,
+            opportunistic: false,
+            rated_watts: None,
         })
     }
 }
@@ -215,6 +541,7 @@ pub struct Schedule {
     pub days: Vec<Weekday>,
     pub time_windows: Vec<(NaiveTime, NaiveTime)>,
     pub room_ids: Vec<Uuid>,
+    pub profile_id: Uuid,
 }
 
 impl Schedule {
@@ -223,6 +550,7 @@ impl Schedule {
         days: Vec<Weekday>,
         time_windows: Vec<(NaiveTime, NaiveTime)>,
         room_ids: Vec<Uuid>,
+        profile_id: Uuid,
     ) -> Result<Self, anyhow::Error> {
         if temps.is_empty() || days.is_empty() || time_windows.is_empty() || room_ids.is_empty() {
             return Err(anyhow!(
@@ -235,9 +563,18 @@ impl Schedule {
             days,
             time_windows,
             room_ids,
+            profile_id,
         })
     }
 
+    pub fn is_active_at(&self, time: &NaiveDateTime) -> bool {
+        self.days.contains(&time.weekday())
+            && self
+                .time_windows
+                .iter()
+                .any(|(from, to)| *from <= time.time() && time.time() < *to)
+    }
+
     pub fn get_temp(&self, price_level: &PriceLevel) -> f64 {
         if let Some(temp) = self.temps.get(price_level) {
             *temp
@@ -289,6 +626,41 @@ impl Schedule {
     }
 }
 
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleProfile {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl ScheduleProfile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct ActiveScheduleProfile {
+    pub profile_id: Uuid,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub fallback_profile_id: Option<Uuid>,
+}
+
+impl ActiveScheduleProfile {
+    // Outside the optional date range the profile that was active before the switch applies
+    pub fn profile_id_at(&self, time: &NaiveDateTime) -> Uuid {
+        let started = self.starts_at.map_or(true, |t| t <= *time);
+        let ended = self.ends_at.map_or(false, |t| t <= *time);
+        match self.fallback_profile_id {
+            Some(fallback_profile_id) if !started || ended => fallback_profile_id,
+            _ => self.profile_id,
+        }
+    }
+}
+
 #[derive(EnumString, Display, Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ActionType {
     ON,
@@ -302,6 +674,10 @@ pub struct TempAction {
     pub action_type: TempActionType,
     pub starts_at: Option<NaiveDateTime>,
     pub expires_at: NaiveDateTime,
+    pub priority: i32,
+    // UTC, only used to order actions with equal priority
+    pub created_at: NaiveDateTime,
+    pub created_by: TempActionSource,
 }
 
 impl TempAction {
@@ -317,8 +693,31 @@ impl TempAction {
             action_type: *action_type,
             starts_at: *starts_at,
             expires_at: *expires_at,
+            priority: 0,
+            created_at: created_at_now(),
+            created_by: TempActionSource::API,
         }
     }
+
+    pub fn is_active_at(&self, time: &NaiveDateTime) -> bool {
+        self.starts_at.map_or(true, |t| t <= *time) && *time <= self.expires_at
+    }
+
+    // Highest priority wins, and the latest created action wins at equal priority
+    pub fn effective_for_room<'a>(
+        actions: impl IntoIterator<Item = &'a TempAction>,
+        room_id: &Uuid,
+    ) -> Option<&'a TempAction> {
+        actions
+            .into_iter()
+            .filter(|a| a.room_ids.contains(room_id))
+            .max_by_key(|a| (a.priority, a.created_at))
+    }
+}
+
+// Truncated to what the database stores, so stored actions compare equal
+fn created_at_now() -> NaiveDateTime {
+    Utc::now().naive_utc().trunc_subsecs(6)
 }
 
 #[derive(Deserialize)]
@@ -328,6 +727,8 @@ pub struct TempActionRequest {
     pub temp: Option<f64>,
     pub starts_at: Option<NaiveDateTime>,
     pub expires_at: NaiveDateTime,
+    #[serde(default)]
+    pub priority: i32,
 }
 
 #[derive(Serialize)]
@@ -338,14 +739,13 @@ pub struct TempActionResponse {
     pub temp: Option<f64>,
     pub starts_at: Option<NaiveDateTime>,
     pub expires_at: NaiveDateTime,
+    pub priority: i32,
+    pub created_by: TempActionSource,
 }
 
 impl From<TempAction> for TempActionResponse {
     fn from(domain: TempAction) -> Self {
-        let (action_type, temp) = match domain.action_type {
-            TempActionType::ON(t) => (ActionType::ON, t),
-            TempActionType::OFF => (ActionType::OFF, None),
-        };
+        let (action_type, temp) = domain.action_type.to_parts();
         Self {
             id: domain.id,
             room_ids: domain.room_ids,
@@ -353,22 +753,66 @@ impl From<TempAction> for TempActionResponse {
             temp,
             starts_at: domain.starts_at,
             expires_at: domain.expires_at,
+            priority: domain.priority,
+            created_by: domain.created_by,
         }
     }
 }
 
 impl From<TempActionRequest> for TempAction {
     fn from(request: TempActionRequest) -> Self {
-        let action_type = match request.action {
-            ActionType::ON => TempActionType::ON(request.temp),
-            ActionType::OFF => TempActionType::OFF,
-        };
-        TempAction::new(
+        let action_type = TempActionType::new(&request.action, request.temp);
+        let mut action = TempAction::new(
             &request.starts_at,
             &request.expires_at,
             &action_type,
             request.room_ids,
-        )
+        );
+        action.priority = request.priority;
+        action
+    }
+}
+
+#[derive(
+    EnumString, Display, Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Default,
+)]
+// Set by the server, requests through the API always create API actions
+pub enum TempActionSource {
+    #[default]
+    API,
+    // Occurrences of recurring actions
+    AUTOMATION,
+}
+
+#[derive(EnumString, Display, Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TempActionEndReason {
+    EXPIRED,
+    CANCELLED,
+    REPLACED,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedTempAction {
+    pub temp_action: TempAction,
+    pub ended_at: NaiveDateTime,
+    pub end_reason: TempActionEndReason,
+}
+
+#[derive(Serialize)]
+pub struct TempActionHistoryResponse {
+    #[serde(flatten)]
+    pub temp_action: TempActionResponse,
+    pub ended_at: NaiveDateTime,
+    pub end_reason: TempActionEndReason,
+}
+
+impl From<ArchivedTempAction> for TempActionHistoryResponse {
+    fn from(domain: ArchivedTempAction) -> Self {
+        Self {
+            temp_action: domain.temp_action.into(),
+            ended_at: domain.ended_at,
+            end_reason: domain.end_reason,
+        }
     }
 }
 
@@ -378,6 +822,223 @@ pub enum TempActionType {
     OFF,
 }
 
+impl TempActionType {
+    pub fn new(action: &ActionType, temp: Option<f64>) -> Self {
+        match action {
+            ActionType::ON => TempActionType::ON(temp),
+            ActionType::OFF => TempActionType::OFF,
+        }
+    }
+
+    fn to_parts(self) -> (ActionType, Option<f64>) {
+        match self {
+            TempActionType::ON(t) => (ActionType::ON, t),
+            TempActionType::OFF => (ActionType::OFF, None),
+        }
+    }
+}
+
+// Forces single plugs ON or OFF, regardless of what is decided for their room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlugOverride {
+    pub id: Uuid,
+    pub plug_ids: Vec<Uuid>,
+    pub action: ActionType,
+    pub starts_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+}
+
+impl PlugOverride {
+    pub fn new(
+        starts_at: &Option<NaiveDateTime>,
+        expires_at: &NaiveDateTime,
+        action: &ActionType,
+        plug_ids: Vec<Uuid>,
+    ) -> Result<Self, anyhow::Error> {
+        if plug_ids.is_empty() {
+            return Err(anyhow!("Plug override must include at least one plug."));
+        }
+        if starts_at.map_or(false, |t| t >= *expires_at) {
+            return Err(anyhow!("Plug override must start before it expires."));
+        }
+        Ok(Self {
+            id: Uuid::new_v4(),
+            plug_ids,
+            action: *action,
+            starts_at: *starts_at,
+            expires_at: *expires_at,
+        })
+    }
+
+    pub fn is_active_at(&self, time: &NaiveDateTime) -> bool {
+        self.starts_at.map_or(true, |t| t <= *time) && *time <= self.expires_at
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PlugOverrideRequest {
+    pub plug_ids: Vec<Uuid>,
+    pub action: ActionType,
+    pub starts_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+}
+
+impl TryFrom<PlugOverrideRequest> for PlugOverride {
+    type Error = anyhow::Error;
+
+    fn try_from(request: PlugOverrideRequest) -> Result<Self, Self::Error> {
+        PlugOverride::new(
+            &request.starts_at,
+            &request.expires_at,
+            &request.action,
+            request.plug_ids,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurringTempAction {
+    pub id: Uuid,
+    pub room_ids: Vec<Uuid>,
+    pub action_type: TempActionType,
+    pub days: Vec<Weekday>,
+    pub from_time: NaiveTime,
+    // A window ending at or before from_time ends on the following day
+    pub to_time: NaiveTime,
+    pub ends_on: Option<NaiveDate>,
+    pub skipped_dates: Vec<NaiveDate>,
+    pub priority: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecurringTempAction {
+    pub fn new(
+        room_ids: Vec<Uuid>,
+        action_type: &TempActionType,
+        days: Vec<Weekday>,
+        time_window: (NaiveTime, NaiveTime),
+        ends_on: Option<NaiveDate>,
+    ) -> Result<Self, anyhow::Error> {
+        if room_ids.is_empty() || days.is_empty() || time_window.0 == time_window.1 {
+            return Err(anyhow!(
+                "Recurring temp action must include one room, one day and a non-empty time window."
+            ));
+        }
+        Ok(Self {
+            id: Uuid::new_v4(),
+            room_ids,
+            action_type: *action_type,
+            days,
+            from_time: time_window.0,
+            to_time: time_window.1,
+            ends_on,
+            skipped_dates: vec![],
+            priority: 0,
+            created_at: created_at_now(),
+        })
+    }
+
+    // The occurrence is identified by the date it starts on
+    pub fn occurrence_at(&self, time: &NaiveDateTime) -> Option<TempAction> {
+        [time.date(), time.date() - Duration::days(1)]
+            .into_iter()
+            .filter(|date| {
+                self.days.contains(&date.weekday())
+                    && !self.skipped_dates.contains(date)
+                    && self.ends_on.map_or(true, |ends_on| *date <= ends_on)
+            })
+            .map(|date| self.occurrence(&date))
+            .find(|occurrence| occurrence.is_active_at(time))
+    }
+
+    pub fn has_ended(&self, date: &NaiveDate) -> bool {
+        self.ends_on
+            .map_or(false, |ends_on| ends_on < *date - Duration::days(1))
+    }
+
+    fn occurrence(&self, date: &NaiveDate) -> TempAction {
+        let end_date = if self.to_time <= self.from_time {
+            *date + Duration::days(1)
+        } else {
+            *date
+        };
+        TempAction {
+            id: self.id,
+            room_ids: self.room_ids.clone(),
+            action_type: self.action_type,
+            starts_at: Some(NaiveDateTime::new(*date, self.from_time)),
+            expires_at: NaiveDateTime::new(end_date, self.to_time),
+            priority: self.priority,
+            created_at: self.created_at,
+            created_by: TempActionSource::AUTOMATION,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RecurringTempActionRequest {
+    pub room_ids: Vec<Uuid>,
+    pub action: ActionType,
+    pub temp: Option<f64>,
+    pub days: Vec<Weekday>,
+    pub from_time: NaiveTime,
+    pub to_time: NaiveTime,
+    pub ends_on: Option<NaiveDate>,
+    #[serde(default)]
+    pub skipped_dates: Vec<NaiveDate>,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl TryFrom<RecurringTempActionRequest> for RecurringTempAction {
+    type Error = anyhow::Error;
+
+    fn try_from(request: RecurringTempActionRequest) -> Result<Self, Self::Error> {
+        let mut action = RecurringTempAction::new(
+            request.room_ids,
+            &TempActionType::new(&request.action, request.temp),
+            request.days,
+            (request.from_time, request.to_time),
+            request.ends_on,
+        )?;
+        action.skipped_dates = request.skipped_dates;
+        action.priority = request.priority;
+        Ok(action)
+    }
+}
+
+#[derive(Serialize)]
+pub struct RecurringTempActionResponse {
+    pub id: Uuid,
+    pub room_ids: Vec<Uuid>,
+    pub action: ActionType,
+    pub temp: Option<f64>,
+    pub days: Vec<Weekday>,
+    pub from_time: NaiveTime,
+    pub to_time: NaiveTime,
+    pub ends_on: Option<NaiveDate>,
+    pub skipped_dates: Vec<NaiveDate>,
+    pub priority: i32,
+}
+
+impl From<RecurringTempAction> for RecurringTempActionResponse {
+    fn from(domain: RecurringTempAction) -> Self {
+        let (action, temp) = domain.action_type.to_parts();
+        Self {
+            id: domain.id,
+            room_ids: domain.room_ids,
+            action,
+            temp,
+            days: domain.days,
+            from_time: domain.from_time,
+            to_time: domain.to_time,
+            ends_on: domain.ends_on,
+            skipped_dates: domain.skipped_dates,
+            priority: domain.priority,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TemperatureLog {
     pub room_id: Uuid,
@@ -393,7 +1054,7 @@ pub enum WorkMessage {
     BUTTON(Uuid, ActionType, u8),
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct NotificationSettings {
     #[serde(skip_serializing)]
     #[serde(default)]
@@ -401,17 +1062,36 @@ pub struct NotificationSettings {
     pub max_consumption: Option<i32>,
     pub max_consumption_timeout_minutes: i32,
     pub ntfy_topic: String,
+    // Tomorrow's price summary is sent after this time, once the prices are stored
+    #[serde(default)]
+    pub price_summary_time: Option<NaiveTime>,
+    // Length of the cheapest and most expensive blocks in the summary
+    #[serde(default = "default_price_summary_block_hours")]
+    pub price_summary_block_hours: i32,
+    // Prices above the max are flagged like VeryExpensive hours, and prices below the min are flagged as cheap
+    #[serde(default)]
+    pub price_alert_max: Option<f64>,
+    #[serde(default)]
+    pub price_alert_min: f64,
+}
+
+fn default_price_summary_block_hours() -> i32 {
+    3
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use chrono::{NaiveTime, Weekday};
+    use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
     use strum::IntoEnumIterator;
     use uuid::Uuid;
 
-    use crate::domain::{PriceLevel, Schedule};
+    use crate::domain::{
+        ActionType, ActiveScheduleProfile, ConsumptionPeriod, GridTariff, MeterReading, Plug,
+        PlugEnergyReading, PlugOverride, PriceInfo, PriceLevel, PriceSettings, RecurringTempAction,
+        Schedule, TempAction, TempActionType,
+    };
 
     fn schedule() -> Schedule {
         Schedule::new(
@@ -422,10 +1102,24 @@ mod tests {
             vec![Weekday::Mon],
             vec![(NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(1, 0, 0))],
             vec![Uuid::new_v4()],
+            Uuid::new_v4(),
         )
         .expect("Failed to create new schedule")
     }
 
+    #[test]
+    fn schedule_is_active_within_time_window() {
+        let monday = NaiveDate::from_weekday_of_month(2023, 7, Weekday::Mon, 1);
+        let sched = schedule();
+        assert!(sched.is_active_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(0, 0, 0))));
+        assert!(sched.is_active_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(0, 59, 0))));
+        assert!(!sched.is_active_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(1, 0, 0))));
+        assert!(!sched.is_active_at(&NaiveDateTime::new(
+            monday.succ(),
+            NaiveTime::from_hms(0, 30, 0)
+        )));
+    }
+
     #[test]
     fn finds_correct_temp_when_match_exists() {
         assert_eq!(schedule().get_temp(&PriceLevel::VeryCheap), 21.0);
@@ -443,6 +1137,7 @@ mod tests {
             vec![Weekday::Mon],
             vec![(NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(1, 0, 0))],
             vec![Uuid::new_v4()],
+            Uuid::new_v4(),
         )
         .expect("Failed to create new schedule");
         assert_eq!(sched.get_temp(&PriceLevel::Normal), 18.3);
@@ -459,6 +1154,7 @@ mod tests {
             vec![Weekday::Mon],
             vec![(NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(1, 0, 0))],
             vec![Uuid::new_v4()],
+            Uuid::new_v4(),
         )
         .expect("Failed to create new schedule");
         assert_eq!(sched.get_temp(&PriceLevel::VeryExpensive), 15.0);
@@ -475,6 +1171,7 @@ mod tests {
             vec![Weekday::Mon],
             vec![(NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(1, 0, 0))],
             vec![Uuid::new_v4()],
+            Uuid::new_v4(),
         )
         .expect("Failed to create new schedule");
         assert_eq!(sched.get_temp(&PriceLevel::VeryCheap), 25.0);
@@ -487,8 +1184,381 @@ mod tests {
             vec![Weekday::Mon],
             vec![(NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(1, 0, 0))],
             vec![Uuid::new_v4()],
+            Uuid::new_v4(),
         )
         .expect("Failed to create new schedule");
         assert_eq!(sched.get_temp(&PriceLevel::VeryCheap), 25.0);
     }
+
+    fn time(hour: u32) -> NaiveDateTime {
+        NaiveDateTime::new(
+            NaiveDate::from_ymd(2023, 7, 1),
+            NaiveTime::from_hms(hour, 0, 0),
+        )
+    }
+
+    #[test]
+    fn active_profile_without_range_is_always_active() {
+        let profile_id = Uuid::new_v4();
+        let active = ActiveScheduleProfile {
+            profile_id,
+            starts_at: None,
+            ends_at: None,
+            fallback_profile_id: None,
+        };
+        assert_eq!(active.profile_id_at(&time(0)), profile_id);
+        assert_eq!(active.profile_id_at(&time(23)), profile_id);
+    }
+
+    #[test]
+    fn active_profile_falls_back_outside_range() {
+        let profile_id = Uuid::new_v4();
+        let fallback_profile_id = Uuid::new_v4();
+        let active = ActiveScheduleProfile {
+            profile_id,
+            starts_at: Some(time(8)),
+            ends_at: Some(time(16)),
+            fallback_profile_id: Some(fallback_profile_id),
+        };
+        assert_eq!(active.profile_id_at(&time(7)), fallback_profile_id);
+        assert_eq!(active.profile_id_at(&time(8)), profile_id);
+        assert_eq!(active.profile_id_at(&time(15)), profile_id);
+        assert_eq!(active.profile_id_at(&time(16)), fallback_profile_id);
+    }
+
+    fn recurring_temp_action() -> RecurringTempAction {
+        RecurringTempAction::new(
+            vec![Uuid::new_v4()],
+            &TempActionType::OFF,
+            vec![Weekday::Mon, Weekday::Tue],
+            (NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(6, 0, 0)),
+            None,
+        )
+        .expect("Failed to create recurring temp action")
+    }
+
+    #[test]
+    fn recurring_temp_action_occurs_on_matching_days() {
+        let monday = NaiveDate::from_weekday_of_month(2023, 7, Weekday::Mon, 1);
+        let action = recurring_temp_action();
+
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(21, 0, 0)))
+            .is_none());
+        let occurrence = action
+            .occurrence_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(23, 0, 0)))
+            .expect("Missing occurrence");
+        assert_eq!(
+            occurrence.expires_at,
+            NaiveDateTime::new(monday.succ(), NaiveTime::from_hms(6, 0, 0))
+        );
+        // The window starting on Tuesday runs into Wednesday morning
+        let wednesday = monday.succ().succ();
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(wednesday, NaiveTime::from_hms(5, 0, 0)))
+            .is_some());
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(
+                wednesday,
+                NaiveTime::from_hms(23, 0, 0)
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn recurring_temp_action_respects_skipped_dates_and_end() {
+        let monday = NaiveDate::from_weekday_of_month(2023, 7, Weekday::Mon, 1);
+        let mut action = recurring_temp_action();
+        action.skipped_dates = vec![monday];
+        action.ends_on = Some(monday.succ());
+
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(monday, NaiveTime::from_hms(23, 0, 0)))
+            .is_none());
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(
+                monday.succ(),
+                NaiveTime::from_hms(23, 0, 0)
+            ))
+            .is_some());
+        assert!(!action.has_ended(&monday.succ().succ()));
+        assert!(action
+            .occurrence_at(&NaiveDateTime::new(
+                monday + chrono::Duration::days(7),
+                NaiveTime::from_hms(23, 0, 0)
+            ))
+            .is_none());
+        assert!(action.has_ended(&(monday + chrono::Duration::days(7))));
+    }
+
+    #[test]
+    fn plug_override_requires_plugs_and_valid_range() {
+        let expires_at = time(12);
+        assert!(PlugOverride::new(&None, &expires_at, &ActionType::OFF, vec![]).is_err());
+        assert!(PlugOverride::new(
+            &Some(time(13)),
+            &expires_at,
+            &ActionType::OFF,
+            vec![Uuid::new_v4()]
+        )
+        .is_err());
+        let plug_override = PlugOverride::new(
+            &Some(time(10)),
+            &expires_at,
+            &ActionType::ON,
+            vec![Uuid::new_v4()],
+        )
+        .expect("Failed to create plug override");
+        assert!(!plug_override.is_active_at(&time(9)));
+        assert!(plug_override.is_active_at(&time(11)));
+    }
+
+    #[test]
+    fn effective_temp_action_uses_priority_then_creation_time() {
+        let room_id = Uuid::new_v4();
+        let mut first = TempAction::new(&None, &time(12), &TempActionType::ON(None), vec![room_id]);
+        let mut second = TempAction::new(&None, &time(14), &TempActionType::OFF, vec![room_id]);
+        second.created_at = first.created_at + chrono::Duration::seconds(1);
+        let other_room = TempAction::new(
+            &None,
+            &time(10),
+            &TempActionType::ON(Some(25.0)),
+            vec![Uuid::new_v4()],
+        );
+
+        let actions = vec![first.clone(), second.clone(), other_room];
+        assert_eq!(
+            TempAction::effective_for_room(&actions, &room_id),
+            Some(&second)
+        );
+
+        first.priority = 1;
+        let actions = vec![first.clone(), second.clone()];
+        assert_eq!(
+            TempAction::effective_for_room(&actions, &room_id),
+            Some(&first)
+        );
+
+        second.priority = 1;
+        let actions = vec![first, second.clone()];
+        assert_eq!(
+            TempAction::effective_for_room(&actions, &room_id),
+            Some(&second)
+        );
+        assert_eq!(
+            TempAction::effective_for_room(&actions, &Uuid::new_v4()),
+            None
+        );
+    }
+
+    #[test]
+    fn grid_tariff_matches_months_days_and_night_window() {
+        let night = GridTariff::new(
+            "night",
+            0.3,
+            vec![],
+            vec![],
+            (NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(6, 0, 0)),
+        )
+        .expect("Failed to create tariff");
+        let summer_weekday = GridTariff::new(
+            "summer day",
+            0.4,
+            vec![6, 7, 8],
+            vec![Weekday::Sat],
+            (NaiveTime::from_hms(6, 0, 0), NaiveTime::from_hms(22, 0, 0)),
+        )
+        .expect("Failed to create tariff");
+        let tariffs = vec![night, summer_weekday];
+
+        assert_eq!(GridTariff::amount_at(&tariffs, &time(23)), 0.3);
+        assert_eq!(GridTariff::amount_at(&tariffs, &time(3)), 0.3);
+        assert_eq!(GridTariff::amount_at(&tariffs, &time(12)), 0.4);
+        assert_eq!(
+            GridTariff::amount_at(&tariffs, &(time(12) + chrono::Duration::days(1))),
+            0.0
+        );
+        assert!(GridTariff::new(
+            "invalid",
+            0.1,
+            vec![13],
+            vec![],
+            (time(0).time(), time(0).time())
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn total_price_includes_tariff_tax_and_vat() {
+        let settings = PriceSettings {
+            electricity_tax: 0.25,
+            vat_percent: 25.0,
+            use_total_price: true,
+            subsidy_threshold: None,
+            subsidy_percent: 90.0,
+            fallback_price_level: None,
+            negative_price_mode: false,
+            negative_price_threshold: 0.0,
+        };
+        assert_eq!(settings.total_price(0.5, 0.25), 1.25);
+    }
+
+    #[test]
+    fn subsidy_covers_share_above_threshold() {
+        let mut settings = PriceSettings {
+            subsidy_threshold: Some(1.0),
+            subsidy_percent: 50.0,
+            ..Default::default()
+        };
+        assert_eq!(settings.subsidy(0.5), Some(0.0));
+        assert_eq!(settings.subsidy(3.0), Some(1.0));
+
+        settings.subsidy_threshold = None;
+        assert_eq!(settings.subsidy(3.0), None);
+    }
+
+    #[test]
+    fn cost_price_deducts_subsidy_with_vat_from_total_price() {
+        let mut settings = PriceSettings {
+            vat_percent: 25.0,
+            use_total_price: true,
+            subsidy_threshold: Some(1.0),
+            subsidy_percent: 50.0,
+            ..Default::default()
+        };
+        let price = PriceInfo {
+            amount: 3.0,
+            total: Some(settings.total_price(3.0, 0.0)),
+            subsidy: settings.subsidy(3.0),
+            currency: "NOK".to_string(),
+            ext_price_level: None,
+            price_level: None,
+            starts_at: NaiveDate::from_ymd(2023, 1, 2).and_hms(3, 0, 0),
+            duration_minutes: 60,
+        };
+
+        assert_eq!(price.total, Some(3.75));
+        assert_eq!(price.cost_price(Some(&settings)), 2.5);
+
+        settings.use_total_price = false;
+        assert_eq!(price.cost_price(Some(&settings)), 2.0);
+        assert_eq!(price.cost_price(None), 2.0);
+    }
+
+    #[test]
+    fn consumption_periods_start_on_first_day() {
+        let date = NaiveDate::from_ymd(2023, 12, 14);
+
+        assert_eq!(ConsumptionPeriod::Day.start_of(&date), date);
+        assert_eq!(
+            ConsumptionPeriod::Day.next(&date),
+            NaiveDate::from_ymd(2023, 12, 15)
+        );
+        assert_eq!(
+            ConsumptionPeriod::Week.start_of(&date),
+            NaiveDate::from_ymd(2023, 12, 11)
+        );
+        assert_eq!(
+            ConsumptionPeriod::Week.next(&date),
+            NaiveDate::from_ymd(2023, 12, 18)
+        );
+        assert_eq!(
+            ConsumptionPeriod::Month.start_of(&date),
+            NaiveDate::from_ymd(2023, 12, 1)
+        );
+        assert_eq!(
+            ConsumptionPeriod::Month.next(&date),
+            NaiveDate::from_ymd(2024, 1, 1)
+        );
+    }
+
+    #[test]
+    fn plug_energy_handles_counter_resets() {
+        let plug = Plug::new("heater", "127.0.0.1", "", "", &Uuid::new_v4(), &true).unwrap();
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let reading = |counter: i64, previous: Option<&PlugEnergyReading>| {
+            let meter = previous
+                .and_then(|previous| previous.counter)
+                .map(|counter| MeterReading {
+                    counter,
+                    energy_wh_since: 0.0,
+                });
+            PlugEnergyReading::new(
+                &plug,
+                &time,
+                Some(counter),
+                Some(1000.0),
+                None,
+                previous,
+                meter.as_ref(),
+            )
+        };
+        let first = reading(6000, None);
+        assert_eq!(first.energy_wh, 0.0);
+
+        let second = reading(9000, Some(&first));
+        assert_eq!(second.energy_wh, 50.0);
+        assert_eq!(second.since, Some(time));
+
+        let restarted = reading(1200, Some(&second));
+        assert_eq!(restarted.energy_wh, 20.0);
+        assert!(!restarted.estimated);
+    }
+
+    #[test]
+    fn plug_energy_is_estimated_without_meter() {
+        let plug = Plug {
+            rated_watts: Some(1200),
+            ..Plug::new("heater", "127.0.0.1", "", "", &Uuid::new_v4(), &true).unwrap()
+        };
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let later = time + Duration::minutes(15);
+        let on = PlugEnergyReading::new(&plug, &time, None, None, Some(true), None, None);
+        let off = PlugEnergyReading::new(&plug, &later, None, None, Some(false), Some(&on), None);
+        assert_eq!(off.energy_wh, 300.0);
+        assert!(off.estimated);
+
+        let still_off =
+            PlugEnergyReading::new(&plug, &later, None, None, Some(false), Some(&off), None);
+        assert_eq!(still_off.energy_wh, 0.0);
+        assert!(!still_off.estimated);
+    }
+
+    #[test]
+    fn plug_energy_is_metered_after_failed_read() {
+        let plug = Plug {
+            rated_watts: Some(1200),
+            ..Plug::new("heater", "127.0.0.1", "", "", &Uuid::new_v4(), &true).unwrap()
+        };
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let metered =
+            PlugEnergyReading::new(&plug, &time, Some(6000), None, Some(true), None, None);
+        // 100 Wh estimated while the meter could not be read
+        let failed = PlugEnergyReading::new(
+            &plug,
+            &(time + Duration::minutes(5)),
+            None,
+            None,
+            Some(true),
+            Some(&metered),
+            None,
+        );
+        assert_eq!(failed.energy_wh, 100.0);
+
+        let meter = MeterReading {
+            counter: 6000,
+            energy_wh_since: failed.energy_wh,
+        };
+        let next = PlugEnergyReading::new(
+            &plug,
+            &(time + Duration::minutes(10)),
+            Some(18000),
+            None,
+            Some(true),
+            Some(&failed),
+            Some(&meter),
+        );
+        assert_eq!(next.energy_wh, 100.0);
+        assert!(!next.estimated);
+    }
 }
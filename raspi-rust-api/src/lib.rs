@@ -21,10 +21,25 @@ pub fn env_var(name: &str) -> String {
 }
 
 pub fn now() -> NaiveDateTime {
-    let utc = Utc::now().naive_utc();
-    let tz: Tz = env::var("TIME_ZONE")
+    to_local(&Utc::now().naive_utc())
+}
+
+fn time_zone() -> Tz {
+    env::var("TIME_ZONE")
         .expect("Missing TIME_ZONE env var")
         .parse()
-        .expect("Failed to parse timezone");
-    tz.from_utc_datetime(&utc).naive_local()
+        .expect("Failed to parse timezone")
+}
+
+pub fn to_local(utc: &NaiveDateTime) -> NaiveDateTime {
+    time_zone().from_utc_datetime(utc).naive_local()
+}
+
+// Picks the first of two candidates when the clock is turned back
+pub fn to_utc(local: &NaiveDateTime) -> NaiveDateTime {
+    time_zone()
+        .from_local_datetime(local)
+        .earliest()
+        .map(|time| time.naive_utc())
+        .unwrap_or(*local)
 }
@@ -5,16 +5,21 @@ use tokio::sync::{mpsc, RwLock};
 
 use rust_home::api::serve_app;
 use rust_home::clients::mqtt::MqttClient;
-use rust_home::clients::{
-    shelly_client::ShellyClient, tibber_client::TibberClient, tibber_subscriber::TibberSubscriber,
+use rust_home::clients::price_provider::{
+    price_provider_from_settings, tibber_client_from_settings,
 };
+use rust_home::clients::{shelly_client::ShellyClient, tibber_subscriber::TibberSubscriber};
 use rust_home::db::DbConfig;
 use rust_home::domain::WorkMessage;
 use rust_home::service::consumption_cache::ConsumptionCache;
+use rust_home::service::consumption_history;
+use rust_home::service::hourly_consumption;
+use rust_home::service::live_events::{self, LiveEvents};
 use rust_home::service::notifications::{NotificationHandler, NotificationMessage};
-use rust_home::{
-    api, configuration::get_configuration, cron_scheduler, env_var, work_handler::WorkHandler,
-};
+use rust_home::service::plug_energy;
+use rust_home::service::price_alerts;
+use rust_home::service::price_fetching::PriceFetchStatus;
+use rust_home::{api, configuration::get_configuration, cron_scheduler, work_handler::WorkHandler};
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -26,23 +31,34 @@ async fn main() -> std::io::Result<()> {
         .expect("Failed to connect to database");
     let pool = Arc::new(db_config.pool.clone());
 
-    let tibber_client = Arc::new(TibberClient::new(env_var("TIBBER_API_TOKEN")));
+    let tibber_client = Arc::new(tibber_client_from_settings(&configuration.price_providers));
+    let price_provider = price_provider_from_settings(&configuration.price_providers);
     let shelly_client = Arc::new(ShellyClient::default());
 
+    let live_events = LiveEvents::default();
     let (notification_tx, notification_rx) = mpsc::channel::<NotificationMessage>(10);
     let (work_message_tx, work_message_rx) = mpsc::channel::<WorkMessage>(10);
 
-    let consumption_cache = Arc::new(RwLock::new(ConsumptionCache::new(notification_tx)));
+    let price_alerts_pool = pool.clone();
+    let price_alerts_tx = notification_tx.clone();
+    tokio::spawn(async { price_alerts::start(price_alerts_pool, price_alerts_tx).await });
+
+    let consumption_cache = Arc::new(RwLock::new(ConsumptionCache::new(
+        notification_tx,
+        &configuration.consumption_cache,
+    )));
     let work_handler = WorkHandler::new(
         shelly_client.clone(),
-        tibber_client.clone(),
+        price_provider.clone(),
         work_message_tx.clone(),
         work_message_rx,
         pool.clone(),
+        live_events.clone(),
     );
     tokio::spawn(async { work_handler.start().await });
 
-    let notification_handler = NotificationHandler::new(notification_rx, pool.clone());
+    let notification_handler =
+        NotificationHandler::new(notification_rx, pool.clone(), live_events.clone());
     tokio::spawn(async { notification_handler.start().await });
 
     if configuration.mqtt.run_mqtt {
@@ -54,9 +70,43 @@ async fn main() -> std::io::Result<()> {
         info!("Not running MQTT, disabled in config")
     }
 
-    let cron_tibber_client = tibber_client.clone();
+    let fetch_status = Arc::new(RwLock::new(PriceFetchStatus::default()));
+    let cron_price_provider = price_provider.clone();
+    let cron_fetch_status = fetch_status.clone();
     let cron_pool = pool.clone();
-    tokio::spawn(async { cron_scheduler::start(cron_tibber_client, cron_pool).await });
+    let cron_events = live_events.clone();
+    let price_level_strategy = configuration.price_level_strategy.clone();
+    tokio::spawn(async {
+        cron_scheduler::start(
+            cron_price_provider,
+            price_level_strategy,
+            cron_fetch_status,
+            cron_pool,
+            cron_events,
+        )
+        .await
+    });
+
+    let history_pool = pool.clone();
+    let history_cache = consumption_cache.clone();
+    let history_settings = configuration.consumption_history.clone();
+    tokio::spawn(async {
+        consumption_history::start(history_pool, history_cache, history_settings).await
+    });
+
+    let plug_energy_pool = pool.clone();
+    let plug_energy_shelly = shelly_client.clone();
+    let plug_energy_events = live_events.clone();
+    tokio::spawn(async {
+        plug_energy::start(plug_energy_pool, plug_energy_shelly, plug_energy_events).await
+    });
+
+    let consumption_pool = pool.clone();
+    tokio::spawn(async { hourly_consumption::start(consumption_pool, tibber_client).await });
+
+    let power_cache = consumption_cache.clone();
+    let power_events = live_events.clone();
+    tokio::spawn(async { live_events::forward_live_power(power_cache, power_events).await });
 
     let subscriber_cache = consumption_cache.clone();
     if configuration.run_live_consumption_subscriber {
@@ -67,9 +117,11 @@ async fn main() -> std::io::Result<()> {
 
     let server = api::start(
         work_message_tx,
-        tibber_client,
+        price_provider,
         shelly_client,
         consumption_cache.clone(),
+        fetch_status,
+        live_events,
         pool,
     )
     .await;
@@ -1,8 +1,13 @@
 pub mod buttons;
+pub mod events;
 pub mod notification_settings;
+pub mod plug_overrides;
 pub mod plugs;
+pub mod price_settings;
 pub mod prices;
+pub mod reports;
 pub mod rooms;
+pub mod schedule_profiles;
 pub mod schedules;
 pub mod temp_actions;
 pub mod temp_sensors;
@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_stream::stream;
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Router};
+use futures::stream::Stream;
+use log::warn;
+use serde::Deserialize;
+use serde_json::to_string;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::routes::lib::error_response;
+use crate::service::live_events::{EventFilter, LiveEventType, LiveEvents};
+
+pub fn events_router(live_events: LiveEvents) -> Router {
+    Router::new()
+        .route("/", get(event_stream))
+        .layer(Extension(live_events))
+}
+
+#[derive(Deserialize)]
+pub struct EventParams {
+    // Comma separated, like temperature,room_decision
+    types: Option<String>,
+    room_id: Option<Uuid>,
+}
+
+impl EventParams {
+    fn filter(&self) -> Result<EventFilter, String> {
+        let types = match &self.types {
+            Some(types) => types
+                .split(',')
+                .map(|event_type| {
+                    LiveEventType::from_str(event_type.trim())
+                        .map_err(|_| format!("Unknown event type: {}", event_type))
+                })
+                .collect::<Result<Vec<LiveEventType>, String>>()?,
+            None => vec![],
+        };
+        Ok(EventFilter {
+            types,
+            room_id: self.room_id,
+        })
+    }
+}
+
+// Each event is named by its type, with the event as JSON data
+async fn event_stream(
+    Extension(live_events): Extension<LiveEvents>,
+    Query(params): Query<EventParams>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, Response> {
+    let filter = params
+        .filter()
+        .map_err(|e| error_response(e, StatusCode::BAD_REQUEST).into_response())?;
+    let mut receiver = live_events.subscribe();
+    Ok(Sse::new(stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if filter.matches(&event) => yield Ok(SseEvent::default()
+                    .event(event.event_type().to_string())
+                    .data(to_string(&event).unwrap())),
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Live event stream skipped {} events", skipped)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+    .keep_alive(KeepAlive::default().interval(Duration::from_secs(15))))
+}
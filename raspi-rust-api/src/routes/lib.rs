@@ -3,8 +3,12 @@ use std::error::Error;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use chrono::NaiveDate;
 use log::error;
 
+use crate::domain::ConsumptionPeriod;
+use crate::now;
+
 struct ErrorType {
     status_code: StatusCode,
     message: String,
@@ -42,3 +46,23 @@ impl IntoResponse for ErrorType {
         (self.status_code, body).into_response()
     }
 }
+
+// Query parameters for summaries of days, weeks or months between two dates
+#[derive(serde::Deserialize)]
+pub struct PeriodParams {
+    period: Option<ConsumptionPeriod>,
+    pub from: NaiveDate,
+    to: Option<NaiveDate>,
+}
+
+impl PeriodParams {
+    // Daily by default
+    pub fn period(&self) -> ConsumptionPeriod {
+        self.period.unwrap_or(ConsumptionPeriod::Day)
+    }
+
+    // Inclusive, today by default
+    pub fn to(&self) -> NaiveDate {
+        self.to.unwrap_or(now().date())
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use log::error;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db;
+use crate::domain::{PlugOverride, PlugOverrideRequest};
+use crate::routes::lib::{error_response, internal_server_error};
+
+pub fn plug_overrides_router(pool: Arc<PgPool>) -> Router {
+    Router::new()
+        .route("/", get(get_plug_overrides).post(create_plug_override))
+        .route(
+            "/:id",
+            post(update_plug_override).delete(delete_plug_override),
+        )
+        .layer(Extension(pool))
+}
+
+async fn get_plug_overrides(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::plug_overrides::get_plug_overrides(&pool)
+        .await
+        .map(|overrides| (StatusCode::OK, Json(overrides)))
+        .map_err(internal_server_error)
+}
+
+async fn create_plug_override(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<PlugOverrideRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let new_override: PlugOverride = match body.try_into() {
+        Ok(plug_override) => plug_override,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to create plug override: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    db::plug_overrides::create_plug_override(&pool, &new_override)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+async fn update_plug_override(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<PlugOverrideRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let mut updated_override: PlugOverride = match body.try_into() {
+        Ok(plug_override) => plug_override,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to update plug override: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    updated_override.id = id;
+    db::plug_overrides::update_plug_override(&pool, &updated_override)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+async fn delete_plug_override(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    db::plug_overrides::delete_plug_override(&pool, &id)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(internal_server_error)
+}
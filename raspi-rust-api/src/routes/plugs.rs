@@ -1,7 +1,7 @@
 use std::str::FromStr;
 use std::sync::Arc;
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::{get, post};
@@ -13,7 +13,7 @@ use uuid::Uuid;
 
 use crate::clients::shelly_client::ShellyClient;
 use crate::domain::Plug;
-use crate::routes::lib::{error_response, internal_server_error};
+use crate::routes::lib::{error_response, internal_server_error, PeriodParams};
 use crate::{db, service};
 
 pub fn plugs_router(pool: Arc<PgPool>, shelly_client: Arc<ShellyClient>) -> Router {
@@ -21,6 +21,7 @@ pub fn plugs_router(pool: Arc<PgPool>, shelly_client: Arc<ShellyClient>) -> Rout
         .route("/", get(get_plugs).post(create_plug))
         .route("/:id", post(update_plug).delete(delete_plug))
         .route("/status", get(get_plug_statuses))
+        .route("/energy", get(get_plug_energy))
         .layer(Extension(pool))
         .layer(Extension(shelly_client))
 }
@@ -41,6 +42,8 @@ pub struct PlugResponse {
     password: String,
     room_id: Uuid,
     scheduled: bool,
+    opportunistic: bool,
+    rated_watts: Option<i32>,
 }
 
 impl Plug {
@@ -53,6 +56,8 @@ impl Plug {
             password: self.password.clone(),
             room_id: self.room_id,
             scheduled: self.scheduled,
+            opportunistic: self.opportunistic,
+            rated_watts: self.rated_watts,
         }
     }
 }
@@ -65,6 +70,10 @@ pub struct PlugRequest {
     password: String,
     room_id: Uuid,
     scheduled: bool,
+    #[serde(default)]
+    opportunistic: bool,
+    #[serde(default)]
+    rated_watts: Option<i32>,
 }
 
 async fn create_plug(
@@ -79,7 +88,11 @@ async fn create_plug(
         &body.room_id,
         &body.scheduled,
     ) {
-        Ok(plug) => plug,
+        Ok(plug) => Plug {
+            opportunistic: body.opportunistic,
+            rated_watts: body.rated_watts,
+            ..plug
+        },
         Err(e) => {
             error!("{}", e);
             return Err(error_response(
@@ -126,6 +139,8 @@ async fn update_plug(
             password: body.password.clone(),
             room_id: body.room_id,
             scheduled: body.scheduled,
+            opportunistic: body.opportunistic,
+            rated_watts: body.rated_watts,
         },
     )
     .await
@@ -170,3 +185,13 @@ async fn get_plug_statuses(
         }
     }
 }
+
+async fn get_plug_energy(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PeriodParams>,
+) -> impl IntoResponse {
+    service::plug_energy::get_plug_energy(&pool, &params.period(), &params.from, &params.to())
+        .await
+        .map(Json)
+        .map_err(internal_server_error)
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use log::error;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db;
+use crate::domain::{GridTariff, GridTariffRequest, PriceSettings};
+use crate::routes::lib::{error_response, internal_server_error};
+
+pub fn price_settings_router(pool: Arc<PgPool>) -> Router {
+    Router::new()
+        .route("/", get(get_settings).post(upsert_settings))
+        .route(
+            "/grid_tariffs",
+            get(get_grid_tariffs).post(create_grid_tariff),
+        )
+        .route(
+            "/grid_tariffs/:id",
+            post(update_grid_tariff).delete(delete_grid_tariff),
+        )
+        .layer(Extension(pool))
+}
+
+async fn get_settings(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::price_settings::get_price_settings(&pool)
+        .await
+        .map(|settings| (StatusCode::OK, Json(settings)))
+        .map_err(internal_server_error)
+}
+
+async fn upsert_settings(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<PriceSettings>,
+) -> impl IntoResponse {
+    db::price_settings::upsert_price_settings(&pool, &body)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(internal_server_error)
+}
+
+async fn get_grid_tariffs(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::price_settings::get_grid_tariffs(&pool)
+        .await
+        .map(|tariffs| (StatusCode::OK, Json(tariffs)))
+        .map_err(internal_server_error)
+}
+
+async fn create_grid_tariff(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<GridTariffRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let new_tariff: GridTariff = match body.try_into() {
+        Ok(tariff) => tariff,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to create grid tariff: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    db::price_settings::create_grid_tariff(&pool, &new_tariff)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+async fn update_grid_tariff(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<GridTariffRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let mut updated_tariff: GridTariff = match body.try_into() {
+        Ok(tariff) => tariff,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to update grid tariff: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    updated_tariff.id = id;
+    db::price_settings::update_grid_tariff(&pool, &updated_tariff)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+async fn delete_grid_tariff(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    db::price_settings::delete_grid_tariff(&pool, &id)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(internal_server_error)
+}
@@ -3,44 +3,119 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use async_stream::stream;
+use axum::extract::Query;
 use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Extension, Json, Router};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
 use futures::stream::Stream;
-use serde::Serialize;
+use log::warn;
+use serde::{Deserialize, Serialize};
 use serde_json::to_string;
 use sqlx::PgPool;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::RwLock;
 
-use crate::clients::tibber_client::TibberClient;
-use crate::domain::{Consumption, LiveConsumption};
-use crate::routes::lib::internal_server_error;
-use crate::service;
+use crate::clients::price_provider::PriceProvider;
+use crate::domain::{Consumption, ConsumptionResolution, LiveConsumption};
+use crate::routes::lib::{internal_server_error, PeriodParams};
 use crate::service::consumption_cache::ConsumptionCache;
+use crate::service::price_fetching::{PriceCoverage, PriceFetchStatus};
+use crate::{db, now, service};
 
 pub fn prices_router(
     pool: Arc<PgPool>,
-    tibber_client: Arc<TibberClient>,
+    price_provider: Arc<dyn PriceProvider>,
     consumption_cache: Arc<RwLock<ConsumptionCache>>,
+    fetch_status: Arc<RwLock<PriceFetchStatus>>,
 ) -> Router {
     Router::new()
         .route("/current", get(get_current_price))
+        .route("/day", get(get_day_prices))
+        .route("/tomorrow", get(get_tomorrow_prices))
+        .route("/range", get(get_price_range))
+        .route("/status", get(get_fetch_status))
         .route("/consumption", get(get_consumption))
+        .route("/consumption/history", get(get_consumption_summaries))
+        .route("/subsidy", get(get_subsidy))
         .route("/live_consumption", get(get_live_consumption))
+        .route("/live_consumption/stats", get(get_live_consumption_stats))
+        .route("/live_consumption/history", get(get_consumption_history))
         .route("/live_consumption_sse", get(consumption_sse))
         .layer(Extension(pool))
-        .layer(Extension(tibber_client))
+        .layer(Extension(price_provider))
         .layer(Extension(consumption_cache))
+        .layer(Extension(fetch_status))
 }
 
 async fn get_current_price(
-    Extension(tibber_client): Extension<Arc<TibberClient>>,
+    Extension(price_provider): Extension<Arc<dyn PriceProvider>>,
     Extension(pool): Extension<Arc<PgPool>>,
 ) -> impl IntoResponse {
-    service::prices::get_current_price(&tibber_client, &pool)
+    Json(service::prices::get_current_price_or_fallback(price_provider.as_ref(), &pool).await)
+}
+
+#[derive(Deserialize)]
+pub struct DayParams {
+    date: Option<NaiveDate>,
+}
+
+// Today's prices by default
+async fn get_day_prices(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<DayParams>,
+) -> impl IntoResponse {
+    let date = params.date.unwrap_or(now().date());
+    price_range(&pool, date, date).await
+}
+
+// Empty until tomorrow's prices are published
+async fn get_tomorrow_prices(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    let tomorrow = now().date() + chrono::Duration::days(1);
+    price_range(&pool, tomorrow, tomorrow).await
+}
+
+#[derive(Deserialize)]
+pub struct PriceRangeParams {
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+}
+
+// Both dates are inclusive, and only the from date is used by default
+async fn get_price_range(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PriceRangeParams>,
+) -> impl IntoResponse {
+    price_range(&pool, params.from, params.to.unwrap_or(params.from)).await
+}
+
+async fn price_range(pool: &PgPool, from: NaiveDate, to: NaiveDate) -> impl IntoResponse {
+    service::prices::get_price_range(
+        pool,
+        &from.and_hms(0, 0, 0),
+        &(to + chrono::Duration::days(1)).and_hms(0, 0, 0),
+    )
+    .await
+    .map(Json)
+    .map_err(internal_server_error)
+}
+
+#[derive(Serialize)]
+struct PriceStatus {
+    #[serde(flatten)]
+    fetch: PriceFetchStatus,
+    coverage: PriceCoverage,
+}
+
+async fn get_fetch_status(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Extension(fetch_status): Extension<Arc<RwLock<PriceFetchStatus>>>,
+) -> impl IntoResponse {
+    let fetch = fetch_status.read().await.clone();
+    service::price_fetching::get_price_coverage(&pool)
         .await
-        .map(Json)
+        .map(|coverage| Json(PriceStatus { fetch, coverage }))
         .map_err(internal_server_error)
 }
 
@@ -48,28 +123,78 @@ async fn get_current_price(
 struct ConsumptionGraphData {
     label: String,
     kwh: Option<f64>,
+    cost: Option<f64>,
 }
 
-impl From<&Consumption> for ConsumptionGraphData {
-    fn from(value: &Consumption) -> Self {
+impl ConsumptionGraphData {
+    fn new(value: &Consumption, cost: Option<f64>) -> Self {
         Self {
             label: value.to.format("%H:%M").to_string(),
             kwh: value.kwh,
+            cost,
         }
     }
 }
 
+// The last 24 hours of stored consumption
 async fn get_consumption(
-    Extension(tibber_client): Extension<Arc<TibberClient>>,
+    Extension(pool): Extension<Arc<PgPool>>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let to = now();
+    let consumption =
+        db::consumption::get_consumption(&pool, &(to - chrono::Duration::hours(24)), &to)
+            .await
+            .map_err(|e| internal_server_error(e).into_response())?;
+    let costs = service::prices::get_consumption_costs(&pool, &consumption)
+        .await
+        .map_err(|e| internal_server_error(e).into_response())?;
+    let json: Vec<ConsumptionGraphData> = consumption
+        .iter()
+        .zip(costs)
+        .map(|(value, cost)| ConsumptionGraphData::new(value, cost))
+        .collect();
+    Ok::<_, Response>(Json(json))
+}
+
+async fn get_consumption_summaries(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PeriodParams>,
 ) -> impl IntoResponse {
-    tibber_client
-        .get_consumption()
+    service::hourly_consumption::get_consumption_summaries(
+        &pool,
+        &params.period(),
+        &params.from,
+        &params.to(),
+    )
+    .await
+    .map(Json)
+    .map_err(internal_server_error)
+}
+
+#[derive(Deserialize)]
+pub struct SubsidyParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+// Both dates are inclusive, and the current month is used by default
+async fn get_subsidy(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<SubsidyParams>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let today = now().date();
+    let from = params
+        .from
+        .unwrap_or(today.with_day(1).unwrap_or(today))
+        .and_hms(0, 0, 0);
+    let to = (params.to.unwrap_or(today) + chrono::Duration::days(1)).and_hms(0, 0, 0);
+    let consumption = db::consumption::get_consumption(&pool, &from, &to)
         .await
-        .map(|consumption| {
-            let json: Vec<ConsumptionGraphData> = consumption.iter().map(|v| v.into()).collect();
-            Json(json)
-        })
-        .map_err(internal_server_error)
+        .map_err(|e| internal_server_error(e).into_response())?;
+    service::prices::get_subsidy(&pool, &consumption, &from, &to)
+        .await
+        .map(Json)
+        .map_err(|e| internal_server_error(e).into_response())
 }
 
 async fn get_live_consumption(
@@ -84,22 +209,71 @@ async fn get_live_consumption(
     Json(res)
 }
 
+async fn get_live_consumption_stats(
+    Extension(consumption_cache): Extension<Arc<RwLock<ConsumptionCache>>>,
+) -> impl IntoResponse {
+    Json(consumption_cache.read().await.get_stats())
+}
+
+#[derive(Deserialize)]
+pub struct ConsumptionHistoryParams {
+    from: NaiveDateTime,
+    to: Option<NaiveDateTime>,
+    resolution: Option<ConsumptionResolution>,
+}
+
+// Until now by default, the resolution depends on the range when not set
+async fn get_consumption_history(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<ConsumptionHistoryParams>,
+) -> impl IntoResponse {
+    service::consumption_history::get_consumption_history(
+        &pool,
+        &params.from,
+        &params.to.unwrap_or(now()),
+        params.resolution,
+    )
+    .await
+    .map(Json)
+    .map_err(internal_server_error)
+}
+
+#[derive(Serialize)]
+struct LiveConsumptionWindow {
+    window_minutes: i64,
+}
+
+// Sends the window and the cached samples first, then each new sample as it arrives
 pub async fn consumption_sse(
     Extension(consumption_cache): Extension<Arc<RwLock<ConsumptionCache>>>,
 ) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    // Subscribing while holding the lock makes sure no sample is missed or sent twice
+    let (window, snapshot, mut receiver) = {
+        let cache = consumption_cache.read().await;
+        let window = LiveConsumptionWindow {
+            window_minutes: cache.window_minutes(),
+        };
+        let snapshot = cache
+            .get_all()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<LiveConsumption>>();
+        (window, snapshot, cache.subscribe())
+    };
     Sse::new(stream! {
+        // Named, so that the first message keeps the same samples as /live_consumption
+        yield Ok(SseEvent::default().event("window").data(to_string(&window).unwrap()));
+        yield Ok(SseEvent::default().data(to_string(&snapshot).unwrap()));
         loop {
-            let cache = consumption_cache.read().await;
-            let res = cache
-                .get_all()
-                .into_iter()
-                .cloned()
-                .collect::<Vec<LiveConsumption>>();
-            yield Ok(SseEvent::default().data(to_string(&res).unwrap()));
-
-            // Wait for a specified interval before the next iteration.
-            // This introduces a delay, during which new data can be collected.
-            tokio::time::sleep(Duration::from_millis(2500)).await;
+            match receiver.recv().await {
+                Ok(sample) => {
+                    yield Ok(SseEvent::default().event("sample").data(to_string(&sample).unwrap()))
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Live consumption stream skipped {} samples", skipped)
+                }
+                Err(RecvError::Closed) => break,
+            }
         }
     })
     .keep_alive(KeepAlive::default().interval(Duration::from_millis(2500)))
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::routes::lib::internal_server_error;
+use crate::{now, service};
+
+pub fn reports_router(pool: Arc<PgPool>) -> Router {
+    Router::new()
+        .route("/month", get(get_month_report))
+        .layer(Extension(pool))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct MonthReportParams {
+    // Any date in the month
+    month: Option<NaiveDate>,
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+// The current month by default
+async fn get_month_report(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<MonthReportParams>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let report = service::reports::get_month_report(&pool, &params.month.unwrap_or(now().date()))
+        .await
+        .map_err(|e| internal_server_error(e).into_response())?;
+    Ok::<_, Response>(match params.format {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Csv => {
+            ([(header::CONTENT_TYPE, "text/csv")], report.to_csv()).into_response()
+        }
+    })
+}
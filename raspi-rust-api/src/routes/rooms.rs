@@ -3,7 +3,7 @@ use std::sync::Arc;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::{
-    extract::{Extension, Json, Path},
+    extract::{Extension, Json, Path, Query},
     routing::{get, post},
     Router,
 };
@@ -11,15 +11,17 @@ use log::error;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::db;
 use crate::domain::Room;
-use crate::routes::lib::error_response;
+use crate::routes::lib::{error_response, internal_server_error, PeriodParams};
+use crate::{db, service};
 
 // Define the `RoomRequest` struct that corresponds to the request payload when creating or updating a room
 #[derive(serde::Deserialize)]
 pub struct RoomRequest {
     name: String,
     min_temp: Option<f64>,
+    #[serde(default)]
+    max_temp: Option<f64>,
 }
 
 pub fn room_routes(pool: Arc<PgPool>) -> Router {
@@ -27,6 +29,8 @@ pub fn room_routes(pool: Arc<PgPool>) -> Router {
     Router::new()
         .route("/", get(get_rooms).post(create_room))
         .route("/:id", post(update_room).delete(delete_room))
+        .route("/energy", get(get_room_energy))
+        .route("/costs", get(get_room_costs))
         .layer(Extension(pool))
 }
 
@@ -50,7 +54,7 @@ async fn create_room(
     Extension(pool): Extension<Arc<PgPool>>,
     Json(body): Json<RoomRequest>,
 ) -> impl IntoResponse {
-    match db::rooms::create_room(&pool, &body.name, &body.min_temp).await {
+    match db::rooms::create_room(&pool, &body.name, &body.min_temp, &body.max_temp).await {
         Ok(_) => StatusCode::OK,
         Err(e) => {
             error!("Failed to create room: {:?}", e);
@@ -71,6 +75,7 @@ async fn update_room(
             id,
             name: body.name,
             min_temp: body.min_temp,
+            max_temp: body.max_temp,
         },
     )
     .await
@@ -90,3 +95,23 @@ async fn delete_room(
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
+
+async fn get_room_energy(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PeriodParams>,
+) -> impl IntoResponse {
+    service::plug_energy::get_room_energy(&pool, &params.period(), &params.from, &params.to())
+        .await
+        .map(Json)
+        .map_err(internal_server_error)
+}
+
+async fn get_room_costs(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PeriodParams>,
+) -> impl IntoResponse {
+    service::heating_costs::get_room_costs(&pool, &params.period(), &params.from, &params.to())
+        .await
+        .map(Json)
+        .map_err(internal_server_error)
+}
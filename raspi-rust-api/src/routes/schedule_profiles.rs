@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use chrono::NaiveDateTime;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{ActiveScheduleProfile, ScheduleProfile};
+use crate::routes::lib::{error_response, internal_server_error};
+use crate::{db, now};
+
+pub fn schedule_profiles_router(pool: Arc<PgPool>) -> Router {
+    Router::new()
+        .route("/", get(get_profiles).post(create_profile))
+        .route("/:id", post(update_profile).delete(delete_profile))
+        .route("/active", get(get_active_profile).post(activate_profile))
+        .layer(Extension(pool))
+}
+
+#[derive(Deserialize)]
+pub struct ScheduleProfileRequest {
+    name: String,
+}
+
+async fn get_profiles(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::schedule_profiles::get_schedule_profiles(&pool)
+        .await
+        .map(|profiles| (StatusCode::OK, Json(profiles)))
+        .map_err(internal_server_error)
+}
+
+async fn create_profile(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<ScheduleProfileRequest>,
+) -> impl IntoResponse {
+    db::schedule_profiles::create_schedule_profile(&pool, &ScheduleProfile::new(&body.name))
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(internal_server_error)
+}
+
+async fn update_profile(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<ScheduleProfileRequest>,
+) -> impl IntoResponse {
+    db::schedule_profiles::update_schedule_profile(
+        &pool,
+        &ScheduleProfile {
+            id,
+            name: body.name,
+        },
+    )
+    .await
+    .map(|_| StatusCode::OK)
+    .map_err(internal_server_error)
+}
+
+async fn delete_profile(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match db::schedule_profiles::delete_schedule_profile(&pool, &id).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => {
+            error!("{}", e);
+            error_response(
+                "Failed to delete profile, make sure it is not active and has no schedules."
+                    .to_string(),
+                StatusCode::CONFLICT,
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ActiveScheduleProfileResponse {
+    active_profile_id: Uuid,
+    #[serde(flatten)]
+    activation: ActiveScheduleProfile,
+}
+
+async fn get_active_profile(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::schedule_profiles::get_active_schedule_profile(&pool)
+        .await
+        .map(|active| {
+            Json(ActiveScheduleProfileResponse {
+                active_profile_id: active.profile_id_at(&now()),
+                activation: active,
+            })
+        })
+        .map_err(internal_server_error)
+}
+
+#[derive(Deserialize)]
+pub struct ActivateScheduleProfileRequest {
+    profile_id: Uuid,
+    starts_at: Option<NaiveDateTime>,
+    ends_at: Option<NaiveDateTime>,
+}
+
+async fn activate_profile(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<ActivateScheduleProfileRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    if let (Some(starts_at), Some(ends_at)) = (body.starts_at, body.ends_at) {
+        if starts_at >= ends_at {
+            return Err(error_response(
+                "Profile activation must start before it ends.".to_string(),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    }
+
+    let current = match db::schedule_profiles::get_active_schedule_profile(&pool).await {
+        Ok(active) => active,
+        Err(e) => return Err(internal_server_error(e).into_response()),
+    };
+
+    let fallback_profile_id = match body.starts_at.is_some() || body.ends_at.is_some() {
+        true => Some(current.profile_id_at(&now())),
+        false => None,
+    };
+
+    let active = ActiveScheduleProfile {
+        profile_id: body.profile_id,
+        starts_at: body.starts_at,
+        ends_at: body.ends_at,
+        fallback_profile_id,
+    };
+
+    match db::schedule_profiles::set_active_schedule_profile(&pool, &active).await {
+        Ok(_) => {
+            info!("Activated schedule profile: {:?}", active);
+            Ok(StatusCode::OK)
+        }
+        Err(e) => Err(internal_server_error(e).into_response()),
+    }
+}
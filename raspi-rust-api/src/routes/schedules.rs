@@ -1,30 +1,31 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
-use chrono::{NaiveTime, Weekday};
+use chrono::{NaiveDate, NaiveTime, Weekday};
 use log::error;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::clients::tibber_client::TibberClient;
+use crate::clients::price_provider::PriceProvider;
 use crate::db::rooms;
 use crate::domain::{PriceLevel, Schedule};
-use crate::routes::lib::error_response;
+use crate::routes::lib::{error_response, internal_server_error};
 use crate::{db, now, service};
 
 // Router definition for the schedules module
-pub fn schedules_router(pool: Arc<PgPool>, tibber_client: Arc<TibberClient>) -> Router {
+pub fn schedules_router(pool: Arc<PgPool>, price_provider: Arc<dyn PriceProvider>) -> Router {
     Router::new()
         .route("/", get(get_schedules).post(create_schedule))
         .route("/:id", post(update_schedule).delete(delete_schedule))
         .route("/active", get(get_active_schedules))
+        .route("/preview", get(get_schedule_preview))
         .layer(Extension(pool))
-        .layer(Extension(tibber_client))
+        .layer(Extension(price_provider))
 }
 
 // Handler to get all schedules
@@ -48,13 +49,20 @@ pub struct ScheduleRequest {
     pub days: Vec<Weekday>,
     pub time_windows: Vec<(NaiveTime, NaiveTime)>,
     pub room_ids: Vec<Uuid>,
+    // Defaults to the currently active profile
+    #[serde(default)]
+    pub profile_id: Option<Uuid>,
 }
 
-impl TryInto<Schedule> for ScheduleRequest {
-    type Error = anyhow::Error;
-
-    fn try_into(self) -> Result<Schedule, Self::Error> {
-        Schedule::new(self.temps, self.days, self.time_windows, self.room_ids)
+impl ScheduleRequest {
+    fn into_schedule(self, active_profile_id: Uuid) -> Result<Schedule, anyhow::Error> {
+        Schedule::new(
+            self.temps,
+            self.days,
+            self.time_windows,
+            self.room_ids,
+            self.profile_id.unwrap_or(active_profile_id),
+        )
     }
 }
 
@@ -62,7 +70,19 @@ async fn create_schedule(
     Extension(pool): Extension<Arc<PgPool>>,
     Json(body): Json<ScheduleRequest>,
 ) -> Result<impl IntoResponse, impl IntoResponse> {
-    let new_schedule = match body.try_into() {
+    let active_profile_id = match db::schedule_profiles::get_active_profile_id(&pool, &now()).await
+    {
+        Ok(profile_id) => profile_id,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                "Failed to get active schedule profile.".to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+
+    let new_schedule = match body.into_schedule(active_profile_id) {
         Ok(schedule) => schedule,
         Err(e) => {
             error!("{}", e);
@@ -91,6 +111,30 @@ async fn update_schedule(
     Path(id): Path<Uuid>,
     Json(body): Json<ScheduleRequest>,
 ) -> impl IntoResponse {
+    let profile_id = match body.profile_id {
+        Some(profile_id) => profile_id,
+        None => match db::schedules::get_schedules(&pool).await {
+            Ok(schedules) => match schedules.iter().find(|s| s.id == id) {
+                Some(existing) => existing.profile_id,
+                None => {
+                    return error_response(
+                        format!("No such schedule: {}", id),
+                        StatusCode::NOT_FOUND,
+                    )
+                    .into_response()
+                }
+            },
+            Err(e) => {
+                error!("{:?}", e);
+                return error_response(
+                    "Failed to update schedule.".to_string(),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response();
+            }
+        },
+    };
+
     match db::schedules::update_schedule(
         &pool,
         Schedule {
@@ -99,6 +143,7 @@ async fn update_schedule(
             days: body.days.clone(),
             time_windows: body.time_windows.clone(),
             room_ids: body.room_ids.clone(),
+            profile_id,
         },
     )
     .await
@@ -135,7 +180,7 @@ pub struct ActiveSchedule {
 }
 async fn get_active_schedules(
     Extension(pool): Extension<Arc<PgPool>>,
-    Extension(tibber_client): Extension<Arc<TibberClient>>,
+    Extension(price_provider): Extension<Arc<dyn PriceProvider>>,
 ) -> Result<impl IntoResponse, impl IntoResponse> {
     let rooms = match rooms::get_rooms(&pool).await {
         Ok(rooms) => rooms,
@@ -148,28 +193,17 @@ async fn get_active_schedules(
         }
     };
 
-    let price_info = match service::prices::get_current_price(&tibber_client, &pool).await {
-        Ok(price) => price,
-        Err(e) => {
-            error!("{:?}", e);
-            return Err(error_response(
-                "Failed to get current price.".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ));
-        }
-    };
+    // Shows the temperatures the control loop uses, also without a price
+    let price_info = service::prices::get_current_price_or_fallback(price_provider.as_ref(), &pool)
+        .await
+        .price;
     let mut active_schedules: Vec<ActiveSchedule> = vec![];
     for room in rooms {
         match db::schedules::get_matching_schedule(&pool, &room.id, &now()).await {
             Ok(schedule) => {
-                let temp =
-                    if let Some(schedule) = schedule.clone() {
-                        Some(schedule.get_temp(
-                            &price_info.price_level.unwrap_or(price_info.ext_price_level),
-                        ))
-                    } else {
-                        None
-                    };
+                let temp = schedule
+                    .clone()
+                    .map(|schedule| schedule.get_temp(&price_info.level()));
                 active_schedules.push(ActiveSchedule {
                     room_id: room.id,
                     schedule,
@@ -187,3 +221,19 @@ async fn get_active_schedules(
     }
     Ok((StatusCode::OK, Json(active_schedules)))
 }
+
+#[derive(serde::Deserialize)]
+pub struct PreviewParams {
+    date: NaiveDate,
+}
+
+// Simulates each hour of a day for all rooms using stored prices
+async fn get_schedule_preview(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<PreviewParams>,
+) -> impl IntoResponse {
+    service::schedules::preview_day(&pool, &params.date)
+        .await
+        .map(Json)
+        .map_err(internal_server_error)
+}
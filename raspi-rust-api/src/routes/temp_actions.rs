@@ -1,24 +1,41 @@
 use std::sync::Arc;
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
+use chrono::{Duration, NaiveDate};
+use log::error;
+use serde::Deserialize;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::db;
 use crate::domain::{
-    ActionType, TempAction, TempActionRequest, TempActionResponse, TempActionType,
+    RecurringTempAction, RecurringTempActionRequest, RecurringTempActionResponse, TempAction,
+    TempActionEndReason, TempActionHistoryResponse, TempActionRequest, TempActionResponse,
 };
-use crate::routes::lib::internal_server_error;
+use crate::routes::lib::{error_response, internal_server_error};
+use crate::service::live_events::{LiveEvent, LiveEvents};
+use crate::{db, now, service};
 
-pub fn temp_actions_router(pool: Arc<PgPool>) -> Router {
+pub fn temp_actions_router(pool: Arc<PgPool>, live_events: LiveEvents) -> Router {
     Router::new()
         .route("/", get(get_temp_actions).post(create_temp_action))
         .route("/:id", post(update_temp_action).delete(delete_temp_action))
+        .route("/effective", get(get_effective_temp_actions))
+        .route("/history", get(get_temp_action_history))
+        .route(
+            "/recurring",
+            get(get_recurring_temp_actions).post(create_recurring_temp_action),
+        )
+        .route(
+            "/recurring/:id",
+            post(update_recurring_temp_action).delete(delete_recurring_temp_action),
+        )
+        .route("/recurring/:id/skip", post(skip_recurring_temp_action))
         .layer(Extension(pool))
+        .layer(Extension(live_events))
 }
 
 async fn get_temp_actions(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
@@ -40,32 +57,73 @@ async fn get_temp_actions(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoR
 
 async fn create_temp_action(
     Extension(pool): Extension<Arc<PgPool>>,
+    Extension(live_events): Extension<LiveEvents>,
     Json(body): Json<TempActionRequest>,
 ) -> impl IntoResponse {
     let new_action: TempAction = body.into();
+    let event = LiveEvent::TempActionCreated {
+        temp_action_id: new_action.id,
+        room_ids: new_action.room_ids.clone(),
+        created_by: new_action.created_by,
+        expires_at: new_action.expires_at,
+    };
     db::temp_actions::create_temp_action(&pool, new_action)
         .await
-        .map(|_| StatusCode::OK)
+        .map(|_| {
+            live_events.publish(event);
+            StatusCode::OK
+        })
+        .map_err(internal_server_error)
+}
+
+async fn get_effective_temp_actions(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    service::temp_actions::get_effective_temp_actions(&pool, &now())
+        .await
+        .map(|effective| (StatusCode::OK, Json(effective)))
         .map_err(internal_server_error)
 }
 
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+// Both dates are inclusive, and the last 30 days are returned by default
+async fn get_temp_action_history(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<HistoryParams>,
+) -> impl IntoResponse {
+    let to = params.to.unwrap_or_else(|| now().date());
+    let from = params.from.unwrap_or(to - Duration::days(30));
+    db::temp_actions::get_temp_action_history(
+        &pool,
+        &from.and_hms(0, 0, 0),
+        &(to + Duration::days(1)).and_hms(0, 0, 0),
+    )
+    .await
+    .map(|history| {
+        (
+            StatusCode::OK,
+            Json(
+                history
+                    .into_iter()
+                    .map(|a| a.into())
+                    .collect::<Vec<TempActionHistoryResponse>>(),
+            ),
+        )
+    })
+    .map_err(internal_server_error)
+}
+
 async fn update_temp_action(
     Extension(pool): Extension<Arc<PgPool>>,
     Path(id): Path<Uuid>,
     Json(body): Json<TempActionRequest>,
 ) -> impl IntoResponse {
-    let action_type = match body.action {
-        ActionType::ON => TempActionType::ON(body.temp),
-        ActionType::OFF => TempActionType::OFF,
-    };
-    let updated_action = TempAction {
-        id,
-        room_ids: body.room_ids.clone(),
-        action_type,
-        expires_at: body.expires_at,
-        starts_at: body.starts_at,
-    };
-    db::temp_actions::update_temp_action(&pool, updated_action)
+    let mut updated_action: TempAction = body.into();
+    updated_action.id = id;
+    db::temp_actions::replace_temp_action(&pool, updated_action, &now())
         .await
         .map(|_| StatusCode::OK)
         .map_err(internal_server_error)
@@ -75,8 +133,100 @@ async fn delete_temp_action(
     Extension(pool): Extension<Arc<PgPool>>,
     Path(id): Path<Uuid>,
 ) -> impl IntoResponse {
-    db::temp_actions::delete_temp_action(&pool, &id)
+    db::temp_actions::archive_temp_action(&pool, &id, &now(), &TempActionEndReason::CANCELLED)
         .await
         .map(|_| StatusCode::OK)
         .map_err(internal_server_error)
 }
+
+async fn get_recurring_temp_actions(Extension(pool): Extension<Arc<PgPool>>) -> impl IntoResponse {
+    db::temp_actions::get_recurring_temp_actions(&pool)
+        .await
+        .map(|actions| {
+            (
+                StatusCode::OK,
+                Json(
+                    actions
+                        .into_iter()
+                        .map(|a| a.into())
+                        .collect::<Vec<RecurringTempActionResponse>>(),
+                ),
+            )
+        })
+        .map_err(internal_server_error)
+}
+
+async fn create_recurring_temp_action(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Json(body): Json<RecurringTempActionRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let new_action: RecurringTempAction = match body.try_into() {
+        Ok(action) => action,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to create recurring temp action: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    db::temp_actions::create_recurring_temp_action(&pool, &new_action)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+async fn update_recurring_temp_action(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<RecurringTempActionRequest>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let mut updated_action: RecurringTempAction = match body.try_into() {
+        Ok(action) => action,
+        Err(e) => {
+            error!("{}", e);
+            return Err(error_response(
+                format!("Failed to update recurring temp action: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    updated_action.id = id;
+    db::temp_actions::update_recurring_temp_action(&pool, &updated_action)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(|e| internal_server_error(e).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SkipOccurrenceRequest {
+    date: NaiveDate,
+}
+
+async fn skip_recurring_temp_action(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<SkipOccurrenceRequest>,
+) -> impl IntoResponse {
+    db::temp_actions::skip_recurring_temp_action(&pool, &id, &body.date)
+        .await
+        .map(|_| StatusCode::OK)
+        .map_err(internal_server_error)
+}
+
+async fn delete_recurring_temp_action(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    db::temp_actions::archive_recurring_temp_action(
+        &pool,
+        &id,
+        &now(),
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .map(|_| StatusCode::OK)
+    .map_err(internal_server_error)
+}
@@ -1,5 +1,16 @@
 pub mod consumption_cache;
+pub mod consumption_history;
+pub mod heating_costs;
+pub mod hourly_consumption;
+pub mod live_events;
 pub mod plugs;
+pub mod plug_energy;
+pub mod price_alerts;
+pub mod price_fetching;
+pub mod price_levels;
 pub mod temperature_logs;
 pub mod prices;
+pub mod reports;
+pub mod schedules;
+pub mod temp_actions;
 pub mod notifications;
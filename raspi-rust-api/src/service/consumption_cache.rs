@@ -1,37 +1,126 @@
-use std::collections::VecDeque;
-
+use chrono::{Duration, NaiveDateTime};
 use log::error;
+use serde::Serialize;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
 
-use crate::domain::LiveConsumption;
+use crate::configuration::ConsumptionCacheSettings;
+use crate::domain::{ConsumptionResolution, LiveConsumption};
 use crate::service::notifications::NotificationMessage;
 
-// 24 per minute (2.5 sec intervals)
-const MAX_CACHE_SIZE: i32 = 24 * 15;
+// Samples usually come every 2.5 seconds, this only bounds the memory used if they come faster
+const MAX_SAMPLES_PER_MINUTE: i64 = 60;
+// Subscribers lagging more than this many samples behind skip the oldest ones
+const BROADCAST_CAPACITY: usize = 64;
+const AVERAGE_MINUTES: [i64; 3] = [1, 5, 15];
+// Energy is not counted across longer gaps, like when the subscriber reconnects
+const MAX_SAMPLE_GAP_SECONDS: i64 = 60;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConsumptionStats {
+    // Average power over the last 1, 5 and 15 minutes, not set when the cache window is shorter
+    pub average_1_min: Option<f64>,
+    pub average_5_min: Option<f64>,
+    pub average_15_min: Option<f64>,
+    pub hour_starts_at: Option<NaiveDateTime>,
+    // Used so far in the hour of the latest sample
+    pub hour_kwh: f64,
+    // Highest power in the hour of the latest sample
+    pub peak: Option<LiveConsumption>,
+}
+
+// Sum of the samples from the oldest included one until the latest
+#[derive(Debug)]
+struct RollingAverage {
+    minutes: i64,
+    sum: i64,
+    count: usize,
+    oldest: usize,
+}
+
+impl RollingAverage {
+    fn average(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            count => Some(self.sum as f64 / count as f64),
+        }
+    }
+}
 
+#[derive(Debug)]
+struct HourStats {
+    starts_at: NaiveDateTime,
+    energy_wh: f64,
+    peak: LiveConsumption,
+}
+
+// A ring buffer of the samples in the window, where each sample is numbered by the order it
+// was added in. Samples older than the window are evicted, or the oldest one when the buffer is
+// full. The statistics are updated as samples are added and evicted.
 #[derive(Debug)]
 pub struct ConsumptionCache {
-    consumption: VecDeque<LiveConsumption>,
+    samples: Vec<LiveConsumption>,
+    capacity: usize,
+    window_minutes: i64,
+    added: usize,
+    oldest: usize,
+    averages: Vec<RollingAverage>,
+    hour: Option<HourStats>,
     notification_sender: Sender<NotificationMessage>,
+    broadcast_sender: broadcast::Sender<LiveConsumption>,
 }
 
 impl ConsumptionCache {
-    pub fn new(notification_sender: Sender<NotificationMessage>) -> Self {
+    pub fn new(
+        notification_sender: Sender<NotificationMessage>,
+        settings: &ConsumptionCacheSettings,
+    ) -> Self {
+        let capacity = (settings.window_minutes * MAX_SAMPLES_PER_MINUTE).max(1) as usize;
+        let (broadcast_sender, _) = broadcast::channel(BROADCAST_CAPACITY);
         Self {
-            consumption: VecDeque::with_capacity(MAX_CACHE_SIZE as usize),
+            samples: Vec::with_capacity(capacity),
+            capacity,
+            window_minutes: settings.window_minutes,
+            added: 0,
+            oldest: 0,
+            averages: AVERAGE_MINUTES
+                .iter()
+                .map(|minutes| RollingAverage {
+                    minutes: *minutes,
+                    sum: 0,
+                    count: 0,
+                    oldest: 0,
+                })
+                .collect(),
+            hour: None,
             notification_sender,
+            broadcast_sender,
         }
     }
 
+    // Receives the samples added after subscribing
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveConsumption> {
+        self.broadcast_sender.subscribe()
+    }
+
     pub async fn add(&mut self, value: LiveConsumption) {
-        if self.consumption.len() as i32 == MAX_CACHE_SIZE {
-            self.consumption = self
-                .consumption
-                .range(0..(MAX_CACHE_SIZE - 1) as usize)
-                .copied()
-                .collect()
-        }
-        self.consumption.push_front(value);
+        let previous = self.get_latest(1).first().map(|previous| **previous);
+        self.add_to_hour(previous, &value);
+        if self.len() == self.capacity {
+            self.evict_oldest();
+        }
+        let index = self.added % self.capacity;
+        if index == self.samples.len() {
+            self.samples.push(value);
+        } else {
+            self.samples[index] = value;
+        }
+        self.added += 1;
+        self.add_to_averages(&value);
+        self.evict_outside_window(&value);
+
+        // Only fails when nobody is subscribed
+        let _ = self.broadcast_sender.send(value);
         let sent = self
             .notification_sender
             .send(NotificationMessage::Consumption {
@@ -43,15 +132,123 @@ impl ConsumptionCache {
         }
     }
 
-    pub fn get_latest(&self, num: i32) -> Vec<&LiveConsumption> {
-        if self.consumption.len() < num as usize {
-            return self.consumption.iter().collect();
+    fn sample(&self, number: usize) -> &LiveConsumption {
+        &self.samples[number % self.capacity]
+    }
+
+    fn len(&self) -> usize {
+        self.added - self.oldest
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self.oldest;
+        let power = self.sample(oldest).power;
+        for average in self.averages.iter_mut() {
+            if average.count > 0 && average.oldest == oldest {
+                average.sum -= power;
+                average.count -= 1;
+                average.oldest += 1;
+            }
+        }
+        self.oldest += 1;
+    }
+
+    // The latest sample is always kept
+    fn evict_outside_window(&mut self, latest: &LiveConsumption) {
+        let from = latest.timestamp - Duration::minutes(self.window_minutes);
+        while self.len() > 1 && self.sample(self.oldest).timestamp <= from {
+            self.evict_oldest();
+        }
+    }
+
+    fn add_to_averages(&mut self, value: &LiveConsumption) {
+        let latest = self.added - 1;
+        for i in 0..self.averages.len() {
+            let from = value.timestamp - Duration::minutes(self.averages[i].minutes);
+            let average = &mut self.averages[i];
+            if average.count == 0 {
+                average.oldest = latest;
+            }
+            average.sum += value.power;
+            average.count += 1;
+            while self.averages[i].count > 0 {
+                let oldest = *self.sample(self.averages[i].oldest);
+                if oldest.timestamp > from {
+                    break;
+                }
+                let average = &mut self.averages[i];
+                average.sum -= oldest.power;
+                average.count -= 1;
+                average.oldest += 1;
+            }
+        }
+    }
+
+    // The power of each sample is used until the next one
+    fn add_to_hour(&mut self, previous: Option<LiveConsumption>, value: &LiveConsumption) {
+        let starts_at = ConsumptionResolution::Hour.truncate(&value.timestamp);
+        let energy_wh = match previous {
+            Some(previous)
+                if (value.timestamp - previous.timestamp).num_seconds()
+                    <= MAX_SAMPLE_GAP_SECONDS =>
+            {
+                let from = previous.timestamp.max(starts_at);
+                previous.power as f64 * (value.timestamp - from).num_milliseconds().max(0) as f64
+                    / 3_600_000.0
+            }
+            _ => 0.0,
+        };
+        match &mut self.hour {
+            Some(hour) if hour.starts_at == starts_at => {
+                hour.energy_wh += energy_wh;
+                if value.power > hour.peak.power {
+                    hour.peak = *value;
+                }
+            }
+            _ => {
+                self.hour = Some(HourStats {
+                    starts_at,
+                    energy_wh,
+                    peak: *value,
+                })
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> ConsumptionStats {
+        let average = |minutes: i64| {
+            self.averages
+                .iter()
+                .find(|average| average.minutes == minutes && minutes <= self.window_minutes)
+                .and_then(RollingAverage::average)
+        };
+        ConsumptionStats {
+            average_1_min: average(1),
+            average_5_min: average(5),
+            average_15_min: average(15),
+            hour_starts_at: self.hour.as_ref().map(|hour| hour.starts_at),
+            hour_kwh: self
+                .hour
+                .as_ref()
+                .map_or(0.0, |hour| hour.energy_wh / 1000.0),
+            peak: self.hour.as_ref().map(|hour| hour.peak),
         }
-        self.consumption.range(0..num as usize).collect()
     }
 
+    pub fn window_minutes(&self) -> i64 {
+        self.window_minutes
+    }
+
+    // Newest first
+    pub fn get_latest(&self, num: i32) -> Vec<&LiveConsumption> {
+        (0..self.len().min(num.max(0) as usize))
+            .map(|i| self.sample(self.added - 1 - i))
+            .collect()
+    }
+
+    // Newest first
     pub fn get_all(&self) -> Vec<&LiveConsumption> {
-        self.consumption.iter().collect()
+        self.get_latest(self.len() as i32)
     }
 }
 
@@ -59,68 +256,108 @@ impl ConsumptionCache {
 mod tests {
     use chrono::NaiveDateTime;
 
+    use crate::configuration::ConsumptionCacheSettings;
     use crate::domain::LiveConsumption;
-    use crate::service::consumption_cache::{ConsumptionCache, MAX_CACHE_SIZE};
+    use crate::service::consumption_cache::{ConsumptionCache, MAX_SAMPLES_PER_MINUTE};
     use crate::service::notifications::NotificationMessage;
 
     fn consumption_cache() -> ConsumptionCache {
         let (tx, _) = tokio::sync::mpsc::channel::<NotificationMessage>(1);
-        ConsumptionCache::new(tx)
+        ConsumptionCache::new(tx, &ConsumptionCacheSettings::default())
+    }
+
+    fn value(seconds: i64, power: i64) -> LiveConsumption {
+        LiveConsumption {
+            timestamp: NaiveDateTime::from_timestamp(1_000_000_000 + seconds, 0),
+            power,
+        }
     }
 
     #[tokio::test]
-    async fn should_discard_old_values_when_max_hit() {
+    async fn should_discard_values_older_than_window() {
         let mut cache = consumption_cache();
         for i in 0..=1000 {
-            cache
-                .add(LiveConsumption {
-                    timestamp: NaiveDateTime::from_timestamp(1_000_000_000 + i * 5, 0),
-                    power: i,
-                })
-                .await;
+            cache.add(value(i * 5, i)).await;
         }
-        assert_eq!(cache.consumption.len() as i32, MAX_CACHE_SIZE)
+        // The window of 15 minutes covers 180 samples 5 seconds apart
+        let all = cache.get_all();
+        assert_eq!(all.len(), 180);
+        assert_eq!(all.first().map(|v| v.power), Some(1000));
+        assert_eq!(all.last().map(|v| v.power), Some(821))
     }
 
     #[tokio::test]
-    async fn should_return_latest_value() {
+    async fn should_discard_old_values_when_max_hit() {
         let mut cache = consumption_cache();
         for i in 0..=1000 {
-            cache
-                .add(LiveConsumption {
-                    timestamp: NaiveDateTime::from_timestamp(1_000_000_000 + i * 5, 0),
-                    power: i,
-                })
-                .await;
+            cache.add(value(0, i)).await;
         }
+        let all = cache.get_all();
+        assert_eq!(all.len() as i64, 15 * MAX_SAMPLES_PER_MINUTE);
+        assert_eq!(all.first().map(|v| v.power), Some(1000));
         assert_eq!(
-            cache.get_latest(1).get(0),
-            Some(&&LiveConsumption {
-                timestamp: NaiveDateTime::from_timestamp(1_000_000_000 + 1000 * 5, 0),
-                power: 1000,
-            })
+            all.last().map(|v| v.power),
+            Some(1000 - 15 * MAX_SAMPLES_PER_MINUTE + 1)
         )
     }
 
+    #[tokio::test]
+    async fn should_return_latest_value() {
+        let mut cache = consumption_cache();
+        for i in 0..=1000 {
+            cache.add(value(i * 5, i)).await;
+        }
+        assert_eq!(cache.get_latest(1).first(), Some(&&value(1000 * 5, 1000)))
+    }
+
+    #[tokio::test]
+    async fn should_send_new_values_to_subscribers() {
+        let mut cache = consumption_cache();
+        cache.add(value(0, 0)).await;
+        let mut receiver = cache.subscribe();
+        cache.add(value(5, 1)).await;
+        cache.add(value(10, 2)).await;
+
+        assert_eq!(receiver.recv().await, Ok(value(5, 1)));
+        assert_eq!(receiver.recv().await, Ok(value(10, 2)));
+        assert!(receiver.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn should_allow_getting_more_than_exists() {
         let mut cache = consumption_cache();
         for i in 0..=2 {
-            cache
-                .add(LiveConsumption {
-                    timestamp: NaiveDateTime::from_timestamp(1_000_000_000 + i * 5, 0),
-                    power: i,
-                })
-                .await;
+            cache.add(value(i * 5, i)).await;
         }
         assert_eq!(cache.get_latest(100).get(50), None);
         dbg!(&cache);
-        assert_eq!(
-            cache.get_latest(3).get(2),
-            Some(&&LiveConsumption {
-                timestamp: NaiveDateTime::from_timestamp(1_000_000_000, 0),
-                power: 0
-            })
-        )
+        assert_eq!(cache.get_latest(3).get(2), Some(&&value(0, 0)))
+    }
+
+    #[tokio::test]
+    async fn should_keep_rolling_stats() {
+        let (tx, _) = tokio::sync::mpsc::channel::<NotificationMessage>(1);
+        let mut cache = ConsumptionCache::new(tx, &ConsumptionCacheSettings { window_minutes: 2 });
+        // 5 Wh for each 5 seconds at 3600 W, and the next hour starts after 800 seconds
+        for i in 0..180 {
+            let power = if i < 60 { 7200 } else { 3600 };
+            cache.add(value(i * 5, power)).await;
+        }
+        let stats = cache.get_stats();
+        assert_eq!(cache.get_all().len(), 24);
+        assert_eq!(stats.average_1_min, Some(3600.0));
+        // Longer than the window
+        assert_eq!(stats.average_5_min, None);
+        assert_eq!(stats.average_15_min, None);
+        assert_eq!(stats.hour_starts_at, Some(value(800, 0).timestamp));
+        assert_eq!(stats.hour_kwh, 0.095);
+        assert_eq!(stats.peak, Some(value(800, 3600)));
+
+        // Energy is not counted across the gap
+        cache.add(value(1000, 10800)).await;
+        let stats = cache.get_stats();
+        assert_eq!(stats.average_1_min, Some(10800.0));
+        assert_eq!(stats.hour_kwh, 0.095);
+        assert_eq!(stats.peak, Some(value(1000, 10800)));
     }
 }
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::{debug, warn};
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+
+use crate::configuration::ConsumptionHistorySettings;
+use crate::db::DbError;
+use crate::domain::{ConsumptionResolution, LiveConsumption, PowerAggregate};
+use crate::service::consumption_cache::ConsumptionCache;
+use crate::{db, now};
+
+// Aggregates are recalculated for the last hours, to include samples stored late
+const DOWNSAMPLE_HOURS: i64 = 2;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ConsumptionHistory {
+    pub resolution: ConsumptionResolution,
+    pub consumption: Vec<PowerAggregate>,
+}
+
+// Stores new samples from the cache every minute, then downsamples and deletes old history
+pub async fn start(
+    pool: Arc<PgPool>,
+    consumption_cache: Arc<RwLock<ConsumptionCache>>,
+    settings: ConsumptionHistorySettings,
+) {
+    let mut last_stored: Option<NaiveDateTime> = None;
+    loop {
+        match store_consumption(pool.as_ref(), &consumption_cache, last_stored).await {
+            Ok(latest) => last_stored = latest,
+            Err(e) => warn!("Failed to store live consumption: {}", e),
+        }
+        let now = now();
+        if let Err(e) = downsample(pool.as_ref(), &now).await {
+            warn!("Failed to downsample live consumption: {}", e)
+        }
+        if let Err(e) = apply_retention(pool.as_ref(), &settings, &now).await {
+            warn!("Failed to delete old live consumption: {}", e)
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+// Returns the timestamp of the latest stored sample
+async fn store_consumption(
+    pool: &PgPool,
+    consumption_cache: &RwLock<ConsumptionCache>,
+    last_stored: Option<NaiveDateTime>,
+) -> Result<Option<NaiveDateTime>, DbError> {
+    let last_stored = match last_stored {
+        Some(last_stored) => Some(last_stored),
+        None => db::live_consumption::get_latest_timestamp(pool).await?,
+    };
+    let samples = new_samples(&consumption_cache.read().await.get_all(), last_stored);
+    if samples.is_empty() {
+        return Ok(last_stored);
+    }
+    db::live_consumption::insert_live_consumption(pool, &samples).await?;
+    debug!("Stored {} live consumption samples", samples.len());
+    Ok(samples.iter().map(|sample| sample.timestamp).max())
+}
+
+fn new_samples(
+    cached: &[&LiveConsumption],
+    last_stored: Option<NaiveDateTime>,
+) -> Vec<LiveConsumption> {
+    cached
+        .iter()
+        .filter(|sample| last_stored.map_or(true, |last| sample.timestamp > last))
+        .map(|sample| **sample)
+        .collect()
+}
+
+// Only complete minutes and hours are aggregated
+pub async fn downsample(pool: &PgPool, now: &NaiveDateTime) -> Result<(), DbError> {
+    let from =
+        ConsumptionResolution::Hour.truncate(now) - chrono::Duration::hours(DOWNSAMPLE_HOURS);
+    db::live_consumption::aggregate_minutes(
+        pool,
+        &from,
+        &ConsumptionResolution::Minute.truncate(now),
+    )
+    .await?;
+    db::live_consumption::aggregate_hours(pool, &from, &ConsumptionResolution::Hour.truncate(now))
+        .await
+}
+
+pub async fn apply_retention(
+    pool: &PgPool,
+    settings: &ConsumptionHistorySettings,
+    now: &NaiveDateTime,
+) -> Result<(), DbError> {
+    db::live_consumption::delete_live_consumption_before(
+        pool,
+        &(*now - chrono::Duration::hours(settings.raw_retention_hours)),
+    )
+    .await?;
+    db::live_consumption::delete_aggregates_before(
+        pool,
+        &ConsumptionResolution::Minute,
+        &(*now - chrono::Duration::days(settings.minute_retention_days)),
+    )
+    .await?;
+    db::live_consumption::delete_aggregates_before(
+        pool,
+        &ConsumptionResolution::Hour,
+        &(*now - chrono::Duration::days(settings.hour_retention_days)),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_consumption_history(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+    resolution: Option<ConsumptionResolution>,
+) -> Result<ConsumptionHistory, DbError> {
+    let resolution = resolution.unwrap_or_else(|| default_resolution(from, to));
+    let consumption = match resolution {
+        ConsumptionResolution::Raw => db::live_consumption::get_live_consumption(pool, from, to)
+            .await?
+            .iter()
+            .map(PowerAggregate::from)
+            .collect(),
+        _ => db::live_consumption::get_aggregates(pool, &resolution, from, to).await?,
+    };
+    Ok(ConsumptionHistory {
+        resolution,
+        consumption,
+    })
+}
+
+// Keeps the number of values reasonable for longer ranges
+fn default_resolution(from: &NaiveDateTime, to: &NaiveDateTime) -> ConsumptionResolution {
+    let range = *to - *from;
+    if range <= chrono::Duration::hours(2) {
+        ConsumptionResolution::Raw
+    } else if range <= chrono::Duration::days(2) {
+        ConsumptionResolution::Minute
+    } else {
+        ConsumptionResolution::Hour
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::domain::{ConsumptionResolution, LiveConsumption};
+
+    use super::{default_resolution, new_samples};
+
+    #[test]
+    fn only_samples_after_last_stored_are_new() {
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let samples: Vec<LiveConsumption> = (0..4)
+            .rev()
+            .map(|i| LiveConsumption {
+                timestamp: time + Duration::seconds(i * 5),
+                power: 1000 + i,
+            })
+            .collect();
+        let cached: Vec<&LiveConsumption> = samples.iter().collect();
+
+        assert_eq!(new_samples(&cached, None).len(), 4);
+        assert_eq!(
+            new_samples(&cached, Some(time + Duration::seconds(5))),
+            vec![samples[0], samples[1]]
+        );
+        assert!(new_samples(&cached, Some(time + Duration::seconds(15))).is_empty());
+    }
+
+    #[test]
+    fn resolution_depends_on_range() {
+        let from = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+
+        assert_eq!(
+            default_resolution(&from, &(from + Duration::hours(1))),
+            ConsumptionResolution::Raw
+        );
+        assert_eq!(
+            default_resolution(&from, &(from + Duration::days(1))),
+            ConsumptionResolution::Minute
+        );
+        assert_eq!(
+            default_resolution(&from, &(from + Duration::days(7))),
+            ConsumptionResolution::Hour
+        );
+        assert_eq!(
+            ConsumptionResolution::Minute.truncate(&(from + Duration::seconds(95))),
+            from + Duration::minutes(1)
+        );
+        assert_eq!(
+            ConsumptionResolution::Hour.truncate(&(from + Duration::seconds(3599))),
+            from
+        );
+    }
+}
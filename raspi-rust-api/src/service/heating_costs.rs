@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db;
+use crate::db::DbError;
+use crate::domain::{ConsumptionPeriod, Plug, PlugEnergyReading, PriceInfo};
+use crate::service::plug_energy::METER_POLL_MINUTES;
+use crate::service::prices::average_price;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PlugCost {
+    pub plug_id: Uuid,
+    pub kwh: f64,
+    pub spot_cost: f64,
+    // Only set when price settings are configured
+    pub total_cost: Option<f64>,
+    // Some of the energy is estimated from the rated wattage of the plug
+    pub estimated: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RoomCost {
+    pub room_id: Uuid,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub kwh: f64,
+    pub spot_cost: f64,
+    pub total_cost: Option<f64>,
+    pub plugs: Vec<PlugCost>,
+}
+
+// Both dates are inclusive, and extended to whole periods
+pub async fn get_room_costs(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDate,
+    to: &NaiveDate,
+) -> Result<Vec<RoomCost>, DbError> {
+    let from = period.start_of(from).and_hms(0, 0, 0);
+    let to = period.next(to).and_hms(0, 0, 0);
+    let readings = db::plug_energy::get_readings(pool, &from, &to).await?;
+    let plugs = db::plugs::get_plugs(pool).await?;
+    // The first readings cover energy used before the period
+    let earliest = readings
+        .iter()
+        .map(|reading| reading.since.unwrap_or(from))
+        .fold(from, NaiveDateTime::min);
+    let prices = db::prices::get_prices(pool, &(earliest - Duration::hours(1)), &to).await?;
+    Ok(attribute_costs(period, &readings, &plugs, &prices))
+}
+
+// Each reading is priced with the prices between the previous reading and itself, and belongs
+// to the period it was taken in. Energy used when there are no stored prices has no cost.
+fn attribute_costs(
+    period: &ConsumptionPeriod,
+    readings: &[PlugEnergyReading],
+    plugs: &[Plug],
+    prices: &[PriceInfo],
+) -> Vec<RoomCost> {
+    let rooms: HashMap<Uuid, Uuid> = plugs.iter().map(|plug| (plug.id, plug.room_id)).collect();
+    let has_total = prices.iter().any(|price| price.total.is_some());
+    let mut costs: BTreeMap<(NaiveDate, Uuid), BTreeMap<Uuid, PlugCost>> = BTreeMap::new();
+    for reading in readings {
+        let room_id = match rooms.get(&reading.plug_id) {
+            Some(room_id) => *room_id,
+            None => continue,
+        };
+        let from = reading
+            .since
+            .unwrap_or(reading.time - Duration::minutes(METER_POLL_MINUTES as i64));
+        let kwh = reading.energy_wh / 1000.0;
+        let spot_price = average_price(prices, &from, &reading.time, |price| price.amount);
+        let total_price = average_price(prices, &from, &reading.time, |price| {
+            price.total.unwrap_or(price.amount)
+        });
+        let plug = costs
+            .entry((period.start_of(&reading.time.date()), room_id))
+            .or_default()
+            .entry(reading.plug_id)
+            .or_insert_with(|| PlugCost {
+                plug_id: reading.plug_id,
+                kwh: 0.0,
+                spot_cost: 0.0,
+                total_cost: has_total.then_some(0.0),
+                estimated: false,
+            });
+        plug.kwh += kwh;
+        plug.spot_cost += kwh * spot_price.unwrap_or(0.0);
+        plug.total_cost = plug
+            .total_cost
+            .map(|cost| cost + kwh * total_price.unwrap_or(0.0));
+        plug.estimated |= reading.estimated;
+    }
+
+    costs
+        .into_iter()
+        .map(|((start, room_id), plugs)| {
+            let plugs: Vec<PlugCost> = plugs.into_values().collect();
+            RoomCost {
+                room_id,
+                from: start.and_hms(0, 0, 0),
+                to: period.next(&start).and_hms(0, 0, 0),
+                kwh: plugs.iter().map(|plug| plug.kwh).sum(),
+                spot_cost: plugs.iter().map(|plug| plug.spot_cost).sum(),
+                total_cost: has_total
+                    .then(|| plugs.iter().filter_map(|plug| plug.total_cost).sum()),
+                plugs,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use uuid::Uuid;
+
+    use crate::domain::{ConsumptionPeriod, Plug, PlugEnergyReading, PriceInfo, PriceLevel};
+
+    use super::attribute_costs;
+
+    fn price(hour: u32, amount: f64, total: Option<f64>) -> PriceInfo {
+        PriceInfo {
+            amount,
+            total,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: NaiveDate::from_ymd(2023, 1, 2).and_hms(hour, 0, 0),
+            duration_minutes: 60,
+        }
+    }
+
+    #[test]
+    fn costs_are_attributed_to_rooms_and_plugs() {
+        let room_id = Uuid::new_v4();
+        let plug = |name| Plug::new(name, "127.0.0.1", "", "", &room_id, &true).unwrap();
+        let (heater, floor) = (plug("heater"), plug("floor"));
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let reading = |plug: &Plug, minutes, energy_wh, estimated| PlugEnergyReading {
+            plug_id: plug.id,
+            time: time + Duration::minutes(minutes),
+            since: Some(time + Duration::minutes(minutes - 30)),
+            counter: None,
+            energy_wh,
+            estimated,
+            power: None,
+            is_on: None,
+        };
+        let readings = [
+            // Half in each hour
+            reading(&heater, 15, 500.0, false),
+            reading(&heater, 45, 250.0, false),
+            reading(&floor, 60, 1000.0, true),
+            // Not priced
+            reading(&floor, 24 * 60, 1000.0, true),
+        ];
+
+        let spot = attribute_costs(
+            &ConsumptionPeriod::Day,
+            &readings,
+            &[heater.clone(), floor.clone()],
+            &[price(9, 1.0, None), price(10, 2.0, None)],
+        );
+        assert_eq!(spot.len(), 2);
+        assert_eq!(spot[0].room_id, room_id);
+        assert_eq!(spot[0].from, time.date().and_hms(0, 0, 0));
+        assert_eq!(spot[0].kwh, 1.75);
+        assert_eq!(spot[0].spot_cost, 3.25);
+        assert_eq!(spot[0].total_cost, None);
+        let heater_cost = spot[0]
+            .plugs
+            .iter()
+            .find(|p| p.plug_id == heater.id)
+            .unwrap();
+        assert_eq!(heater_cost.kwh, 0.75);
+        assert_eq!(heater_cost.spot_cost, 1.25);
+        assert!(!heater_cost.estimated);
+        assert_eq!(spot[1].kwh, 1.0);
+        assert_eq!(spot[1].spot_cost, 0.0);
+        assert!(spot[1].plugs[0].estimated);
+
+        let total = attribute_costs(
+            &ConsumptionPeriod::Month,
+            &readings,
+            &[heater, floor],
+            &[price(9, 1.0, Some(2.0)), price(10, 2.0, Some(3.0))],
+        );
+        assert_eq!(total.len(), 1);
+        assert_eq!(
+            total[0].from,
+            NaiveDate::from_ymd(2023, 1, 1).and_hms(0, 0, 0)
+        );
+        assert_eq!(total[0].kwh, 2.75);
+        assert_eq!(total[0].spot_cost, 3.25);
+        assert_eq!(total[0].total_cost, Some(5.0));
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use log::{info, warn};
+use sqlx::PgPool;
+use thiserror::Error;
+
+use crate::clients::tibber_client::{TibberClient, TibberClientError};
+use crate::db::DbError;
+use crate::domain::{ConsumptionPeriod, ConsumptionSummary};
+use crate::{db, now};
+
+const IMPORT_INTERVAL_MINUTES: u64 = 60;
+// Tibber corrects the consumption of the latest hours, so they are fetched again
+const OVERLAP_HOURS: i64 = 48;
+// Tibber returns the hours it has when asked for more
+const MAX_BACKFILL_HOURS: i64 = 24 * 366;
+
+#[derive(Error, Debug)]
+pub enum HourlyConsumptionError {
+    #[error("TibberClientError: {0}")]
+    TibberClientError(#[from] TibberClientError),
+    #[error("DbError: {0}")]
+    DbError(#[from] DbError),
+}
+
+// Imports the hourly consumption from Tibber every hour, backfilling on the first run
+pub async fn start(pool: Arc<PgPool>, tibber_client: Arc<TibberClient>) {
+    loop {
+        match import_consumption(tibber_client.as_ref(), pool.as_ref()).await {
+            Ok(hours) => info!("Imported {} hours of consumption", hours),
+            Err(e) => warn!("Failed to import consumption: {}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(IMPORT_INTERVAL_MINUTES * 60)).await;
+    }
+}
+
+// Returns the number of hours fetched
+pub async fn import_consumption(
+    tibber_client: &TibberClient,
+    pool: &PgPool,
+) -> Result<usize, HourlyConsumptionError> {
+    let latest = db::consumption::get_latest_consumption_time(pool).await?;
+    let consumption = tibber_client
+        .get_consumption(hours_to_fetch(latest, &now()))
+        .await?;
+    db::consumption::upsert_consumption(pool, &consumption).await?;
+    Ok(consumption.len())
+}
+
+fn hours_to_fetch(latest: Option<NaiveDateTime>, now: &NaiveDateTime) -> u32 {
+    match latest {
+        Some(latest) => ((*now - latest).num_hours() + OVERLAP_HOURS).min(MAX_BACKFILL_HOURS),
+        None => MAX_BACKFILL_HOURS,
+    }
+    .max(OVERLAP_HOURS) as u32
+}
+
+// Both dates are inclusive, and extended to whole periods
+pub async fn get_consumption_summaries(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDate,
+    to: &NaiveDate,
+) -> Result<Vec<ConsumptionSummary>, DbError> {
+    db::consumption::get_consumption_summaries(
+        pool,
+        period,
+        &period.start_of(from).and_hms(0, 0, 0),
+        &period.next(to).and_hms(0, 0, 0),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::{hours_to_fetch, MAX_BACKFILL_HOURS, OVERLAP_HOURS};
+
+    #[test]
+    fn fetches_hours_since_latest_with_overlap() {
+        let now = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 30, 0);
+
+        assert_eq!(hours_to_fetch(None, &now), MAX_BACKFILL_HOURS as u32);
+        assert_eq!(
+            hours_to_fetch(Some(now - Duration::hours(3)), &now),
+            (OVERLAP_HOURS + 3) as u32
+        );
+        assert_eq!(
+            hours_to_fetch(Some(now - Duration::days(800)), &now),
+            MAX_BACKFILL_HOURS as u32
+        );
+        assert_eq!(
+            hours_to_fetch(Some(now + Duration::hours(5)), &now),
+            OVERLAP_HOURS as u32
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use log::warn;
+use serde::Serialize;
+use strum_macros::{Display, EnumDiscriminants, EnumString};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::domain::{ActionType, TempActionSource};
+use crate::service::consumption_cache::ConsumptionCache;
+
+// Subscribers lagging more than this many events behind skip the oldest ones
+const EVENT_CAPACITY: usize = 256;
+
+#[derive(Serialize, Debug, Clone, PartialEq, EnumDiscriminants)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[strum_discriminants(
+    name(LiveEventType),
+    derive(EnumString, Display, Hash),
+    strum(serialize_all = "snake_case")
+)]
+pub enum LiveEvent {
+    Temperature {
+        room_id: Uuid,
+        temp: f64,
+        time: NaiveDateTime,
+    },
+    PlugCommanded {
+        plug_id: Uuid,
+        room_id: Uuid,
+        action: ActionType,
+        time: NaiveDateTime,
+    },
+    // Read from the plug, only sent when it changes
+    PlugState {
+        plug_id: Uuid,
+        room_id: Uuid,
+        is_on: bool,
+        time: NaiveDateTime,
+    },
+    RoomDecision {
+        room_id: Uuid,
+        action: ActionType,
+        time: NaiveDateTime,
+    },
+    TempActionCreated {
+        temp_action_id: Uuid,
+        room_ids: Vec<Uuid>,
+        created_by: TempActionSource,
+        expires_at: NaiveDateTime,
+    },
+    TempActionExpired {
+        temp_action_id: Uuid,
+        room_ids: Vec<Uuid>,
+        time: NaiveDateTime,
+    },
+    PriceUpdate {
+        // End of the stored prices
+        to: Option<NaiveDateTime>,
+        has_tomorrow: bool,
+        time: NaiveDateTime,
+    },
+    NotificationSent {
+        message: String,
+        time: NaiveDateTime,
+    },
+    LivePower {
+        power: i64,
+        time: NaiveDateTime,
+    },
+}
+
+impl LiveEvent {
+    pub fn event_type(&self) -> LiveEventType {
+        self.into()
+    }
+
+    // The rooms the event is about, empty for events about the whole house
+    fn room_ids(&self) -> Vec<Uuid> {
+        match self {
+            LiveEvent::Temperature { room_id, .. }
+            | LiveEvent::PlugCommanded { room_id, .. }
+            | LiveEvent::PlugState { room_id, .. }
+            | LiveEvent::RoomDecision { room_id, .. } => vec![*room_id],
+            LiveEvent::TempActionCreated { room_ids, .. }
+            | LiveEvent::TempActionExpired { room_ids, .. } => room_ids.clone(),
+            LiveEvent::PriceUpdate { .. }
+            | LiveEvent::NotificationSent { .. }
+            | LiveEvent::LivePower { .. } => vec![],
+        }
+    }
+}
+
+// Cheap to clone, and publishing without subscribers does nothing
+#[derive(Debug, Clone)]
+pub struct LiveEvents {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl Default for LiveEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl LiveEvents {
+    pub fn publish(&self, event: LiveEvent) {
+        // Only fails when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct EventFilter {
+    // All types when empty
+    pub types: Vec<LiveEventType>,
+    // Events about other rooms are left out, while events about the whole house are kept
+    pub room_id: Option<Uuid>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &LiveEvent) -> bool {
+        let type_matches = self.types.is_empty() || self.types.contains(&event.event_type());
+        let room_matches = match self.room_id {
+            None => true,
+            Some(room_id) => {
+                let room_ids = event.room_ids();
+                room_ids.is_empty() || room_ids.contains(&room_id)
+            }
+        };
+        type_matches && room_matches
+    }
+}
+
+// Publishes the samples from the Tibber subscriber as live power events
+pub async fn forward_live_power(
+    consumption_cache: Arc<RwLock<ConsumptionCache>>,
+    live_events: LiveEvents,
+) {
+    let mut receiver = consumption_cache.read().await.subscribe();
+    loop {
+        match receiver.recv().await {
+            Ok(sample) => live_events.publish(LiveEvent::LivePower {
+                power: sample.power,
+                time: sample.timestamp,
+            }),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Live power events skipped {} samples", skipped)
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    use crate::domain::ActionType;
+
+    use super::{EventFilter, LiveEvent, LiveEventType, LiveEvents};
+
+    #[test]
+    fn filter_matches_types_and_rooms() {
+        let room_id = Uuid::new_v4();
+        let time = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+        let decision = |room_id| LiveEvent::RoomDecision {
+            room_id,
+            action: ActionType::ON,
+            time,
+        };
+        let power = LiveEvent::LivePower { power: 1000, time };
+
+        assert!(EventFilter::default().matches(&decision(room_id)));
+        let rooms = EventFilter {
+            types: vec![],
+            room_id: Some(room_id),
+        };
+        assert!(rooms.matches(&decision(room_id)));
+        assert!(!rooms.matches(&decision(Uuid::new_v4())));
+        assert!(rooms.matches(&power));
+        let types = EventFilter {
+            types: vec![LiveEventType::from_str("live_power").unwrap()],
+            room_id: None,
+        };
+        assert!(types.matches(&power));
+        assert!(!types.matches(&decision(room_id)));
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_published_events() {
+        let live_events = LiveEvents::default();
+        let event = LiveEvent::LivePower {
+            power: 1000,
+            time: NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0),
+        };
+        live_events.publish(event.clone());
+        let mut receiver = live_events.subscribe();
+        live_events.publish(event.clone());
+
+        assert_eq!(receiver.recv().await, Ok(event));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            serde_json::to_value(LiveEvent::LivePower {
+                power: 1000,
+                time: NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0),
+            })
+            .unwrap()["type"],
+            "live_power"
+        );
+    }
+}
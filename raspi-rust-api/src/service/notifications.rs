@@ -11,6 +11,8 @@ use tokio::sync::mpsc::Receiver;
 use crate::clients::ntfy::{NtfyClient, NtfyClientError};
 use crate::db::DbError;
 use crate::domain::NotificationSettings;
+use crate::service::live_events::{LiveEvent, LiveEvents};
+use crate::service::price_alerts::PriceSummary;
 use crate::{db, now};
 
 pub struct NotificationHandler {
@@ -18,6 +20,7 @@ pub struct NotificationHandler {
     pool: Arc<PgPool>,
     receiver: Receiver<NotificationMessage>,
     last_sent: HashMap<NotificationKey, NaiveDateTime>,
+    live_events: LiveEvents,
 }
 
 #[derive(Error, Debug)]
@@ -28,9 +31,10 @@ pub enum NotificationHandlerError {
     DbError(#[from] DbError),
 }
 
-#[derive(Debug, Display, PartialEq, Eq)]
+#[derive(Debug, Display, PartialEq)]
 pub enum NotificationMessage {
     Consumption { watt_usage: i64 },
+    PriceSummary(PriceSummary),
 }
 
 type NotificationKey = i32;
@@ -41,11 +45,13 @@ impl NotificationMessage {
             NotificationMessage::Consumption { watt_usage } => {
                 format!("⚡️Current consumption {} W!️", watt_usage)
             }
+            NotificationMessage::PriceSummary(summary) => summary.display(),
         }
     }
     fn key(&self) -> NotificationKey {
         match self {
             NotificationMessage::Consumption { .. } => 1,
+            NotificationMessage::PriceSummary(_) => 2,
         }
     }
     fn timeout(&self, settings: &NotificationSettings) -> Duration {
@@ -53,6 +59,7 @@ impl NotificationMessage {
             NotificationMessage::Consumption { .. } => {
                 Duration::minutes(settings.max_consumption_timeout_minutes as i64)
             }
+            NotificationMessage::PriceSummary(_) => Duration::zero(),
         }
     }
 
@@ -75,17 +82,24 @@ impl NotificationMessage {
                     false
                 }
             }
+            // Only sent once for each day by the price alerts
+            NotificationMessage::PriceSummary(_) => true,
         }
     }
 }
 
 impl NotificationHandler {
-    pub fn new(receiver: Receiver<NotificationMessage>, pool: Arc<PgPool>) -> Self {
+    pub fn new(
+        receiver: Receiver<NotificationMessage>,
+        pool: Arc<PgPool>,
+        live_events: LiveEvents,
+    ) -> Self {
         Self {
             client: NtfyClient::default(),
             pool,
             receiver,
             last_sent: HashMap::new(),
+            live_events,
         }
     }
 
@@ -117,6 +131,10 @@ impl NotificationHandler {
                     .publish_notification(msg, &settings.ntfy_topic)
                     .await?;
                 self.last_sent.insert(msg.key(), now());
+                self.live_events.publish(LiveEvent::NotificationSent {
+                    message: msg.display(),
+                    time: now(),
+                });
                 info!("Notification published: {}", msg)
             }
         } else {
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use log::{debug, warn};
+use sqlx::PgPool;
+
+use crate::clients::shelly_client::ShellyClient;
+use crate::db::DbError;
+use crate::domain::{ConsumptionPeriod, PlugEnergy, PlugEnergyReading, RoomEnergy};
+use crate::service::live_events::{LiveEvent, LiveEvents};
+use crate::service::plugs::is_dummy_plug;
+use crate::{db, now};
+
+pub const METER_POLL_MINUTES: u64 = 5;
+
+pub async fn start(pool: Arc<PgPool>, shelly_client: Arc<ShellyClient>, live_events: LiveEvents) {
+    loop {
+        match read_meters(pool.as_ref(), shelly_client.as_ref(), &live_events, &now()).await {
+            Ok(plugs) => debug!("Read the meters of {} plugs", plugs),
+            Err(e) => warn!("Failed to read plug meters: {}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(METER_POLL_MINUTES * 60)).await;
+    }
+}
+
+// Returns the number of plugs read, plugs that can't be reached are skipped until the next time.
+// Plugs without a meter only report if they are on, and get their energy estimated.
+pub async fn read_meters(
+    pool: &PgPool,
+    shelly_client: &ShellyClient,
+    live_events: &LiveEvents,
+    now: &NaiveDateTime,
+) -> Result<usize, DbError> {
+    let mut read = 0;
+    for plug in db::plugs::get_plugs(pool).await? {
+        if is_dummy_plug(&plug) {
+            continue;
+        }
+        let meter = shelly_client.get_meter_values(&plug).await;
+        let status = shelly_client.get_plug_status(&plug).await;
+        if let (Err(e), Err(_)) = (&meter, &status) {
+            warn!("Failed to read the meter of plug {}: {}", plug.name, e);
+            continue;
+        }
+        let (counter, power) = match meter {
+            Ok(meter) => (Some(meter.total as i64), Some(meter.power)),
+            Err(_) => (None, None),
+        };
+        let previous = db::plug_energy::get_latest_reading(pool, &plug.id).await?;
+        let meter = db::plug_energy::get_latest_meter_reading(pool, &plug.id).await?;
+        let reading = PlugEnergyReading::new(
+            &plug,
+            now,
+            counter,
+            power,
+            status.ok().map(|status| status.ison),
+            previous.as_ref(),
+            meter.as_ref(),
+        );
+        db::plug_energy::insert_reading(pool, &reading).await?;
+        if let Some(is_on) = reading.is_on {
+            if previous.and_then(|previous| previous.is_on) != Some(is_on) {
+                live_events.publish(LiveEvent::PlugState {
+                    plug_id: plug.id,
+                    room_id: plug.room_id,
+                    is_on,
+                    time: *now,
+                });
+            }
+        }
+        read += 1;
+    }
+    Ok(read)
+}
+
+// Both dates are inclusive, and extended to whole periods
+pub async fn get_plug_energy(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDate,
+    to: &NaiveDate,
+) -> Result<Vec<PlugEnergy>, DbError> {
+    db::plug_energy::get_plug_energy(
+        pool,
+        period,
+        &period.start_of(from).and_hms(0, 0, 0),
+        &period.next(to).and_hms(0, 0, 0),
+    )
+    .await
+}
+
+// Both dates are inclusive, and extended to whole periods
+pub async fn get_room_energy(
+    pool: &PgPool,
+    period: &ConsumptionPeriod,
+    from: &NaiveDate,
+    to: &NaiveDate,
+) -> Result<Vec<RoomEnergy>, DbError> {
+    db::plug_energy::get_room_energy(
+        pool,
+        period,
+        &period.start_of(from).and_hms(0, 0, 0),
+        &period.next(to).and_hms(0, 0, 0),
+    )
+    .await
+}
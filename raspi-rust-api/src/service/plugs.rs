@@ -141,6 +141,8 @@ mod tests {
             password: "test".to_string(),
             room_id: Uuid::new_v4(),
             scheduled: false,
+            opportunistic: false,
+            rated_watts: None,
         }));
         assert!(!is_dummy_plug(&Plug {
             id: Uuid::new_v4(),
@@ -150,6 +152,8 @@ mod tests {
             password: "test".to_string(),
             room_id: Uuid::new_v4(),
             scheduled: false,
+            opportunistic: false,
+            rated_watts: None,
         }));
     }
 
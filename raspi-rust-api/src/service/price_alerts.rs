@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use itertools::Itertools;
+use log::{error, info, warn};
+use sqlx::PgPool;
+use tokio::sync::mpsc::Sender;
+
+use crate::domain::{NotificationSettings, PriceInfo, PriceLevel};
+use crate::service::notifications::NotificationMessage;
+use crate::service::prices::{average_price, get_price_range, PriceServiceError};
+use crate::{db, now};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PriceSummary {
+    pub date: NaiveDate,
+    pub currency: String,
+    pub cheapest: Option<PriceBlock>,
+    pub most_expensive: Option<PriceBlock>,
+    // Consecutive VeryExpensive intervals, or intervals above the max price
+    pub expensive: Vec<PriceBlock>,
+    // Consecutive intervals below the min price, like negative prices
+    pub cheap: Vec<PriceBlock>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PriceBlock {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub average: f64,
+}
+
+impl PriceSummary {
+    pub fn display(&self) -> String {
+        let time_range = |block: &PriceBlock| {
+            format!(
+                "{}-{}",
+                block.from.format("%H:%M"),
+                block.to.format("%H:%M")
+            )
+        };
+        let mut lines = vec![format!("💡 Prices for {}", self.date.format("%a %d.%m"))];
+        if let Some(block) = &self.cheapest {
+            lines.push(format!(
+                "Cheapest: {}, avg {:.2} {}",
+                time_range(block),
+                block.average,
+                self.currency
+            ));
+        }
+        if let Some(block) = &self.most_expensive {
+            lines.push(format!(
+                "Most expensive: {}, avg {:.2} {}",
+                time_range(block),
+                block.average,
+                self.currency
+            ));
+        }
+        if !self.expensive.is_empty() {
+            lines.push(format!(
+                "⚠️ Very expensive: {}",
+                self.expensive.iter().map(time_range).join(", ")
+            ));
+        }
+        if !self.cheap.is_empty() {
+            lines.push(format!(
+                "🎉 Cheap: {}",
+                self.cheap.iter().map(time_range).join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// Sends tomorrow's price summary once a day, when it is due and the prices are stored
+pub async fn start(pool: Arc<PgPool>, sender: Sender<NotificationMessage>) {
+    let mut last_summary: Option<NaiveDate> = None;
+    loop {
+        match get_due_summary(pool.as_ref(), &now(), last_summary).await {
+            Ok(Some(summary)) => {
+                let date = summary.date;
+                match sender
+                    .send(NotificationMessage::PriceSummary(summary))
+                    .await
+                {
+                    Ok(_) => {
+                        info!("Sent price summary for {}", date);
+                        last_summary = Some(date);
+                    }
+                    Err(e) => error!("NotificationMessage SendError: {}", e),
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to create price summary: {}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+async fn get_due_summary(
+    pool: &PgPool,
+    now: &NaiveDateTime,
+    last_summary: Option<NaiveDate>,
+) -> Result<Option<PriceSummary>, PriceServiceError> {
+    let settings = match db::notification_settings::get_notification_settings(pool).await? {
+        Some(settings) => settings,
+        None => return Ok(None),
+    };
+    let tomorrow = now.date() + chrono::Duration::days(1);
+    if !is_due(now, settings.price_summary_time, last_summary) {
+        return Ok(None);
+    }
+    let from = tomorrow.and_hms(0, 0, 0);
+    let to = from + chrono::Duration::days(1);
+    let prices = get_price_range(pool, &from, &to).await?.prices;
+    if prices.last().map_or(true, |price| price.ends_at() < to) {
+        return Ok(None);
+    }
+    Ok(Some(summarize_prices(tomorrow, &prices, &settings)))
+}
+
+fn is_due(
+    now: &NaiveDateTime,
+    summary_time: Option<NaiveTime>,
+    last_summary: Option<NaiveDate>,
+) -> bool {
+    let tomorrow = now.date() + chrono::Duration::days(1);
+    summary_time.map_or(false, |time| now.time() >= time) && last_summary != Some(tomorrow)
+}
+
+// The prices have to be sorted
+fn summarize_prices(
+    date: NaiveDate,
+    prices: &[PriceInfo],
+    settings: &NotificationSettings,
+) -> PriceSummary {
+    let block = chrono::Duration::hours(settings.price_summary_block_hours.max(1) as i64);
+    let blocks: Vec<PriceBlock> = prices
+        .iter()
+        .filter_map(|price| {
+            let to = price.starts_at + block;
+            average_price(prices, &price.starts_at, &to, |price| price.amount).map(|average| {
+                PriceBlock {
+                    from: price.starts_at,
+                    to,
+                    average,
+                }
+            })
+        })
+        .collect();
+    let by_average = |a: &&PriceBlock, b: &&PriceBlock| a.average.partial_cmp(&b.average).unwrap();
+
+    PriceSummary {
+        date,
+        currency: prices
+            .first()
+            .map(|price| price.currency.to_string())
+            .unwrap_or_default(),
+        cheapest: blocks.iter().min_by(by_average).cloned(),
+        most_expensive: blocks.iter().rev().max_by(by_average).cloned(),
+        expensive: flagged_blocks(prices, |price| {
+            price.level() == PriceLevel::VeryExpensive
+                || settings
+                    .price_alert_max
+                    .map_or(false, |max| price.amount > max)
+        }),
+        cheap: flagged_blocks(prices, |price| price.amount < settings.price_alert_min),
+    }
+}
+
+// Joins consecutive flagged intervals into blocks
+fn flagged_blocks(prices: &[PriceInfo], flagged: impl Fn(&PriceInfo) -> bool) -> Vec<PriceBlock> {
+    let mut blocks: Vec<Vec<&PriceInfo>> = vec![];
+    for price in prices.iter().filter(|price| flagged(price)) {
+        match blocks.last_mut() {
+            Some(block) if block.last().map(|last| last.ends_at()) == Some(price.starts_at) => {
+                block.push(price)
+            }
+            _ => blocks.push(vec![price]),
+        }
+    }
+    blocks
+        .into_iter()
+        .map(|block| {
+            let minutes: i32 = block.iter().map(|price| price.duration_minutes).sum();
+            PriceBlock {
+                from: block[0].starts_at,
+                to: block[block.len() - 1].ends_at(),
+                average: block
+                    .iter()
+                    .map(|price| price.amount * price.duration_minutes as f64)
+                    .sum::<f64>()
+                    / minutes as f64,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveTime};
+
+    use crate::domain::{NotificationSettings, PriceInfo, PriceLevel};
+
+    use super::{is_due, summarize_prices, PriceBlock};
+
+    fn settings() -> NotificationSettings {
+        NotificationSettings {
+            id: None,
+            max_consumption: None,
+            max_consumption_timeout_minutes: 15,
+            ntfy_topic: "topic".to_string(),
+            price_summary_time: Some(NaiveTime::from_hms(14, 0, 0)),
+            price_summary_block_hours: 2,
+            price_alert_max: Some(4.5),
+            price_alert_min: 0.0,
+        }
+    }
+
+    fn prices(amounts: &[f64]) -> Vec<PriceInfo> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(hour, amount)| PriceInfo {
+                amount: *amount,
+                total: None,
+                subsidy: None,
+                currency: "NOK".to_string(),
+                ext_price_level: Some(PriceLevel::Normal),
+                price_level: None,
+                starts_at: NaiveDate::from_ymd(2023, 1, 3).and_hms(0, 0, 0)
+                    + Duration::hours(hour as i64),
+                duration_minutes: 60,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn summary_has_cheapest_and_most_expensive_blocks() {
+        let day = NaiveDate::from_ymd(2023, 1, 3);
+        let mut prices = prices(&[2.0, -1.0, -0.5, 3.0, 4.0, 5.0, 2.0]);
+        prices[3].price_level = Some(PriceLevel::VeryExpensive);
+
+        let summary = summarize_prices(day, &prices, &settings());
+        assert_eq!(
+            summary.cheapest,
+            Some(PriceBlock {
+                from: day.and_hms(1, 0, 0),
+                to: day.and_hms(3, 0, 0),
+                average: -0.75,
+            })
+        );
+        assert_eq!(
+            summary.most_expensive,
+            Some(PriceBlock {
+                from: day.and_hms(4, 0, 0),
+                to: day.and_hms(6, 0, 0),
+                average: 4.5,
+            })
+        );
+        // The VeryExpensive hour and the hour above the max are not consecutive
+        assert_eq!(
+            summary.expensive,
+            vec![
+                PriceBlock {
+                    from: day.and_hms(3, 0, 0),
+                    to: day.and_hms(4, 0, 0),
+                    average: 3.0,
+                },
+                PriceBlock {
+                    from: day.and_hms(5, 0, 0),
+                    to: day.and_hms(6, 0, 0),
+                    average: 5.0,
+                }
+            ]
+        );
+        assert_eq!(
+            summary.cheap,
+            vec![PriceBlock {
+                from: day.and_hms(1, 0, 0),
+                to: day.and_hms(3, 0, 0),
+                average: -0.75,
+            }]
+        );
+        assert_eq!(
+            summary.display(),
+            "💡 Prices for Tue 03.01\n\
+             Cheapest: 01:00-03:00, avg -0.75 NOK\n\
+             Most expensive: 04:00-06:00, avg 4.50 NOK\n\
+             ⚠️ Very expensive: 03:00-04:00, 05:00-06:00\n\
+             🎉 Cheap: 01:00-03:00"
+        );
+    }
+
+    #[test]
+    fn summary_is_due_once_after_summary_time() {
+        let today = NaiveDate::from_ymd(2023, 1, 2);
+        let tomorrow = Some(today + Duration::days(1));
+        let time = Some(NaiveTime::from_hms(14, 0, 0));
+
+        assert!(!is_due(&today.and_hms(13, 59, 0), time, None));
+        assert!(is_due(&today.and_hms(14, 0, 0), time, Some(today)));
+        assert!(!is_due(&today.and_hms(14, 0, 0), time, tomorrow));
+        assert!(!is_due(&today.and_hms(14, 0, 0), None, None));
+    }
+}
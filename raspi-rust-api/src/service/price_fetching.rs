@@ -0,0 +1,228 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::clients::price_provider::PriceProvider;
+use crate::configuration::PriceLevelStrategy;
+use crate::domain::PriceInfo;
+use crate::service::prices::{store_prices, PriceServiceError};
+use crate::{db, now};
+
+const RETRY_MINUTES: i64 = 15;
+pub const BACKFILL_DAYS: i64 = 7;
+
+// Tomorrow's day-ahead prices are published around 13:00 CET
+fn publication_time() -> NaiveTime {
+    NaiveTime::from_hms(13, 0, 0)
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PriceFetchStatus {
+    pub last_attempt: Option<NaiveDateTime>,
+    pub last_success: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub next_fetch: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PriceCoverage {
+    // Stored prices from the start of the backfill period
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub has_tomorrow: bool,
+    pub gaps: Vec<PriceGap>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct PriceGap {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+}
+
+pub async fn get_price_coverage(pool: &PgPool) -> Result<PriceCoverage, PriceServiceError> {
+    let time = now();
+    let from = (time.date() - Duration::days(BACKFILL_DAYS)).and_hms(0, 0, 0);
+    let prices = db::prices::get_prices(
+        pool,
+        &(from - Duration::seconds(1)),
+        &(time.date() + Duration::days(2)).and_hms(0, 0, 0),
+    )
+    .await?;
+    Ok(coverage(&prices, &from, &time))
+}
+
+fn coverage(prices: &[PriceInfo], from: &NaiveDateTime, now: &NaiveDateTime) -> PriceCoverage {
+    let to = prices.iter().map(|price| price.ends_at()).max();
+    PriceCoverage {
+        from: prices.first().map(|price| price.starts_at),
+        to,
+        has_tomorrow: to.map_or(false, |to| {
+            to >= (now.date() + Duration::days(2)).and_hms(0, 0, 0)
+        }),
+        gaps: match to {
+            Some(to) => find_gaps(prices, from, &to),
+            None => vec![],
+        },
+    }
+}
+
+// Periods between from and to without a price, the prices have to be sorted
+fn find_gaps(prices: &[PriceInfo], from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<PriceGap> {
+    let mut gaps = vec![];
+    let mut covered_until = *from;
+    for price in prices {
+        if price.starts_at > covered_until {
+            gaps.push(PriceGap {
+                from: covered_until,
+                to: price.starts_at.min(*to),
+            });
+        }
+        covered_until = covered_until.max(price.ends_at());
+        if covered_until >= *to {
+            break;
+        }
+    }
+    if covered_until < *to {
+        gaps.push(PriceGap {
+            from: covered_until,
+            to: *to,
+        });
+    }
+    gaps
+}
+
+// Fetches at the publication time, and retries until tomorrow's prices are stored
+pub fn next_fetch(now: &NaiveDateTime, has_tomorrow: bool) -> NaiveDateTime {
+    let publication = now.date().and_time(publication_time());
+    if has_tomorrow {
+        publication + Duration::days(1)
+    } else if *now < publication {
+        publication
+    } else {
+        *now + Duration::minutes(RETRY_MINUTES)
+    }
+}
+
+// Returns the number of prices stored
+pub async fn backfill_gaps(
+    price_provider: &dyn PriceProvider,
+    pool: &PgPool,
+    price_level_strategy: &PriceLevelStrategy,
+) -> Result<usize, PriceServiceError> {
+    let mut stored = 0;
+    for gap in get_price_coverage(pool).await?.gaps {
+        info!("Backfilling prices from {} to {}", gap.from, gap.to);
+        let (prices, daily_prices) = match price_provider
+            .get_historical_prices_with_daily(&gap.from, &gap.to)
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                warn!(
+                    "Failed to backfill prices from {} to {}: {}",
+                    gap.from, gap.to, e
+                );
+                continue;
+            }
+        };
+        stored += prices.len();
+        store_prices(pool, price_level_strategy, prices, daily_prices).await?;
+    }
+    Ok(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::domain::{PriceInfo, PriceLevel};
+
+    use super::{coverage, find_gaps, next_fetch, PriceGap};
+
+    fn price(hour: i64) -> PriceInfo {
+        PriceInfo {
+            amount: 1.0,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: NaiveDate::from_ymd(2023, 1, 2).and_hms(0, 0, 0) + Duration::hours(hour),
+            duration_minutes: 60,
+        }
+    }
+
+    #[test]
+    fn finds_gaps_between_prices() {
+        let day = NaiveDate::from_ymd(2023, 1, 2).and_hms(0, 0, 0);
+        let prices = vec![price(1), price(2), price(5)];
+
+        assert_eq!(
+            find_gaps(&prices, &day, &(day + Duration::hours(8))),
+            vec![
+                PriceGap {
+                    from: day,
+                    to: day + Duration::hours(1)
+                },
+                PriceGap {
+                    from: day + Duration::hours(3),
+                    to: day + Duration::hours(5)
+                },
+                PriceGap {
+                    from: day + Duration::hours(6),
+                    to: day + Duration::hours(8)
+                },
+            ]
+        );
+        assert_eq!(
+            find_gaps(
+                &prices,
+                &(day + Duration::hours(1)),
+                &(day + Duration::hours(3))
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn coverage_ends_at_last_price() {
+        let day = NaiveDate::from_ymd(2023, 1, 2).and_hms(0, 0, 0);
+        let prices: Vec<PriceInfo> = (0..48).filter(|hour| *hour != 3).map(price).collect();
+
+        let today = coverage(&prices, &day, &(day + Duration::hours(14)));
+        assert_eq!(today.to, Some(day + Duration::days(2)));
+        assert!(today.has_tomorrow);
+        assert_eq!(
+            today.gaps,
+            vec![PriceGap {
+                from: day + Duration::hours(3),
+                to: day + Duration::hours(4)
+            }]
+        );
+
+        let tomorrow = coverage(&prices, &day, &(day + Duration::hours(26)));
+        assert!(!tomorrow.has_tomorrow);
+        assert!(coverage(&[], &day, &day).gaps.is_empty());
+    }
+
+    #[test]
+    fn fetches_at_publication_until_tomorrow_is_stored() {
+        let day = NaiveDate::from_ymd(2023, 1, 2);
+        let publication = day.and_hms(13, 0, 0);
+
+        assert_eq!(next_fetch(&day.and_hms(9, 0, 0), false), publication);
+        assert_eq!(
+            next_fetch(&day.and_hms(13, 5, 0), false),
+            day.and_hms(13, 20, 0)
+        );
+        assert_eq!(
+            next_fetch(&day.and_hms(13, 20, 0), true),
+            publication + Duration::days(1)
+        );
+        assert_eq!(
+            next_fetch(&day.and_hms(9, 0, 0), true),
+            publication + Duration::days(1)
+        );
+    }
+}
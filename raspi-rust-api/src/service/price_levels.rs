@@ -0,0 +1,523 @@
+use std::ops::Sub;
+
+use chrono::Duration;
+use itertools::Itertools;
+use log::{info, warn};
+
+use crate::configuration::PriceLevelStrategy;
+use crate::domain::{DailyPrice, GridTariff, PriceInfo, PriceLevel, PriceSettings};
+use crate::now;
+
+pub fn calculate_price_levels(
+    strategy: &PriceLevelStrategy,
+    daily_prices: Vec<DailyPrice>,
+    new_prices: Vec<PriceInfo>,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
+) -> Vec<PriceInfo> {
+    let prices = strategy_levels(
+        strategy,
+        daily_prices,
+        new_prices,
+        price_settings,
+        grid_tariffs,
+    );
+    match price_settings {
+        Some(settings) => negative_price_levels(prices, settings),
+        None => prices,
+    }
+}
+
+fn strategy_levels(
+    strategy: &PriceLevelStrategy,
+    daily_prices: Vec<DailyPrice>,
+    new_prices: Vec<PriceInfo>,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
+) -> Vec<PriceInfo> {
+    let uses_daily_prices = matches!(
+        strategy,
+        PriceLevelStrategy::MedianRatio { .. } | PriceLevelStrategy::Rolling { .. }
+    );
+    if uses_daily_prices && daily_prices.is_empty() {
+        warn!("Got no daily prices");
+        return new_prices;
+    }
+
+    if uses_daily_prices
+        && daily_prices[daily_prices.len() - 1].starts_at < now().sub(Duration::days(2))
+    {
+        warn!("Got old daily prices");
+        return new_prices;
+    }
+
+    set_price_levels(
+        strategy,
+        &daily_prices,
+        new_prices,
+        price_settings,
+        grid_tariffs,
+    )
+}
+
+fn set_price_levels(
+    strategy: &PriceLevelStrategy,
+    daily_prices: &[DailyPrice],
+    new_prices: Vec<PriceInfo>,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
+) -> Vec<PriceInfo> {
+    match strategy {
+        PriceLevelStrategy::Provider => new_prices,
+        PriceLevelStrategy::MedianRatio {
+            thresholds,
+            provider_weight,
+        } => match median(daily_prices) {
+            Some(median) => {
+                info!("Calculating prices with daily median of: {}", &median);
+                ratio_levels(
+                    new_prices,
+                    median,
+                    thresholds,
+                    *provider_weight,
+                    price_settings,
+                    grid_tariffs,
+                )
+            }
+            None => new_prices,
+        },
+        PriceLevelStrategy::Percentile { thresholds } => percentile_levels(
+            new_prices,
+            thresholds,
+            price_settings.map_or(false, |settings| settings.use_total_price),
+        ),
+        PriceLevelStrategy::Rolling { days, thresholds } => {
+            match rolling_average(daily_prices, *days) {
+                Some(average) => {
+                    info!(
+                        "Calculating prices with {} day average of: {}",
+                        days, &average
+                    );
+                    ratio_levels(
+                        new_prices,
+                        average,
+                        thresholds,
+                        0.0,
+                        price_settings,
+                        grid_tariffs,
+                    )
+                }
+                None => new_prices,
+            }
+        }
+    }
+}
+
+// Prices in negative price mode are always VeryCheap, whatever the strategy says
+fn negative_price_levels(prices: Vec<PriceInfo>, price_settings: &PriceSettings) -> Vec<PriceInfo> {
+    prices
+        .into_iter()
+        .map(
+            |price| match price_settings.is_negative_price(price.amount) {
+                true => PriceInfo {
+                    price_level: Some(PriceLevel::VeryCheap),
+                    ..price
+                },
+                false => price,
+            },
+        )
+        .collect()
+}
+
+fn median(daily_prices: &[DailyPrice]) -> Option<f64> {
+    let sorted: Vec<f64> = daily_prices
+        .iter()
+        .map(|daily_price| daily_price.total)
+        .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+        .collect();
+    positive(*sorted.get(sorted.len() / 2)?)
+}
+
+fn rolling_average(daily_prices: &[DailyPrice], days: usize) -> Option<f64> {
+    let latest: Vec<f64> = daily_prices
+        .iter()
+        .sorted_by_key(|daily_price| daily_price.starts_at)
+        .rev()
+        .take(days)
+        .map(|daily_price| daily_price.total)
+        .collect();
+    if latest.is_empty() {
+        return None;
+    }
+    positive(latest.iter().sum::<f64>() / latest.len() as f64)
+}
+
+fn positive(reference: f64) -> Option<f64> {
+    if reference <= 0.0 {
+        warn!("Got 0 or negative average daily price");
+        return None;
+    }
+    Some(reference)
+}
+
+fn ratio_levels(
+    new_prices: Vec<PriceInfo>,
+    reference: f64,
+    thresholds: &[f64; 4],
+    provider_weight: f64,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
+) -> Vec<PriceInfo> {
+    new_prices
+        .into_iter()
+        .map(|mut price_info| {
+            let ratio = price_ratio(&price_info, reference, price_settings, grid_tariffs);
+            price_info.price_level = Some(blended_level(
+                &price_info,
+                PriceLevel::from_thresholds(ratio, thresholds),
+                provider_weight,
+            ));
+            price_info
+        })
+        .collect()
+}
+
+// Ranks each price among the prices starting on the same day, the cheapest is 0 and the most expensive 1
+fn percentile_levels(
+    new_prices: Vec<PriceInfo>,
+    thresholds: &[f64; 4],
+    use_total_price: bool,
+) -> Vec<PriceInfo> {
+    let price_of = |price_info: &PriceInfo| match use_total_price {
+        true => price_info.total.unwrap_or(price_info.amount),
+        false => price_info.amount,
+    };
+    let days = new_prices
+        .iter()
+        .into_group_map_by(|price_info| price_info.starts_at.date());
+    let ranks: Vec<f64> = new_prices
+        .iter()
+        .map(|price_info| {
+            let day = &days[&price_info.starts_at.date()];
+            let cheaper = day
+                .iter()
+                .filter(|other| price_of(other) < price_of(price_info))
+                .count();
+            match day.len() {
+                1 => 0.5,
+                len => cheaper as f64 / (len - 1) as f64,
+            }
+        })
+        .collect();
+    new_prices
+        .into_iter()
+        .zip(ranks)
+        .map(|(mut price_info, rank)| {
+            price_info.price_level = Some(PriceLevel::from_thresholds(rank, thresholds));
+            price_info
+        })
+        .collect()
+}
+
+// With the total price, the hour is compared to its total price at the reference spot price,
+// so that grid tariffs make an hour cheaper or more expensive
+fn price_ratio(
+    price_info: &PriceInfo,
+    reference: f64,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
+) -> f64 {
+    match price_settings
+        .filter(|settings| settings.use_total_price)
+        .zip(price_info.total)
+    {
+        Some((settings, total)) => {
+            total
+                / settings.total_price(
+                    reference,
+                    GridTariff::amount_at(grid_tariffs, &price_info.starts_at),
+                )
+        }
+        None => price_info.amount / reference,
+    }
+}
+
+// Prices without a provider level keep the calculated level
+fn blended_level(price_info: &PriceInfo, level: PriceLevel, provider_weight: f64) -> PriceLevel {
+    match price_info.ext_price_level {
+        Some(provider_level) => {
+            let index = level.index_of() as f64;
+            let provider_index = provider_level.index_of() as f64;
+            let actual_index =
+                ((1.0 - provider_weight) * index + provider_weight * provider_index).round();
+            PriceLevel::from(actual_index as i32)
+        }
+        None => level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+
+    use crate::configuration::PriceLevelStrategy;
+    use crate::domain::{DailyPrice, GridTariff, PriceInfo, PriceLevel, PriceSettings};
+
+    use super::{calculate_price_levels, price_ratio, set_price_levels};
+
+    fn price(amount: f64, total: Option<f64>) -> PriceInfo {
+        PriceInfo {
+            amount,
+            total,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: NaiveDate::from_ymd(2023, 1, 2).and_hms(3, 0, 0),
+            duration_minutes: 60,
+        }
+    }
+
+    fn hours(amounts: &[f64]) -> Vec<PriceInfo> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, amount)| {
+                let mut hour = price(*amount, None);
+                hour.starts_at += chrono::Duration::hours(i as i64);
+                hour
+            })
+            .collect()
+    }
+
+    fn daily_prices(totals: &[f64]) -> Vec<DailyPrice> {
+        totals
+            .iter()
+            .enumerate()
+            .map(|(i, total)| DailyPrice {
+                total: *total,
+                starts_at: NaiveDate::from_ymd(2023, 1, 1).and_hms(0, 0, 0)
+                    - chrono::Duration::days(i as i64),
+            })
+            .collect()
+    }
+
+    fn levels(prices: Vec<PriceInfo>) -> Vec<Option<PriceLevel>> {
+        prices.into_iter().map(|p| p.price_level).collect()
+    }
+
+    #[test]
+    fn price_ratio_uses_total_price_when_configured() {
+        let mut settings = PriceSettings {
+            use_total_price: false,
+            ..Default::default()
+        };
+        let night = GridTariff::new(
+            "night",
+            1.0,
+            vec![],
+            vec![],
+            (NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(6, 0, 0)),
+        )
+        .expect("Failed to create tariff");
+        let price = price(2.0, Some(3.0));
+
+        assert_eq!(
+            price_ratio(&price, 1.0, Some(&settings), &[night.clone()]),
+            2.0
+        );
+        settings.use_total_price = true;
+        assert_eq!(price_ratio(&price, 1.0, Some(&settings), &[night]), 1.5);
+        assert_eq!(price_ratio(&price, 1.0, None, &[]), 2.0);
+    }
+
+    #[test]
+    fn provider_strategy_keeps_provider_level() {
+        let prices = set_price_levels(
+            &PriceLevelStrategy::Provider,
+            &[],
+            hours(&[0.1, 10.0]),
+            None,
+            &[],
+        );
+        assert_eq!(levels(prices), vec![None, None]);
+    }
+
+    #[test]
+    fn median_ratio_strategy_blends_with_provider_level() {
+        let prices = set_price_levels(
+            &PriceLevelStrategy::default(),
+            &daily_prices(&[1.0, 2.0, 3.0]),
+            hours(&[0.5, 2.0, 4.0]),
+            None,
+            &[],
+        );
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::Cheap),
+                Some(PriceLevel::Normal),
+                Some(PriceLevel::Expensive)
+            ]
+        );
+
+        let unblended = PriceLevelStrategy::MedianRatio {
+            thresholds: [0.3, 0.5, 1.5, 1.75],
+            provider_weight: 0.0,
+        };
+        let prices = set_price_levels(
+            &unblended,
+            &daily_prices(&[1.0, 2.0, 3.0]),
+            hours(&[0.5, 2.0, 4.0]),
+            None,
+            &[],
+        );
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::VeryCheap),
+                Some(PriceLevel::Normal),
+                Some(PriceLevel::VeryExpensive)
+            ]
+        );
+    }
+
+    #[test]
+    fn median_ratio_strategy_keeps_calculated_level_without_provider_level() {
+        let entsoe_prices = hours(&[0.5, 2.0, 4.0])
+            .into_iter()
+            .map(|mut hour| {
+                hour.ext_price_level = None;
+                hour
+            })
+            .collect();
+        let prices = set_price_levels(
+            &PriceLevelStrategy::default(),
+            &daily_prices(&[1.0, 2.0, 3.0]),
+            entsoe_prices,
+            None,
+            &[],
+        );
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::VeryCheap),
+                Some(PriceLevel::Normal),
+                Some(PriceLevel::VeryExpensive)
+            ]
+        );
+    }
+
+    #[test]
+    fn median_ratio_strategy_skips_non_positive_median() {
+        let prices = set_price_levels(
+            &PriceLevelStrategy::default(),
+            &daily_prices(&[-1.0, 0.0, 1.0]),
+            hours(&[0.5]),
+            None,
+            &[],
+        );
+        assert_eq!(levels(prices), vec![None]);
+    }
+
+    #[test]
+    fn percentile_strategy_ranks_prices_within_day() {
+        let strategy = PriceLevelStrategy::Percentile {
+            thresholds: [0.2, 0.4, 0.6, 0.8],
+        };
+        let mut prices = hours(&[5.0, 1.0, 3.0, 4.0, 2.0]);
+        let mut next_day = price(100.0, None);
+        next_day.starts_at += chrono::Duration::days(1);
+        prices.push(next_day);
+
+        let prices = set_price_levels(&strategy, &[], prices, None, &[]);
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::VeryExpensive),
+                Some(PriceLevel::VeryCheap),
+                Some(PriceLevel::Normal),
+                Some(PriceLevel::Expensive),
+                Some(PriceLevel::Cheap),
+                Some(PriceLevel::Normal)
+            ]
+        );
+    }
+
+    #[test]
+    fn percentile_strategy_uses_total_price_when_configured() {
+        let strategy = PriceLevelStrategy::Percentile {
+            thresholds: [0.2, 0.4, 0.6, 0.8],
+        };
+        let settings = PriceSettings {
+            use_total_price: true,
+            ..Default::default()
+        };
+        let mut prices = vec![price(1.0, Some(4.0)), price(2.0, Some(3.0))];
+        prices[1].starts_at += chrono::Duration::hours(1);
+
+        let prices = set_price_levels(&strategy, &[], prices, Some(&settings), &[]);
+        assert_eq!(
+            levels(prices),
+            vec![Some(PriceLevel::VeryExpensive), Some(PriceLevel::VeryCheap)]
+        );
+    }
+
+    #[test]
+    fn rolling_strategy_compares_with_average_of_latest_days() {
+        let strategy = PriceLevelStrategy::Rolling {
+            days: 2,
+            thresholds: [0.5, 0.85, 1.15, 1.5],
+        };
+        // The latest two days average 2, the older day is left out
+        let prices = set_price_levels(
+            &strategy,
+            &daily_prices(&[1.0, 3.0, 100.0]),
+            hours(&[0.5, 2.0, 4.0]),
+            None,
+            &[],
+        );
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::VeryCheap),
+                Some(PriceLevel::Normal),
+                Some(PriceLevel::VeryExpensive)
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_prices_are_very_cheap_in_negative_price_mode() {
+        let mut settings = PriceSettings {
+            negative_price_mode: false,
+            negative_price_threshold: 0.0,
+            ..Default::default()
+        };
+        let prices = calculate_price_levels(
+            &PriceLevelStrategy::Provider,
+            vec![],
+            hours(&[-0.5, 0.0, 0.5]),
+            Some(&settings),
+            &[],
+        );
+        assert_eq!(levels(prices), vec![None, None, None]);
+
+        settings.negative_price_mode = true;
+        let prices = calculate_price_levels(
+            &PriceLevelStrategy::Provider,
+            vec![],
+            hours(&[-0.5, 0.0, 0.5]),
+            Some(&settings),
+            &[],
+        );
+        assert_eq!(
+            levels(prices),
+            vec![
+                Some(PriceLevel::VeryCheap),
+                Some(PriceLevel::VeryCheap),
+                None
+            ]
+        );
+    }
+}
@@ -1,98 +1,440 @@
 use std::ops::Sub;
 
-use chrono::Duration;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Timelike};
 use itertools::Itertools;
-use log::{info, warn};
+use log::warn;
+use serde::Serialize;
 use sqlx::PgPool;
 use thiserror::Error;
 
-use crate::clients::tibber_client::{TibberClient, TibberClientError, TibberDailyPrice};
+use crate::clients::price_provider::{PriceProvider, PriceProviderError};
+use crate::configuration::PriceLevelStrategy;
 use crate::db::DbError;
-use crate::domain::{PriceInfo, PriceLevel};
+use crate::domain::{Consumption, DailyPrice, GridTariff, PriceInfo, PriceLevel, PriceSettings};
+use crate::service::price_levels::calculate_price_levels;
 use crate::{db, now};
 
 #[derive(Error, Debug)]
 pub enum PriceServiceError {
-    #[error("Tibber Client Error {0}")]
-    TibberClientError(#[from] TibberClientError),
+    #[error("Price Provider Error {0}")]
+    PriceProviderError(#[from] PriceProviderError),
     #[error("DbError {0}")]
     DbError(#[from] DbError),
 }
 
 pub async fn get_current_price(
-    tibber_client: &TibberClient,
+    price_provider: &dyn PriceProvider,
     pool: &PgPool,
 ) -> Result<PriceInfo, PriceServiceError> {
     if let Some(price) = db::prices::get_price(pool, &now()).await? {
         Ok(price)
     } else {
-        warn!("Couldn't find price for timestamp in DB, fetching from Tibber");
-        Ok(tibber_client.get_current_price().await?)
+        warn!(
+            "Couldn't find price for timestamp in DB, fetching from {}",
+            price_provider.name()
+        );
+        Ok(price_provider.get_current_price().await?)
     }
 }
 
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CurrentPrice {
+    #[serde(flatten)]
+    pub price: PriceInfo,
+    // Set when no price was found, and the price is a fallback
+    pub degraded: bool,
+}
+
+// Keeps the control loop running without prices, by falling back to the configured level,
+// or the level of the latest stored price at the same time of day
+pub async fn get_current_price_or_fallback(
+    price_provider: &dyn PriceProvider,
+    pool: &PgPool,
+) -> CurrentPrice {
+    match get_current_price(price_provider, pool).await {
+        Ok(price) => CurrentPrice {
+            price,
+            degraded: false,
+        },
+        Err(e) => {
+            let price = fallback_price(pool, &now()).await;
+            warn!(
+                "Running degraded, failed to get price: {}. Using fallback price level: {}",
+                e,
+                price.level()
+            );
+            CurrentPrice {
+                price,
+                degraded: true,
+            }
+        }
+    }
+}
+
+async fn fallback_price(pool: &PgPool, time: &NaiveDateTime) -> PriceInfo {
+    let fallback_level = db::price_settings::get_price_settings(pool)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|settings| settings.fallback_price_level);
+    let last_known = match fallback_level {
+        Some(_) => None,
+        None => db::prices::get_latest_price_at_time_of_day(pool, time)
+            .await
+            .ok()
+            .flatten(),
+    };
+    last_known.unwrap_or_else(|| {
+        let level = fallback_level.unwrap_or(PriceLevel::Normal);
+        // The amount is unknown, only the level is used
+        PriceInfo {
+            amount: 0.0,
+            total: None,
+            subsidy: None,
+            currency: String::new(),
+            ext_price_level: Some(level),
+            price_level: Some(level),
+            starts_at: time.date().and_hms(time.hour(), 0, 0),
+            duration_minutes: 60,
+        }
+    })
+}
+
 pub async fn fetch_and_store_prices(
-    tibber_client: &TibberClient,
+    price_provider: &dyn PriceProvider,
     pool: &PgPool,
+    price_level_strategy: &PriceLevelStrategy,
 ) -> Result<(), PriceServiceError> {
-    let new_prices = tibber_client.get_hourly_prices().await?;
-    let daily_prices = tibber_client.get_daily_prices().await?;
-    db::prices::insert_prices(pool, &calculate_price_levels(daily_prices, new_prices)).await?;
-    Ok(())
+    let (new_prices, daily_prices) = price_provider.get_prices_with_daily().await?;
+    store_prices(pool, price_level_strategy, new_prices, daily_prices).await
 }
 
-pub fn calculate_price_levels(
-    daily_prices: Vec<TibberDailyPrice>,
+// Sets the total price, subsidy and price level before storing the prices
+pub async fn store_prices(
+    pool: &PgPool,
+    price_level_strategy: &PriceLevelStrategy,
     new_prices: Vec<PriceInfo>,
+    daily_prices: Vec<DailyPrice>,
+) -> Result<(), PriceServiceError> {
+    let price_settings = db::price_settings::get_price_settings(pool).await?;
+    let grid_tariffs = db::price_settings::get_grid_tariffs(pool).await?;
+    let new_prices = apply_price_settings(new_prices, price_settings.as_ref(), &grid_tariffs);
+    db::prices::insert_prices(
+        pool,
+        &calculate_price_levels(
+            price_level_strategy,
+            daily_prices,
+            new_prices,
+            price_settings.as_ref(),
+            &grid_tariffs,
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+pub fn apply_price_settings(
+    prices: Vec<PriceInfo>,
+    price_settings: Option<&PriceSettings>,
+    grid_tariffs: &[GridTariff],
 ) -> Vec<PriceInfo> {
-    if daily_prices.is_empty() {
-        warn!("Got no daily prices from tibber");
-        return new_prices;
+    match price_settings {
+        Some(price_settings) => prices
+            .into_iter()
+            .map(|mut price_info| {
+                price_info.total = Some(price_settings.total_price(
+                    price_info.amount,
+                    GridTariff::amount_at(grid_tariffs, &price_info.starts_at),
+                ));
+                price_info.subsidy = price_settings.subsidy(price_info.amount);
+                price_info
+            })
+            .collect(),
+        None => prices,
     }
+}
 
-    if daily_prices[daily_prices.len() - 1].starts_at.naive_local() < now().sub(Duration::days(2)) {
-        warn!("Got old daily prices from Tibber");
-        return new_prices;
+// Cost of each hour with the stored prices, None when the consumption or the price is unknown
+pub async fn get_consumption_costs(
+    pool: &PgPool,
+    consumption: &[Consumption],
+) -> Result<Vec<Option<f64>>, PriceServiceError> {
+    let price_settings = db::price_settings::get_price_settings(pool).await?;
+    let prices = match (
+        consumption.iter().map(|c| c.from).min(),
+        consumption.iter().map(|c| c.to).max(),
+    ) {
+        (Some(from), Some(to)) => {
+            db::prices::get_prices(pool, &from.sub(Duration::seconds(1)), &to).await?
+        }
+        _ => vec![],
+    };
+
+    Ok(consumption
+        .iter()
+        .map(|c| {
+            let price = average_price(&prices, &c.from, &c.to, |price| {
+                price.cost_price(price_settings.as_ref())
+            })?;
+            Some(c.kwh? * price)
+        })
+        .collect())
+}
+
+// Weighted by how much of the period each price interval covers, None unless the whole period has
+// prices. Used to price hourly consumption with prices of any resolution
+pub fn average_price(
+    prices: &[PriceInfo],
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+    price_of: impl Fn(&PriceInfo) -> f64,
+) -> Option<f64> {
+    let (sum, minutes) = prices
+        .iter()
+        .filter(|price| price.starts_at < *to && price.ends_at() > *from)
+        .fold((0.0, 0), |(sum, minutes), price| {
+            let overlap = (price.ends_at().min(*to) - price.starts_at.max(*from)).num_minutes();
+            (sum + price_of(price) * overlap as f64, minutes + overlap)
+        });
+    match minutes > 0 && minutes == (*to - *from).num_minutes() {
+        true => Some(sum / minutes as f64),
+        false => None,
     }
+}
 
-    let sorted: Vec<TibberDailyPrice> = daily_prices
-        .into_iter()
-        .sorted_by(|a, b| a.total.partial_cmp(&b.total).unwrap())
-        .collect();
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SubsidySummary {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub kwh: f64,
+    pub cost_before_subsidy: f64,
+    pub subsidy: f64,
+    pub cost: f64,
+}
 
-    let median = sorted.get(sorted.len() / 2).unwrap().total;
+pub async fn get_subsidy(
+    pool: &PgPool,
+    consumption: &[Consumption],
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<SubsidySummary, PriceServiceError> {
+    let price_settings = db::price_settings::get_price_settings(pool).await?;
+    let prices = db::prices::get_prices(pool, &from.sub(Duration::seconds(1)), to).await?;
+    Ok(summarize_subsidy(
+        consumption,
+        &prices,
+        price_settings.as_ref(),
+        from,
+        to,
+    ))
+}
 
-    if median <= 0.0 {
-        warn!("Got 0 or negative average daily price from Tibber");
-        return new_prices;
+// Hours without consumption or price are left out
+fn summarize_subsidy(
+    consumption: &[Consumption],
+    prices: &[PriceInfo],
+    price_settings: Option<&PriceSettings>,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> SubsidySummary {
+    let hours: Vec<(f64, f64, f64)> = consumption
+        .iter()
+        .filter(|c| *from <= c.from && c.from < *to)
+        .filter_map(|c| {
+            let kwh = c.kwh?;
+            let cost_price = average_price(prices, &c.from, &c.to, |price| {
+                price.cost_price(price_settings)
+            })?;
+            let subsidy = average_price(prices, &c.from, &c.to, |price| {
+                price.subsidy_deduction(price_settings)
+            })?;
+            Some((kwh, kwh * cost_price, kwh * subsidy))
+        })
+        .collect();
+    let kwh = hours.iter().map(|(kwh, _, _)| kwh).sum();
+    let cost = hours.iter().map(|(_, cost, _)| cost).sum::<f64>();
+    let subsidy = hours.iter().map(|(_, _, subsidy)| subsidy).sum::<f64>();
+    SubsidySummary {
+        from: *from,
+        to: *to,
+        kwh,
+        cost_before_subsidy: cost + subsidy,
+        subsidy,
+        cost,
     }
+}
 
-    info!("Calculating prices with daily median of: {}", &median);
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PriceRange {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub prices: Vec<PriceInfo>,
+    pub days: Vec<DailyPriceSummary>,
+}
 
-    new_prices
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DailyPriceSummary {
+    pub date: NaiveDate,
+    pub currency: String,
+    pub min: f64,
+    pub max: f64,
+    // Weighted by the length of the price intervals
+    pub average: f64,
+}
+
+// Prices starting in the period, days without prices are left out
+pub async fn get_price_range(
+    pool: &PgPool,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> Result<PriceRange, PriceServiceError> {
+    let prices = db::prices::get_prices(pool, &from.sub(Duration::seconds(1)), to).await?;
+    Ok(PriceRange {
+        from: *from,
+        to: *to,
+        days: summarize_days(&prices),
+        prices,
+    })
+}
+
+fn summarize_days(prices: &[PriceInfo]) -> Vec<DailyPriceSummary> {
+    prices
+        .iter()
+        .group_by(|price| price.starts_at.date())
         .into_iter()
-        .map(|mut price_info| {
-            price_info.price_level = Some(get_price_level(&price_info, median));
-            price_info
+        .map(|(date, day)| {
+            let day: Vec<&PriceInfo> = day.collect();
+            let minutes: i32 = day.iter().map(|price| price.duration_minutes).sum();
+            DailyPriceSummary {
+                date,
+                currency: day[0].currency.to_string(),
+                min: day
+                    .iter()
+                    .map(|price| price.amount)
+                    .fold(f64::MAX, f64::min),
+                max: day
+                    .iter()
+                    .map(|price| price.amount)
+                    .fold(f64::MIN, f64::max),
+                average: day
+                    .iter()
+                    .map(|price| price.amount * price.duration_minutes as f64)
+                    .sum::<f64>()
+                    / minutes as f64,
+            }
         })
         .collect()
 }
 
-fn get_price_level(price_info: &PriceInfo, median: f64) -> PriceLevel {
-    let ratio = price_info.amount / median;
-    let daily_level = if ratio < 0.5 {
-        PriceLevel::VeryCheap
-    } else if ratio < 0.85 {
-        PriceLevel::Cheap
-    } else if ratio < 1.15 {
-        PriceLevel::Normal
-    } else if ratio < 1.5 {
-        PriceLevel::Expensive
-    } else {
-        PriceLevel::VeryExpensive
-    };
-    let daily_index = daily_level.index_of() as f64;
-    let hourly_index = price_info.ext_price_level.index_of() as f64;
-    let actual_index = ((2.0 * daily_index + hourly_index) / 3.0).round() as i32;
-    PriceLevel::from(actual_index)
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::domain::{Consumption, PriceInfo, PriceLevel, PriceSettings};
+
+    use super::{average_price, summarize_days, summarize_subsidy};
+
+    fn price(amount: f64, total: Option<f64>) -> PriceInfo {
+        PriceInfo {
+            amount,
+            total,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: NaiveDate::from_ymd(2023, 1, 2).and_hms(3, 0, 0),
+            duration_minutes: 60,
+        }
+    }
+
+    #[test]
+    fn subsidy_summary_sums_hours_in_period() {
+        let mut subsidized = price(2.0, Some(3.0));
+        subsidized.subsidy = Some(0.5);
+        let mut outside = price(2.0, None);
+        outside.starts_at = subsidized.starts_at + chrono::Duration::hours(1);
+        let consumption: Vec<Consumption> = [&subsidized, &outside]
+            .iter()
+            .map(|price| Consumption {
+                from: price.starts_at,
+                to: price.starts_at + chrono::Duration::hours(1),
+                kwh: Some(2.0),
+                cost: 0.0,
+            })
+            .collect();
+
+        let summary = summarize_subsidy(
+            &consumption,
+            &[subsidized.clone(), outside.clone()],
+            Some(&PriceSettings {
+                use_total_price: true,
+                ..Default::default()
+            }),
+            &subsidized.starts_at,
+            &outside.starts_at,
+        );
+        assert_eq!(summary.kwh, 2.0);
+        assert_eq!(summary.subsidy, 1.0);
+        assert_eq!(summary.cost, 5.0);
+        assert_eq!(summary.cost_before_subsidy, 6.0);
+    }
+
+    #[test]
+    fn average_price_weights_intervals_covering_period() {
+        let quarters: Vec<PriceInfo> = [1.0, 2.0, 3.0, 6.0]
+            .iter()
+            .enumerate()
+            .map(|(i, amount)| {
+                let mut quarter = price(*amount, None);
+                quarter.starts_at += chrono::Duration::minutes(15 * i as i64);
+                quarter.duration_minutes = 15;
+                quarter
+            })
+            .collect();
+        let from = quarters[0].starts_at;
+
+        let hour = average_price(
+            &quarters,
+            &from,
+            &(from + chrono::Duration::hours(1)),
+            |p| p.amount,
+        );
+        assert_eq!(hour, Some(3.0));
+        let half_hour = average_price(
+            &quarters,
+            &(from + chrono::Duration::minutes(30)),
+            &(from + chrono::Duration::hours(1)),
+            |p| p.amount,
+        );
+        assert_eq!(half_hour, Some(4.5));
+        let uncovered = average_price(
+            &quarters,
+            &from,
+            &(from + chrono::Duration::hours(2)),
+            |p| p.amount,
+        );
+        assert_eq!(uncovered, None);
+    }
+
+    #[test]
+    fn daily_summary_per_day_of_prices() {
+        let mut prices: Vec<PriceInfo> = [1.0, 4.0, 2.0, 3.0]
+            .iter()
+            .map(|amount| price(*amount, None))
+            .collect();
+        prices[1].starts_at += chrono::Duration::minutes(60);
+        prices[1].duration_minutes = 30;
+        prices[2].starts_at += chrono::Duration::minutes(90);
+        prices[2].duration_minutes = 30;
+        prices[3].starts_at += chrono::Duration::days(1);
+
+        let days = summarize_days(&prices);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, NaiveDate::from_ymd(2023, 1, 2));
+        assert_eq!(days[0].min, 1.0);
+        assert_eq!(days[0].max, 4.0);
+        assert_eq!(days[0].average, 2.0);
+        assert_eq!(days[1].date, NaiveDate::from_ymd(2023, 1, 3));
+        assert_eq!(days[1].average, 3.0);
+    }
 }
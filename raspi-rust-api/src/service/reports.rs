@@ -0,0 +1,220 @@
+use std::ops::Sub;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::db;
+use crate::db::DbError;
+use crate::domain::{Consumption, ConsumptionPeriod, PriceInfo};
+use crate::service::prices::average_price;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ConsumptionReport {
+    pub kwh: f64,
+    pub cost: f64,
+    pub hours: usize,
+    // Spot price weighted by the consumption of each hour
+    pub weighted_spot_price: Option<f64>,
+    // Plain average of the spot price over the same hours
+    pub average_spot_price: Option<f64>,
+    // Saved by using more energy in cheap hours, compared with using it evenly
+    pub savings: Option<f64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DayReport {
+    pub date: NaiveDate,
+    #[serde(flatten)]
+    pub consumption: ConsumptionReport,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MonthReport {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub month_to_date: ConsumptionReport,
+    // Month-to-date extrapolated to the whole month, from the hours with consumption so far
+    pub projected_kwh: Option<f64>,
+    pub projected_cost: Option<f64>,
+    pub days: Vec<DayReport>,
+}
+
+impl MonthReport {
+    // One row per day, then the month-to-date and projected totals
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("date,kwh,cost,hours,weighted_spot_price,average_spot_price,savings\n");
+        for day in &self.days {
+            csv.push_str(&csv_row(&day.date.to_string(), &day.consumption));
+        }
+        csv.push_str(&csv_row("month_to_date", &self.month_to_date));
+        csv.push_str(&format!(
+            "projected,{},{},,,,\n",
+            csv_value(self.projected_kwh),
+            csv_value(self.projected_cost)
+        ));
+        csv
+    }
+}
+
+fn csv_row(label: &str, report: &ConsumptionReport) -> String {
+    format!(
+        "{},{},{},{},{},{},{}\n",
+        label,
+        report.kwh,
+        report.cost,
+        report.hours,
+        csv_value(report.weighted_spot_price),
+        csv_value(report.average_spot_price),
+        csv_value(report.savings)
+    )
+}
+
+fn csv_value(value: Option<f64>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+// The month of the date, with the consumption stored so far
+pub async fn get_month_report(pool: &PgPool, date: &NaiveDate) -> Result<MonthReport, DbError> {
+    let from = ConsumptionPeriod::Month.start_of(date).and_hms(0, 0, 0);
+    let to = ConsumptionPeriod::Month.next(date).and_hms(0, 0, 0);
+    let consumption = db::consumption::get_consumption(pool, &from, &to).await?;
+    let prices = db::prices::get_prices(pool, &from.sub(Duration::seconds(1)), &to).await?;
+    Ok(month_report(&from, &to, &consumption, &prices))
+}
+
+fn month_report(
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+    consumption: &[Consumption],
+    prices: &[PriceInfo],
+) -> MonthReport {
+    let hours: Vec<&Consumption> = consumption
+        .iter()
+        .filter(|c| c.kwh.is_some() && *from <= c.from && c.from < *to)
+        .collect();
+    let month_to_date = report(&hours, prices);
+    let elapsed = hours
+        .iter()
+        .map(|c| c.to)
+        .max()
+        .map(|latest| latest - *from);
+    let (projected_kwh, projected_cost) = match elapsed {
+        Some(elapsed) if elapsed.num_minutes() > 0 => {
+            let factor = (*to - *from).num_minutes() as f64 / elapsed.num_minutes() as f64;
+            (
+                Some(month_to_date.kwh * factor),
+                Some(month_to_date.cost * factor),
+            )
+        }
+        _ => (None, None),
+    };
+    let days = hours
+        .iter()
+        .group_by(|c| c.from.date())
+        .into_iter()
+        .map(|(date, day)| DayReport {
+            date,
+            consumption: report(&day.copied().collect::<Vec<&Consumption>>(), prices),
+        })
+        .collect();
+    MonthReport {
+        from: *from,
+        to: *to,
+        month_to_date,
+        projected_kwh,
+        projected_cost,
+        days,
+    }
+}
+
+// The spot prices only include hours with a stored price
+fn report(hours: &[&Consumption], prices: &[PriceInfo]) -> ConsumptionReport {
+    let priced: Vec<(f64, f64)> = hours
+        .iter()
+        .filter_map(|c| {
+            let spot_price = average_price(prices, &c.from, &c.to, |price| price.amount)?;
+            Some((c.kwh?, spot_price))
+        })
+        .collect();
+    let priced_kwh: f64 = priced.iter().map(|(kwh, _)| kwh).sum();
+    let weighted_spot_price = match priced_kwh > 0.0 {
+        true => Some(priced.iter().map(|(kwh, price)| kwh * price).sum::<f64>() / priced_kwh),
+        false => None,
+    };
+    let average_spot_price = match priced.is_empty() {
+        true => None,
+        false => Some(priced.iter().map(|(_, price)| price).sum::<f64>() / priced.len() as f64),
+    };
+    ConsumptionReport {
+        kwh: hours.iter().filter_map(|c| c.kwh).sum(),
+        cost: hours.iter().map(|c| c.cost).sum(),
+        hours: hours.len(),
+        weighted_spot_price,
+        average_spot_price,
+        savings: weighted_spot_price
+            .zip(average_spot_price)
+            .map(|(weighted, average)| (average - weighted) * priced_kwh),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::domain::{Consumption, PriceInfo, PriceLevel};
+
+    use super::month_report;
+
+    #[test]
+    fn month_report_compares_weighted_and_average_price() {
+        let from = NaiveDate::from_ymd(2023, 4, 1).and_hms(0, 0, 0);
+        let to = NaiveDate::from_ymd(2023, 5, 1).and_hms(0, 0, 0);
+        // The first 3 days, with 3 kWh in the cheap hour and 1 kWh in the expensive hour
+        let hours: Vec<(i64, f64, f64)> = (0..3)
+            .flat_map(|day| [(day * 24, 3.0, 1.0), (day * 24 + 23, 1.0, 4.0)])
+            .collect();
+        let consumption: Vec<Consumption> = hours
+            .iter()
+            .map(|(hour, kwh, price)| Consumption {
+                from: from + Duration::hours(*hour),
+                to: from + Duration::hours(hour + 1),
+                kwh: Some(*kwh),
+                cost: kwh * price,
+            })
+            .collect();
+        let prices: Vec<PriceInfo> = hours
+            .iter()
+            .map(|(hour, _, price)| PriceInfo {
+                amount: *price,
+                total: None,
+                subsidy: None,
+                currency: "NOK".to_string(),
+                ext_price_level: Some(PriceLevel::Normal),
+                price_level: None,
+                starts_at: from + Duration::hours(*hour),
+                duration_minutes: 60,
+            })
+            .collect();
+
+        let report = month_report(&from, &to, &consumption, &prices);
+        assert_eq!(report.month_to_date.kwh, 12.0);
+        assert_eq!(report.month_to_date.cost, 21.0);
+        assert_eq!(report.month_to_date.hours, 6);
+        assert_eq!(report.month_to_date.weighted_spot_price, Some(1.75));
+        assert_eq!(report.month_to_date.average_spot_price, Some(2.5));
+        assert_eq!(report.month_to_date.savings, Some(9.0));
+        assert_eq!(report.projected_kwh, Some(120.0));
+        assert_eq!(report.projected_cost, Some(210.0));
+        assert_eq!(report.days.len(), 3);
+        assert_eq!(report.days[0].date, from.date());
+        assert_eq!(report.days[0].consumption.kwh, 4.0);
+
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 6);
+        assert_eq!(csv.lines().nth(1), Some("2023-04-01,4,7,2,1.75,2.5,3"));
+        assert_eq!(csv.lines().last(), Some("projected,120,210,,,,"));
+    }
+}
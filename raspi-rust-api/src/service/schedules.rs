@@ -0,0 +1,250 @@
+use std::ops::{Add, Sub};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db;
+use crate::db::DbError;
+use crate::domain::{
+    PriceInfo, PriceLevel, Room, Schedule, TempAction, TempActionResponse, TempActionType,
+};
+
+#[derive(Error, Debug)]
+pub enum ScheduleServiceError {
+    #[error("DbError {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Serialize)]
+pub struct RoomDayPreview {
+    pub room_id: Uuid,
+    pub room_name: String,
+    // One entry per price interval, or per hour when there are no prices
+    pub hours: Vec<HourPreview>,
+}
+
+#[derive(Serialize)]
+pub struct HourPreview {
+    pub starts_at: NaiveDateTime,
+    pub price: Option<PriceInfo>,
+    pub price_level: Option<PriceLevel>,
+    pub schedule_id: Option<Uuid>,
+    pub schedule_temp: Option<f64>,
+    pub temp_action: Option<TempActionResponse>,
+    pub min_temp: Option<f64>,
+    // None when the room is off, or when it is forced on without a target temperature
+    pub target_temp: Option<f64>,
+    pub forced_on: bool,
+}
+
+pub async fn preview_day(
+    pool: &PgPool,
+    date: &NaiveDate,
+) -> Result<Vec<RoomDayPreview>, ScheduleServiceError> {
+    let day_start = NaiveDateTime::new(*date, NaiveTime::from_hms(0, 0, 0));
+    let prices = db::prices::get_prices(
+        pool,
+        &day_start.sub(Duration::seconds(1)),
+        &day_start.add(Duration::days(1)),
+    )
+    .await?;
+    let rooms = db::rooms::get_rooms(pool).await?;
+    let schedules = db::schedules::get_schedules(pool).await?;
+    let active_profile = db::schedule_profiles::get_active_schedule_profile(pool).await?;
+    let temp_actions = db::temp_actions::get_temp_actions(pool).await?;
+    let recurring_temp_actions = db::temp_actions::get_recurring_temp_actions(pool).await?;
+    let interval_minutes = prices
+        .iter()
+        .map(|price| price.duration_minutes as i64)
+        .min()
+        .unwrap_or(60);
+
+    Ok(rooms
+        .into_iter()
+        .map(|room| {
+            let hours = (0..24 * 60 / interval_minutes)
+                .map(|interval| {
+                    let time = day_start.add(Duration::minutes(interval * interval_minutes));
+                    let profile_id = active_profile.profile_id_at(&time);
+                    let schedule = schedules.iter().find(|s| {
+                        s.profile_id == profile_id
+                            && s.room_ids.contains(&room.id)
+                            && s.is_active_at(&time)
+                    });
+                    let active_temp_actions: Vec<TempAction> = temp_actions
+                        .iter()
+                        .filter(|a| a.is_active_at(&time))
+                        .cloned()
+                        .chain(
+                            recurring_temp_actions
+                                .iter()
+                                .filter_map(|a| a.occurrence_at(&time)),
+                        )
+                        .collect();
+                    let temp_action =
+                        TempAction::effective_for_room(&active_temp_actions, &room.id);
+                    let price = prices.iter().find(|p| p.contains(&time));
+                    preview_hour(&time, &room, price, schedule, temp_action)
+                })
+                .collect();
+            RoomDayPreview {
+                room_id: room.id,
+                room_name: room.name,
+                hours,
+            }
+        })
+        .collect())
+}
+
+fn preview_hour(
+    time: &NaiveDateTime,
+    room: &Room,
+    price: Option<&PriceInfo>,
+    schedule: Option<&Schedule>,
+    temp_action: Option<&TempAction>,
+) -> HourPreview {
+    let price_level = price.map(|p| p.level());
+    let schedule_temp = match (schedule, price_level) {
+        (Some(schedule), Some(level)) => Some(schedule.get_temp(&level)),
+        _ => None,
+    };
+
+    // Mirrors the precedence in WorkHandler::get_action: min temp, temp action, then schedule
+    let (target_temp, forced_on) = match temp_action.map(|a| a.action_type) {
+        Some(TempActionType::OFF) => (None, false),
+        Some(TempActionType::ON(None)) => (None, true),
+        Some(TempActionType::ON(Some(temp))) => (max_temp(Some(temp), schedule_temp), false),
+        None => (schedule_temp, false),
+    };
+    let target_temp = match forced_on {
+        true => None,
+        false => max_temp(room.min_temp, target_temp),
+    };
+
+    HourPreview {
+        starts_at: *time,
+        price: price.cloned(),
+        price_level,
+        schedule_id: schedule.map(|s| s.id),
+        schedule_temp,
+        temp_action: temp_action.map(|a| a.clone().into()),
+        min_temp: room.min_temp,
+        target_temp,
+        forced_on,
+    }
+}
+
+fn max_temp(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+    use uuid::Uuid;
+
+    use crate::domain::{PriceInfo, PriceLevel, Room, Schedule, TempAction, TempActionType};
+
+    use super::preview_hour;
+
+    fn time() -> NaiveDateTime {
+        NaiveDateTime::new(
+            NaiveDate::from_weekday_of_month(2023, 7, Weekday::Mon, 1),
+            NaiveTime::from_hms(6, 0, 0),
+        )
+    }
+
+    fn room(min_temp: Option<f64>) -> Room {
+        Room {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            min_temp,
+            max_temp: None,
+        }
+    }
+
+    fn price(level: PriceLevel) -> PriceInfo {
+        PriceInfo {
+            amount: 1.0,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(level),
+            price_level: None,
+            starts_at: time(),
+            duration_minutes: 60,
+        }
+    }
+
+    fn schedule(room: &Room) -> Schedule {
+        Schedule::new(
+            HashMap::from([(PriceLevel::Cheap, 22.0), (PriceLevel::Expensive, 18.0)]),
+            vec![Weekday::Mon],
+            vec![(NaiveTime::from_hms(5, 0, 0), NaiveTime::from_hms(8, 0, 0))],
+            vec![room.id],
+            Uuid::new_v4(),
+        )
+        .expect("Failed to create schedule")
+    }
+
+    fn temp_action(room: &Room, action_type: TempActionType) -> TempAction {
+        TempAction::new(&None, &time(), &action_type, vec![room.id])
+    }
+
+    #[test]
+    fn uses_schedule_temp_for_price_level() {
+        let room = room(None);
+        let preview = preview_hour(
+            &time(),
+            &room,
+            Some(&price(PriceLevel::Expensive)),
+            Some(&schedule(&room)),
+            None,
+        );
+        assert_eq!(preview.schedule_temp, Some(18.0));
+        assert_eq!(preview.target_temp, Some(18.0));
+        assert!(!preview.forced_on);
+    }
+
+    #[test]
+    fn min_temp_overrides_off_action() {
+        let room = room(Some(10.0));
+        let action = temp_action(&room, TempActionType::OFF);
+        let preview = preview_hour(
+            &time(),
+            &room,
+            Some(&price(PriceLevel::Cheap)),
+            Some(&schedule(&room)),
+            Some(&action),
+        );
+        assert_eq!(preview.schedule_temp, Some(22.0));
+        assert_eq!(preview.target_temp, Some(10.0));
+    }
+
+    #[test]
+    fn on_action_raises_target_temp() {
+        let room = room(None);
+        let action = temp_action(&room, TempActionType::ON(Some(25.0)));
+        let preview = preview_hour(
+            &time(),
+            &room,
+            Some(&price(PriceLevel::Cheap)),
+            Some(&schedule(&room)),
+            Some(&action),
+        );
+        assert_eq!(preview.target_temp, Some(25.0));
+
+        let action = temp_action(&room, TempActionType::ON(None));
+        let preview = preview_hour(&time(), &room, None, None, Some(&action));
+        assert_eq!(preview.target_temp, None);
+        assert!(preview.forced_on);
+    }
+}
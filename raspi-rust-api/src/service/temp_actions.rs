@@ -0,0 +1,51 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::db;
+use crate::db::DbError;
+use crate::domain::{TempAction, TempActionResponse};
+
+#[derive(Error, Debug)]
+pub enum TempActionServiceError {
+    #[error("DbError {0}")]
+    DbError(#[from] DbError),
+}
+
+#[derive(Serialize)]
+pub struct EffectiveTempAction {
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub temp_action: Option<TempActionResponse>,
+}
+
+// Recurring actions are included through their current occurrence
+pub async fn get_effective_temp_actions(
+    pool: &PgPool,
+    time: &NaiveDateTime,
+) -> Result<Vec<EffectiveTempAction>, TempActionServiceError> {
+    let mut active: Vec<TempAction> = db::temp_actions::get_temp_actions(pool)
+        .await?
+        .into_iter()
+        .filter(|a| a.is_active_at(time))
+        .collect();
+    active.extend(
+        db::temp_actions::get_recurring_temp_actions(pool)
+            .await?
+            .iter()
+            .filter_map(|a| a.occurrence_at(time)),
+    );
+
+    Ok(db::rooms::get_rooms(pool)
+        .await?
+        .into_iter()
+        .map(|room| EffectiveTempAction {
+            temp_action: TempAction::effective_for_room(&active, &room.id)
+                .map(|a| a.clone().into()),
+            room_id: room.id,
+            room_name: room.name,
+        })
+        .collect())
+}
@@ -11,19 +11,22 @@ use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{Receiver, Sender};
 use uuid::Uuid;
 
+use crate::clients::price_provider::{PriceProvider, PriceProviderError};
 use crate::clients::shelly_client::{ShellyClient, ShellyClientError};
-use crate::clients::tibber_client::{TibberClient, TibberClientError};
 use crate::db::DbError;
 use crate::domain::{
-    ActionType, Plug, PriceInfo, Room, TempAction, TempActionType, TemperatureLog, WorkMessage,
+    ActionType, Plug, PriceInfo, Room, TempAction, TempActionEndReason, TempActionType,
+    TemperatureLog, WorkMessage,
 };
+use crate::service::live_events::{LiveEvent, LiveEvents};
 use crate::service::plugs::is_dummy_plug;
+use crate::service::prices::CurrentPrice;
 use crate::{db, now, service};
 
 #[derive(Error, Debug)]
 pub enum WorkHandlerError {
     #[error("PriceError: {0}")]
-    PriceError(#[from] TibberClientError),
+    PriceError(#[from] PriceProviderError),
     #[error("DbError: {0}")]
     DbError(#[from] DbError),
     #[error("SendError")]
@@ -38,27 +41,30 @@ pub enum WorkHandlerError {
 
 pub struct WorkHandler {
     shelly_client: Arc<ShellyClient>,
-    tibber_client: Arc<TibberClient>,
+    price_provider: Arc<dyn PriceProvider>,
     pool: Arc<PgPool>,
     sender: Sender<WorkMessage>,
     receiver: Receiver<WorkMessage>,
+    live_events: LiveEvents,
     poll_interval_mins: u64,
 }
 
 impl WorkHandler {
     pub fn new(
         shelly_client: Arc<ShellyClient>,
-        tibber_client: Arc<TibberClient>,
+        price_provider: Arc<dyn PriceProvider>,
         sender: Sender<WorkMessage>,
         receiver: Receiver<WorkMessage>,
         pool: Arc<PgPool>,
+        live_events: LiveEvents,
     ) -> Self {
         WorkHandler {
             shelly_client,
-            tibber_client,
+            price_provider,
             pool,
             sender,
             receiver,
+            live_events,
             poll_interval_mins: 1,
         }
     }
@@ -78,22 +84,17 @@ impl WorkHandler {
                 match message {
                     WorkMessage::REFRESH | WorkMessage::POLL => {
                         let now = now();
-                        match service::prices::get_current_price(
-                            self.tibber_client.as_ref(),
+                        let current_price = service::prices::get_current_price_or_fallback(
+                            self.price_provider.as_ref(),
                             self.pool.as_ref(),
                         )
-                        .await
-                        {
-                            Ok(price) => {
-                                match self.main_handler(&price, &now).await {
-                                    Ok(_) => {
-                                        debug!("Work handled.")
-                                    }
-                                    Err(e) => error!("Work failed, error: {}", e),
-                                };
+                        .await;
+                        match self.main_handler(&current_price, &now).await {
+                            Ok(_) => {
+                                debug!("Work handled.")
                             }
-                            Err(_) => error!("Failed to get price"),
-                        }
+                            Err(e) => error!("Work failed, error: {}", e),
+                        };
                     }
                     WorkMessage::TEMP(room_id, temp) => {
                         match self.temperature_handler(&room_id, &temp).await {
@@ -170,6 +171,12 @@ impl WorkHandler {
                         continue;
                     }
                     self.shelly_client.execute_action(plug, action).await?;
+                    self.live_events.publish(LiveEvent::PlugCommanded {
+                        plug_id: plug.id,
+                        room_id: plug.room_id,
+                        action: *action,
+                        time: now(),
+                    });
                 }
 
                 Ok(())
@@ -192,6 +199,11 @@ impl WorkHandler {
             },
         )
         .await?;
+        self.live_events.publish(LiveEvent::Temperature {
+            room_id: *room_id,
+            temp: *temp,
+            time: now,
+        });
         match self.sender.send(WorkMessage::REFRESH).await {
             Ok(_) => Ok(()),
             Err(_) => Err(WorkHandlerError::SendError),
@@ -200,9 +212,10 @@ impl WorkHandler {
 
     pub async fn main_handler(
         &self,
-        price: &PriceInfo,
+        current_price: &CurrentPrice,
         now: &NaiveDateTime,
     ) -> Result<(), WorkHandlerError> {
+        let price = &current_price.price;
         debug!("Current local time: {}", &now);
         debug!("Current price: {}", price);
 
@@ -210,44 +223,144 @@ impl WorkHandler {
         let mut temp_actions = vec![];
         for action in all_actions {
             if action.expires_at < *now {
-                db::temp_actions::delete_temp_action(&self.pool, &action.id).await?;
-            } else if action.starts_at.map_or(true, |t| t <= *now) {
+                db::temp_actions::archive_temp_action(
+                    &self.pool,
+                    &action.id,
+                    now,
+                    &TempActionEndReason::EXPIRED,
+                )
+                .await?;
+                self.live_events.publish(LiveEvent::TempActionExpired {
+                    temp_action_id: action.id,
+                    room_ids: action.room_ids.clone(),
+                    time: *now,
+                });
+            } else if action.is_active_at(now) {
                 temp_actions.push(action)
             }
         }
 
+        for action in db::temp_actions::get_recurring_temp_actions(&self.pool).await? {
+            if action.has_ended(&now.date()) {
+                db::temp_actions::archive_recurring_temp_action(
+                    &self.pool,
+                    &action.id,
+                    now,
+                    &TempActionEndReason::EXPIRED,
+                )
+                .await?;
+                self.live_events.publish(LiveEvent::TempActionExpired {
+                    temp_action_id: action.id,
+                    room_ids: action.room_ids.clone(),
+                    time: *now,
+                });
+            } else if let Some(occurrence) = action.occurrence_at(now) {
+                temp_actions.push(occurrence)
+            }
+        }
+
         debug!("Found temp actions {:?}", temp_actions);
 
+        let mut plug_overrides = vec![];
+        let mut expired_override_plug_ids = vec![];
+        for plug_override in db::plug_overrides::get_plug_overrides(&self.pool).await? {
+            if plug_override.expires_at < *now {
+                db::plug_overrides::delete_plug_override(&self.pool, &plug_override.id).await?;
+                expired_override_plug_ids.extend(plug_override.plug_ids);
+            } else if plug_override.is_active_at(now) {
+                plug_overrides.push(plug_override)
+            }
+        }
+
+        debug!("Found plug overrides {:?}", plug_overrides);
+
+        // Fallback prices are left out, since their amount is unknown or from another day
+        let price_settings = db::price_settings::get_price_settings(&self.pool).await?;
+        let negative_price = !current_price.degraded
+            && price_settings
+                .as_ref()
+                .map_or(false, |settings| settings.is_negative_price(price.amount));
+        if negative_price {
+            info!("Negative price mode is active, price: {}", price.amount);
+        }
+        // Read from the stored prices, so that plugs are also turned off after a restart
+        let previous_price = db::prices::get_price(
+            &self.pool,
+            &(price.starts_at - chrono::Duration::minutes(1)),
+        )
+        .await?;
+        let negative_price_ended = !negative_price
+            && previous_price
+                .zip(price_settings.as_ref())
+                .map_or(false, |(previous, settings)| {
+                    settings.is_negative_price(previous.amount)
+                });
+
         let rooms = db::rooms::get_rooms(&self.pool).await?;
         let current_temps = db::temperature_logs::get_current_temps(&self.pool, &rooms).await?;
 
         debug!("Current temperatures: {:?}", &current_temps);
 
         for room in rooms {
-            let room_temp_actions: Vec<TempAction> = temp_actions
-                .clone()
-                .into_iter()
-                .filter(|a| a.room_ids.contains(&room.id))
-                .sorted_by(|a, b| Ord::cmp(&a.expires_at, &b.expires_at))
-                .collect();
+            let temp_action = TempAction::effective_for_room(&temp_actions, &room.id);
 
             let action = self
-                .get_action(now, price, &room, &current_temps, room_temp_actions.first())
+                .get_action(
+                    now,
+                    price,
+                    &room,
+                    &current_temps,
+                    temp_action,
+                    negative_price,
+                )
                 .await?;
+            self.live_events.publish(LiveEvent::RoomDecision {
+                room_id: room.id,
+                action,
+                time: *now,
+            });
 
             let room_plugs = db::plugs::get_room_plugs(&self.pool, &room.id).await?;
 
             for plug in room_plugs {
-                if plug.scheduled {
-                    if is_dummy_plug(&plug) {
-                        debug!("Dummy plug, skipping");
-                        continue;
+                // Overrides also apply to plugs that are not scheduled
+                let plug_action = match plug_overrides
+                    .iter()
+                    .filter(|o| o.plug_ids.contains(&plug.id))
+                    .sorted_by(|a, b| Ord::cmp(&a.expires_at, &b.expires_at))
+                    .next()
+                {
+                    Some(plug_override) => plug_override.action,
+                    None if plug.opportunistic && negative_price => {
+                        opportunistic_action(&room, &current_temps, temp_action)
                     }
-                    match self.shelly_client.execute_action(&plug, &action).await {
-                        Ok(_) => debug!("Turned plug {} {}", plug.name, action),
-                        Err(e) => {
-                            error!("Failed to turn plug {} {}, error: {}", plug.name, action, e)
-                        }
+                    None if plug.scheduled => action,
+                    // Unscheduled plugs have nothing to go back to, so they are turned off once
+                    None if expired_override_plug_ids.contains(&plug.id) => ActionType::OFF,
+                    // Otherwise left alone, so that they can be controlled manually. Opportunistic
+                    // plugs are turned off during the interval after negative prices
+                    None if plug.opportunistic && negative_price_ended => ActionType::OFF,
+                    None => continue,
+                };
+                if is_dummy_plug(&plug) {
+                    debug!("Dummy plug, skipping");
+                    continue;
+                }
+                match self.shelly_client.execute_action(&plug, &plug_action).await {
+                    Ok(_) => {
+                        debug!("Turned plug {} {}", plug.name, plug_action);
+                        self.live_events.publish(LiveEvent::PlugCommanded {
+                            plug_id: plug.id,
+                            room_id: room.id,
+                            action: plug_action,
+                            time: *now,
+                        });
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to turn plug {} {}, error: {}",
+                            plug.name, plug_action, e
+                        )
                     }
                 }
             }
@@ -263,6 +376,7 @@ impl WorkHandler {
         room: &Room,
         current_temps: &HashMap<Uuid, TemperatureLog>,
         temp_action_opt: Option<&TempAction>,
+        negative_price: bool,
     ) -> Result<ActionType, DbError> {
         let matching_schedule =
             db::schedules::get_matching_schedule(&self.pool, &room.id, now).await?;
@@ -293,6 +407,16 @@ impl WorkHandler {
             }
         }
 
+        if negative_price {
+            if let Some(max_temp) = room.max_temp {
+                return if current_temp.temp < max_temp {
+                    Ok(ActionType::ON)
+                } else {
+                    Ok(ActionType::OFF)
+                };
+            }
+        }
+
         if let Some(schedule) = matching_schedule {
             if current_temp.temp < schedule.get_temp(&price.level()) {
                 Ok(ActionType::ON)
@@ -304,3 +428,19 @@ impl WorkHandler {
         }
     }
 }
+
+// Opportunistic plugs run during negative prices until the room reaches its max temp,
+// unless the room is turned off by a temp action
+fn opportunistic_action(
+    room: &Room,
+    current_temps: &HashMap<Uuid, TemperatureLog>,
+    temp_action_opt: Option<&TempAction>,
+) -> ActionType {
+    if let Some(TempActionType::OFF) = temp_action_opt.map(|temp_action| &temp_action.action_type) {
+        return ActionType::OFF;
+    }
+    match (room.max_temp, current_temps.get(&room.id)) {
+        (Some(max_temp), Some(current_temp)) if current_temp.temp >= max_temp => ActionType::OFF,
+        _ => ActionType::ON,
+    }
+}
@@ -6,11 +6,14 @@ use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio::task::{self, JoinHandle};
 
 use rust_home::api::start;
+use rust_home::configuration::ConsumptionCacheSettings;
 use rust_home::clients::shelly_client::ShellyClient;
 use rust_home::clients::tibber_client::TibberClient;
 use rust_home::domain::WorkMessage;
 use rust_home::service::consumption_cache::ConsumptionCache;
+use rust_home::service::live_events::LiveEvents;
 use rust_home::service::notifications::NotificationMessage;
+use rust_home::service::price_fetching::PriceFetchStatus;
 
 use crate::configuration::DatabaseTestConfig;
 
@@ -33,7 +36,12 @@ async fn spin_up_test_server() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<(
         work_tx.clone(),
         tibber_client,
         Arc::new(ShellyClient::default()),
-        Arc::new(RwLock::new(ConsumptionCache::new(notification_tx.clone()))),
+        Arc::new(RwLock::new(ConsumptionCache::new(
+            notification_tx.clone(),
+            &ConsumptionCacheSettings::default(),
+        ))),
+        Arc::new(RwLock::new(PriceFetchStatus::default())),
+        LiveEvents::default(),
         Arc::new(test_config.db_config.pool),
     )
     .await;
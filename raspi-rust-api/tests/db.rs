@@ -8,14 +8,24 @@ use sqlx::PgPool;
 use sqlx::types::ipnetwork::IpNetwork;
 use testcontainers::clients::Cli;
 use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use configuration::DatabaseTestConfig;
-use rust_home::db;
-use rust_home::db::{plugs, rooms, schedules, temp_actions, temperature_logs};
+use rust_home::clients::shelly_client::ShellyClient;
+use rust_home::configuration::ConsumptionHistorySettings;
+use rust_home::{db, service};
+use rust_home::db::{
+    plug_overrides, plugs, rooms, schedule_profiles, schedules, temp_actions, temperature_logs,
+};
 use rust_home::domain::{
-    Button, NotificationSettings, Plug, PriceInfo, PriceLevel, Room, Schedule, TempAction,
-    TempActionType, TemperatureLog, TempSensor,
+    ActionType, ActiveScheduleProfile, Button, Consumption, ConsumptionPeriod,
+    ConsumptionResolution, GridTariff, LiveConsumption, NotificationSettings, Plug, PlugOverride,
+    PowerAggregate, PriceInfo, PriceLevel, PriceSettings, RecurringTempAction, Room, Schedule,
+    ScheduleProfile, TempAction, TempActionEndReason, TempActionSource, TempActionType,
+    TemperatureLog, TempSensor,
 };
+use rust_home::service::live_events::LiveEvents;
 
 mod configuration;
 mod setup;
@@ -50,7 +60,7 @@ fn temperature_log(room_id: Uuid) -> TemperatureLog {
 }
 
 async fn create_room(pool: &PgPool) {
-    rooms::create_room(pool, "test_room", &None)
+    rooms::create_room(pool, "test_room", &None, &None)
         .await
         .expect("Could not insert room");
 }
@@ -76,6 +86,7 @@ async fn rooms() {
             id: result_room.id,
             name: "test2".to_string(),
             min_temp: Some(20.0),
+            max_temp: Some(24.0),
         },
     )
     .await
@@ -85,6 +96,7 @@ async fn rooms() {
     let result_room = result[0].clone();
     assert_eq!(result_room.name, "test2");
     assert_eq!(result_room.min_temp, Some(20.0));
+    assert_eq!(result_room.max_temp, Some(24.0));
 }
 
 #[tokio::test]
@@ -145,6 +157,8 @@ async fn can_update_plug() {
         password: "new_pass".to_string(),
         room_id,
         scheduled: false,
+        opportunistic: true,
+        rated_watts: Some(1200),
     };
 
     plugs::update_plug(&pool, updated_plug.clone())
@@ -195,14 +209,14 @@ async fn schedules() {
 
     create_room(&pool).await;
 
-    rooms::create_room(&pool, "test_room_2", &None)
+    rooms::create_room(&pool, "test_room_2", &None, &None)
         .await
         .expect("Could not insert room");
 
     let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
     let room_id_2 = rooms[1].clone().id;
 
-    let new_schedule = setup::schedule(vec![&rooms[0]]);
+    let new_schedule = setup::schedule(&pool, vec![&rooms[0]]).await;
 
     schedules::create_schedule(&pool, new_schedule.clone())
         .await
@@ -223,6 +237,7 @@ async fn schedules() {
         days: vec![Weekday::Fri],
         time_windows: vec![(NaiveTime::from_hms(1, 0, 0), NaiveTime::from_hms(2, 0, 0))],
         room_ids: vec![room_id_2],
+        profile_id: stored_schedule.profile_id,
     };
 
     schedules::update_schedule(&pool, update_expected.clone())
@@ -276,7 +291,7 @@ async fn can_delete_schedule() {
 
     let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
 
-    let new_schedule = setup::schedule(vec![&rooms[0]]);
+    let new_schedule = setup::schedule(&pool, vec![&rooms[0]]).await;
 
     schedules::create_schedule(&pool, new_schedule.clone())
         .await
@@ -309,7 +324,7 @@ async fn schedules_constraints() {
 
     let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
 
-    let new_schedule = setup::schedule(vec![&rooms[0]]);
+    let new_schedule = setup::schedule(&pool, vec![&rooms[0]]).await;
 
     schedules::create_schedule(&pool, new_schedule.clone())
         .await
@@ -335,6 +350,7 @@ async fn schedules_constraints() {
             days: stored[0].days.clone(),
             time_windows,
             room_ids: stored[0].room_ids.clone(),
+            profile_id: stored[0].profile_id,
         },
     )
     .await;
@@ -342,6 +358,87 @@ async fn schedules_constraints() {
     assert!(duplicate_times.is_err());
 }
 
+#[tokio::test]
+async fn schedule_profiles() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+
+    create_room(&pool).await;
+
+    let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
+    let room_id = rooms[0].id;
+
+    let profiles = schedule_profiles::get_schedule_profiles(&pool)
+        .await
+        .expect("Can't get profiles");
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].name, "Normal");
+    let normal = profiles[0].clone();
+
+    let holiday = ScheduleProfile::new("Holiday");
+    schedule_profiles::create_schedule_profile(&pool, &holiday)
+        .await
+        .expect("Could not insert profile");
+
+    let new_schedule = setup::schedule(&pool, vec![&rooms[0]]).await;
+    assert_eq!(new_schedule.profile_id, normal.id);
+    schedules::create_schedule(&pool, new_schedule.clone())
+        .await
+        .expect("Could not insert schedule");
+
+    let time = NaiveDateTime::new(
+        NaiveDate::from_weekday_of_month(2020, 11, Weekday::Mon, 1),
+        NaiveTime::from_hms(1, 30, 0),
+    );
+
+    let matching = schedules::get_matching_schedule(&pool, &room_id, &time)
+        .await
+        .expect("Couldn't fetch schedule");
+    assert_eq!(matching, Some(new_schedule.clone()));
+
+    schedule_profiles::set_active_schedule_profile(
+        &pool,
+        &ActiveScheduleProfile {
+            profile_id: holiday.id,
+            starts_at: None,
+            ends_at: Some(time.add(Duration::days(7))),
+            fallback_profile_id: Some(normal.id),
+        },
+    )
+    .await
+    .expect("Could not activate profile");
+
+    let matching = schedules::get_matching_schedule(&pool, &room_id, &time)
+        .await
+        .expect("Couldn't fetch schedule");
+    assert_eq!(matching, None);
+
+    let matching = schedules::get_matching_schedule(&pool, &room_id, &time.add(Duration::days(7)))
+        .await
+        .expect("Couldn't fetch schedule");
+    assert_eq!(matching, Some(new_schedule.clone()));
+
+    let should_fail = schedule_profiles::delete_schedule_profile(&pool, &normal.id).await;
+    assert!(should_fail.is_err());
+
+    schedule_profiles::update_schedule_profile(
+        &pool,
+        &ScheduleProfile {
+            id: holiday.id,
+            name: "Home office".to_string(),
+        },
+    )
+    .await
+    .expect("Could not update profile");
+
+    let profiles = schedule_profiles::get_schedule_profiles(&pool)
+        .await
+        .expect("Can't get profiles");
+    assert!(profiles.iter().any(|p| p.name == "Home office"));
+}
+
 #[tokio::test]
 async fn temp_actions() {
     let docker = Cli::default();
@@ -351,7 +448,7 @@ async fn temp_actions() {
 
     create_room(&pool).await;
 
-    rooms::create_room(&pool, "test_room_2", &None)
+    rooms::create_room(&pool, "test_room_2", &None, &None)
         .await
         .expect("Could not insert room");
 
@@ -379,11 +476,15 @@ async fn temp_actions() {
         action_type: TempActionType::ON(Some(23.0)),
         expires_at: stored_action.expires_at,
         starts_at: Some(NaiveDateTime::from_timestamp(1666291900, 0)),
+        priority: 2,
+        created_at: stored_action.created_at,
+        created_by: stored_action.created_by,
     };
 
-    temp_actions::update_temp_action(&pool, updated_action.clone())
+    let now = Utc::now().naive_local();
+    temp_actions::replace_temp_action(&pool, updated_action.clone(), &now)
         .await
-        .expect("Failed to update temp action");
+        .expect("Failed to replace temp action");
 
     let after_update = temp_actions::get_temp_actions(&pool)
         .await
@@ -393,12 +494,233 @@ async fn temp_actions() {
 
     assert_eq!(after_update_action, updated_action);
 
-    temp_actions::delete_temp_action(&pool, &after_update_action.id)
-        .await
-        .expect("Failed to delete temp action");
+    temp_actions::archive_temp_action(
+        &pool,
+        &after_update_action.id,
+        &now,
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .expect("Failed to archive temp action");
     let after_delete = temp_actions::get_temp_actions(&pool)
         .await
         .expect("Failed to get temp actions");
+    assert_eq!(after_delete.len(), 0);
+
+    let history = temp_actions::get_temp_action_history(
+        &pool,
+        &now.sub(Duration::hours(1)),
+        &now.add(Duration::hours(1)),
+    )
+    .await
+        .expect("Failed to get temp action history");
+    assert_eq!(history.len(), 2)
+}
+
+#[tokio::test]
+async fn temp_action_history() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+
+    create_room(&pool).await;
+
+    let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
+    let room_id = rooms[0].clone().id;
+
+    let mut new_action = temp_action(vec![room_id]);
+    new_action.created_by = TempActionSource::AUTOMATION;
+    temp_actions::create_temp_action(&pool, new_action.clone())
+        .await
+        .expect("Failed to insert temp action");
+
+    let replaced_at = NaiveDateTime::from_timestamp(1666291000, 0);
+    let replacement = TempAction {
+        action_type: TempActionType::OFF,
+        ..new_action.clone()
+    };
+    temp_actions::replace_temp_action(&pool, replacement.clone(), &replaced_at)
+        .await
+        .expect("Failed to replace temp action");
+
+    let cancelled_at = replaced_at.add(Duration::minutes(10));
+    temp_actions::archive_temp_action(
+        &pool,
+        &new_action.id,
+        &cancelled_at,
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .expect("Failed to archive temp action");
+
+    let remaining = temp_actions::get_temp_actions(&pool)
+        .await
+        .expect("Failed to get temp actions");
+    assert_eq!(remaining.len(), 0);
+
+    let history = temp_actions::get_temp_action_history(
+        &pool,
+        &replaced_at,
+        &cancelled_at.add(Duration::seconds(1)),
+    )
+    .await
+    .expect("Failed to get temp action history");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].temp_action, new_action);
+    assert_eq!(history[0].end_reason, TempActionEndReason::REPLACED);
+    assert_eq!(history[0].ended_at, replaced_at);
+    assert_eq!(history[1].temp_action, replacement);
+    assert_eq!(history[1].end_reason, TempActionEndReason::CANCELLED);
+
+    let before = temp_actions::get_temp_action_history(&pool, &cancelled_at, &cancelled_at)
+        .await
+        .expect("Failed to get temp action history");
+    assert_eq!(before.len(), 0);
+}
+
+#[tokio::test]
+async fn recurring_temp_actions() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+
+    create_room(&pool).await;
+
+    let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
+    let room_id = rooms[0].clone().id;
+
+    let new_action = RecurringTempAction::new(
+        vec![room_id],
+        &TempActionType::ON(Some(22.0)),
+        vec![Weekday::Sat, Weekday::Sun],
+        (NaiveTime::from_hms(8, 0, 0), NaiveTime::from_hms(12, 0, 0)),
+        None,
+    )
+    .expect("Failed to create recurring temp action");
+
+    temp_actions::create_recurring_temp_action(&pool, &new_action)
+        .await
+        .expect("Failed to insert recurring temp action");
+
+    let stored = temp_actions::get_recurring_temp_actions(&pool)
+        .await
+        .expect("Failed to get recurring temp actions");
+    assert_eq!(stored, vec![new_action.clone()]);
+
+    let updated_action = RecurringTempAction {
+        action_type: TempActionType::OFF,
+        days: vec![Weekday::Fri],
+        ends_on: Some(NaiveDate::from_ymd(2023, 12, 31)),
+        ..new_action.clone()
+    };
+    temp_actions::update_recurring_temp_action(&pool, &updated_action)
+        .await
+        .expect("Failed to update recurring temp action");
+
+    let skipped = NaiveDate::from_ymd(2023, 7, 7);
+    temp_actions::skip_recurring_temp_action(&pool, &new_action.id, &skipped)
+        .await
+        .expect("Failed to skip occurrence");
+    temp_actions::skip_recurring_temp_action(&pool, &new_action.id, &skipped)
+        .await
+        .expect("Failed to skip occurrence");
+
+    let after_update = temp_actions::get_recurring_temp_actions(&pool)
+        .await
+        .expect("Failed to get recurring temp actions");
+    assert_eq!(
+        after_update,
+        vec![RecurringTempAction {
+            skipped_dates: vec![skipped],
+            ..updated_action
+        }]
+    );
+
+    let cancelled_at = NaiveDate::from_ymd(2023, 7, 1).and_hms(9, 0, 0);
+    temp_actions::archive_recurring_temp_action(
+        &pool,
+        &new_action.id,
+        &cancelled_at,
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .expect("Failed to archive recurring temp action");
+    let after_delete = temp_actions::get_recurring_temp_actions(&pool)
+        .await
+        .expect("Failed to get recurring temp actions");
+    assert_eq!(after_delete.len(), 0);
+
+    let history = temp_actions::get_temp_action_history(
+        &pool,
+        &cancelled_at,
+        &cancelled_at.add(Duration::seconds(1)),
+    )
+    .await
+    .expect("Failed to get temp action history");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].temp_action.id, new_action.id);
+    assert_eq!(history[0].temp_action.action_type, TempActionType::OFF);
+    assert_eq!(
+        history[0].temp_action.expires_at,
+        NaiveDate::from_ymd(2023, 12, 31).and_hms(12, 0, 0)
+    );
+    assert_eq!(history[0].end_reason, TempActionEndReason::CANCELLED);
+}
+
+#[tokio::test]
+async fn plug_overrides() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+
+    create_room(&pool).await;
+
+    let rooms = rooms::get_rooms(&pool).await.expect("Can't get rooms");
+    let new_plug = plug(&rooms[0].id);
+    plugs::create_plug(&pool, &new_plug)
+        .await
+        .expect("Could not create plug");
+
+    let new_override = PlugOverride::new(
+        &None,
+        &NaiveDateTime::from_timestamp(1666291743, 0),
+        &ActionType::OFF,
+        vec![new_plug.id],
+    )
+    .expect("Failed to create plug override");
+
+    plug_overrides::create_plug_override(&pool, &new_override)
+        .await
+        .expect("Failed to insert plug override");
+
+    let stored = plug_overrides::get_plug_overrides(&pool)
+        .await
+        .expect("Failed to get plug overrides");
+    assert_eq!(stored, vec![new_override.clone()]);
+
+    let updated_override = PlugOverride {
+        action: ActionType::ON,
+        starts_at: Some(NaiveDateTime::from_timestamp(1666291000, 0)),
+        ..new_override
+    };
+    plug_overrides::update_plug_override(&pool, &updated_override)
+        .await
+        .expect("Failed to update plug override");
+
+    let after_update = plug_overrides::get_plug_overrides(&pool)
+        .await
+        .expect("Failed to get plug overrides");
+    assert_eq!(after_update, vec![updated_override.clone()]);
+
+    plug_overrides::delete_plug_override(&pool, &updated_override.id)
+        .await
+        .expect("Failed to delete plug override");
+    let after_delete = plug_overrides::get_plug_overrides(&pool)
+        .await
+        .expect("Failed to get plug overrides");
     assert_eq!(after_delete.len(), 0)
 }
 
@@ -445,7 +767,7 @@ async fn latest_temp_logs() {
     let pool = Arc::new(test_config.db_config.pool);
 
     for i in 1..5 {
-        rooms::create_room(&pool, format!("room_{}", i).as_str(), &None)
+        rooms::create_room(&pool, format!("room_{}", i).as_str(), &None, &None)
             .await
             .expect("Could not insert room");
     }
@@ -479,7 +801,7 @@ async fn latest_temp_logs() {
         assert_eq!(room_temp.temp, 10.0)
     }
 
-    rooms::create_room(&pool, "dummy", &None)
+    rooms::create_room(&pool, "dummy", &None, &None)
         .await
         .expect("Cant create room");
     let new_rooms = rooms::get_rooms(&pool).await.expect("Cant get rooms");
@@ -510,10 +832,13 @@ async fn prices() {
         some_prices.push({
             PriceInfo {
                 amount: i as f64,
+                total: None,
+                subsidy: None,
                 currency: "NOK".to_string(),
-                ext_price_level: PriceLevel::Normal,
+                ext_price_level: Some(PriceLevel::Normal),
                 price_level: Some(PriceLevel::Cheap),
                 starts_at: NaiveDateTime::new(date, NaiveTime::from_hms(i, 0, 0)),
+                duration_minutes: 60,
             }
         })
     }
@@ -533,10 +858,13 @@ async fn prices() {
         new_prices.push({
             PriceInfo {
                 amount: i as f64 * 1.5,
+                total: Some(i as f64 * 2.0),
+                subsidy: None,
                 currency: "NOK".to_string(),
-                ext_price_level: PriceLevel::VeryExpensive,
+                ext_price_level: Some(PriceLevel::VeryExpensive),
                 price_level: Some(PriceLevel::VeryCheap),
                 starts_at: NaiveDateTime::new(date, NaiveTime::from_hms(i, 0, 0)),
+                duration_minutes: 60,
             }
         })
     }
@@ -558,14 +886,183 @@ async fn prices() {
         current,
         Some(PriceInfo {
             amount: 23.0 * 1.5,
+            total: Some(23.0 * 2.0),
+            subsidy: None,
             currency: "NOK".to_string(),
-            ext_price_level: PriceLevel::VeryExpensive,
+            ext_price_level: Some(PriceLevel::VeryExpensive),
             price_level: Some(PriceLevel::VeryCheap),
             starts_at: NaiveDateTime::new(date, NaiveTime::from_hms(23, 0, 0)),
+            duration_minutes: 60,
         })
     )
 }
 
+#[tokio::test]
+async fn quarter_hour_prices() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+    let hour = NaiveDate::from_ymd(2020, 1, 1).and_hms(10, 0, 0);
+    let price = |minutes: i64, duration_minutes: i32, amount: f64| PriceInfo {
+        amount,
+        total: None,
+        subsidy: None,
+        currency: "NOK".to_string(),
+        ext_price_level: Some(PriceLevel::Normal),
+        price_level: None,
+        starts_at: hour.add(Duration::minutes(minutes)),
+        duration_minutes,
+    };
+
+    db::prices::insert_prices(&pool, &vec![price(0, 60, 1.0)])
+        .await
+        .expect("Failed to insert prices");
+    let quarters = vec![
+        price(0, 15, 2.0),
+        price(15, 15, 3.0),
+        price(30, 15, 4.0),
+        price(45, 15, 5.0),
+    ];
+    db::prices::insert_prices(&pool, &quarters)
+        .await
+        .expect("Failed to insert prices");
+
+    let current = db::prices::get_price(&pool, &hour.add(Duration::minutes(20)))
+        .await
+        .expect("Failed to get price");
+    assert_eq!(current, Some(quarters[1].clone()));
+
+    let stored = db::prices::get_prices(
+        &pool,
+        &hour.sub(Duration::seconds(1)),
+        &hour.add(Duration::hours(1)),
+    )
+    .await
+    .expect("Failed to get prices");
+    assert_eq!(stored, quarters);
+
+    // Going back to hourly prices replaces all quarters of the hour
+    db::prices::insert_prices(&pool, &vec![price(0, 60, 1.0)])
+        .await
+        .expect("Failed to insert prices");
+    let current = db::prices::get_price(&pool, &hour.add(Duration::minutes(50)))
+        .await
+        .expect("Failed to get price");
+    assert_eq!(current, Some(price(0, 60, 1.0)));
+    let after = db::prices::get_price(&pool, &hour.add(Duration::minutes(60)))
+        .await
+        .expect("Failed to get price");
+    assert_eq!(after, None);
+}
+
+#[tokio::test]
+async fn price_range() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = test_config.db_config.pool;
+    let day = NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let price = |hours: i64, amount: f64| PriceInfo {
+        amount,
+        total: None,
+        subsidy: None,
+        currency: "NOK".to_string(),
+        ext_price_level: Some(PriceLevel::Normal),
+        price_level: Some(PriceLevel::Cheap),
+        starts_at: day.add(Duration::hours(hours)),
+        duration_minutes: 60,
+    };
+    let prices = vec![price(25, 4.0), price(0, 1.0), price(23, 3.0), price(24, 2.0)];
+    db::prices::insert_prices(&pool, &prices)
+        .await
+        .expect("Failed to insert prices");
+
+    let range = service::prices::get_price_range(&pool, &day, &day.add(Duration::days(1)))
+        .await
+        .expect("Failed to get price range");
+    assert_eq!(range.prices, vec![price(0, 1.0), price(23, 3.0)]);
+    assert_eq!(range.days.len(), 1);
+    assert_eq!(range.days[0].min, 1.0);
+    assert_eq!(range.days[0].max, 3.0);
+    assert_eq!(range.days[0].average, 2.0);
+
+    let range = service::prices::get_price_range(&pool, &day, &day.add(Duration::days(2)))
+        .await
+        .expect("Failed to get price range");
+    assert_eq!(range.prices.len(), 4);
+    assert_eq!(range.days.len(), 2);
+    assert_eq!(range.days[1].average, 3.0);
+}
+
+#[tokio::test]
+async fn price_settings() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = Arc::new(test_config.db_config.pool);
+
+    let settings = db::price_settings::get_price_settings(pool.as_ref())
+        .await
+        .expect("Failed to get settings");
+    assert_eq!(settings, None);
+
+    let new_settings = PriceSettings {
+        electricity_tax: 0.0951,
+        vat_percent: 25.0,
+        use_total_price: true,
+        subsidy_threshold: Some(0.75),
+        subsidy_percent: 90.0,
+        fallback_price_level: Some(PriceLevel::Expensive),
+        negative_price_mode: true,
+        negative_price_threshold: -0.5,
+    };
+    db::price_settings::upsert_price_settings(pool.as_ref(), &new_settings)
+        .await
+        .expect("Failed to upsert settings");
+    db::price_settings::upsert_price_settings(pool.as_ref(), &new_settings)
+        .await
+        .expect("Failed to upsert settings");
+    let settings = db::price_settings::get_price_settings(pool.as_ref())
+        .await
+        .expect("Failed to get settings");
+    assert_eq!(settings, Some(new_settings));
+
+    let mut tariff = GridTariff::new(
+        "winter day",
+        0.4,
+        vec![1, 2, 3],
+        vec![Weekday::Mon, Weekday::Tue],
+        (NaiveTime::from_hms(6, 0, 0), NaiveTime::from_hms(22, 0, 0)),
+    )
+    .expect("Failed to create tariff");
+    db::price_settings::create_grid_tariff(pool.as_ref(), &tariff)
+        .await
+        .expect("Failed to create tariff");
+    let stored = db::price_settings::get_grid_tariffs(pool.as_ref())
+        .await
+        .expect("Failed to get tariffs");
+    assert_eq!(stored, vec![tariff.clone()]);
+
+    tariff.amount = 0.5;
+    tariff.days = vec![];
+    db::price_settings::update_grid_tariff(pool.as_ref(), &tariff)
+        .await
+        .expect("Failed to update tariff");
+    let stored = db::price_settings::get_grid_tariffs(pool.as_ref())
+        .await
+        .expect("Failed to get tariffs");
+    assert_eq!(stored, vec![tariff.clone()]);
+
+    db::price_settings::delete_grid_tariff(pool.as_ref(), &tariff.id)
+        .await
+        .expect("Failed to delete tariff");
+    let stored = db::price_settings::get_grid_tariffs(pool.as_ref())
+        .await
+        .expect("Failed to get tariffs");
+    assert!(stored.is_empty());
+}
+
 #[tokio::test]
 async fn settings() {
     let docker = Cli::default();
@@ -585,6 +1082,10 @@ async fn settings() {
             max_consumption: Some(3),
             max_consumption_timeout_minutes: 15,
             ntfy_topic: "test_topic".to_string(),
+            price_summary_time: None,
+            price_summary_block_hours: 3,
+            price_alert_max: None,
+            price_alert_min: 0.0,
         },
     )
     .await
@@ -600,6 +1101,10 @@ async fn settings() {
             max_consumption: Some(3),
             max_consumption_timeout_minutes: 15,
             ntfy_topic: "test_topic".to_string(),
+            price_summary_time: None,
+            price_summary_block_hours: 3,
+            price_alert_max: None,
+            price_alert_min: 0.0,
         })
     );
 
@@ -610,6 +1115,10 @@ async fn settings() {
             max_consumption: Some(10),
             max_consumption_timeout_minutes: 20,
             ntfy_topic: "test_topic".to_string(),
+            price_summary_time: Some(NaiveTime::from_hms(14, 0, 0)),
+            price_summary_block_hours: 2,
+            price_alert_max: Some(2.5),
+            price_alert_min: -0.5,
         },
     )
     .await
@@ -625,6 +1134,10 @@ async fn settings() {
             max_consumption: Some(10),
             max_consumption_timeout_minutes: 20,
             ntfy_topic: "test_topic".to_string(),
+            price_summary_time: Some(NaiveTime::from_hms(14, 0, 0)),
+            price_summary_block_hours: 2,
+            price_alert_max: Some(2.5),
+            price_alert_min: -0.5,
         })
     );
 }
@@ -757,3 +1270,325 @@ async fn buttons() {
         .expect("Couldn't get buttons");
     assert_eq!(buttons.len(), 0);
 }
+
+#[tokio::test]
+async fn live_consumption_history() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = test_config.db_config.pool;
+    let hour = NaiveDate::from_ymd(2020, 1, 1).and_hms(10, 0, 0);
+    let sample = |seconds: i64, power: i64| LiveConsumption {
+        timestamp: hour.add(Duration::seconds(seconds)),
+        power,
+    };
+    let samples = vec![sample(0, 1000), sample(30, 2000), sample(60, 3000), sample(3610, 500)];
+    db::live_consumption::insert_live_consumption(&pool, &samples)
+        .await
+        .expect("Failed to insert live consumption");
+    db::live_consumption::insert_live_consumption(&pool, &samples[..1])
+        .await
+        .expect("Failed to insert live consumption twice");
+    assert_eq!(
+        db::live_consumption::get_latest_timestamp(&pool)
+            .await
+            .expect("Failed to get latest timestamp"),
+        Some(hour.add(Duration::seconds(3610)))
+    );
+
+    let now = hour.add(Duration::minutes(62));
+    service::consumption_history::downsample(&pool, &now)
+        .await
+        .expect("Failed to downsample");
+
+    let aggregate = |starts_at: NaiveDateTime, min: i64, avg: f64, max: i64, samples: i64| {
+        PowerAggregate {
+            starts_at,
+            min_power: min,
+            avg_power: avg,
+            max_power: max,
+            samples,
+        }
+    };
+    let history = service::consumption_history::get_consumption_history(
+        &pool,
+        &hour,
+        &now,
+        Some(ConsumptionResolution::Minute),
+    )
+    .await
+    .expect("Failed to get history");
+    assert_eq!(
+        history.consumption,
+        vec![
+            aggregate(hour, 1000, 1500.0, 2000, 2),
+            aggregate(hour.add(Duration::minutes(1)), 3000, 3000.0, 3000, 1),
+            aggregate(hour.add(Duration::minutes(60)), 500, 500.0, 500, 1),
+        ]
+    );
+    // Only the complete hour is aggregated
+    let history = service::consumption_history::get_consumption_history(
+        &pool,
+        &hour,
+        &now.add(Duration::days(3)),
+        None,
+    )
+    .await
+    .expect("Failed to get history");
+    assert_eq!(history.resolution, ConsumptionResolution::Hour);
+    assert_eq!(history.consumption, vec![aggregate(hour, 1000, 2000.0, 3000, 3)]);
+
+    let settings = ConsumptionHistorySettings {
+        raw_retention_hours: 1,
+        ..Default::default()
+    };
+    service::consumption_history::apply_retention(&pool, &settings, &now)
+        .await
+        .expect("Failed to apply retention");
+    let history = service::consumption_history::get_consumption_history(&pool, &hour, &now, None)
+        .await
+        .expect("Failed to get history");
+    assert_eq!(history.resolution, ConsumptionResolution::Raw);
+    assert_eq!(
+        history.consumption,
+        vec![aggregate(hour.add(Duration::seconds(3610)), 500, 500.0, 500, 1)]
+    );
+}
+
+#[tokio::test]
+async fn hourly_consumption() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = test_config.db_config.pool;
+    // A Sunday
+    let day = NaiveDate::from_ymd(2023, 1, 29).and_hms(0, 0, 0);
+    let hour = |hours: i64, kwh: Option<f64>, cost: f64| Consumption {
+        from: day.add(Duration::hours(hours)),
+        to: day.add(Duration::hours(hours + 1)),
+        kwh,
+        cost,
+    };
+    db::consumption::upsert_consumption(
+        &pool,
+        &[hour(0, Some(1.0), 2.0), hour(23, Some(2.0), 2.5), hour(24, None, 0.0)],
+    )
+    .await
+    .expect("Failed to insert consumption");
+    db::consumption::upsert_consumption(&pool, &[hour(24, Some(0.5), 0.25)])
+        .await
+        .expect("Failed to insert consumption");
+    db::consumption::upsert_consumption(&pool, &[hour(0, Some(1.5), 3.0)])
+        .await
+        .expect("Failed to update consumption");
+    assert_eq!(
+        db::consumption::get_latest_consumption_time(&pool)
+            .await
+            .expect("Failed to get latest consumption"),
+        Some(day.add(Duration::hours(24)))
+    );
+    assert_eq!(
+        db::consumption::get_consumption(&pool, &day, &day.add(Duration::days(1)))
+            .await
+            .expect("Failed to get consumption"),
+        vec![hour(0, Some(1.5), 3.0), hour(23, Some(2.0), 2.5)]
+    );
+
+    let summary = |from: NaiveDate, to: NaiveDate, kwh: f64, cost: f64, hours: i64| {
+        rust_home::domain::ConsumptionSummary {
+            from: from.and_hms(0, 0, 0),
+            to: to.and_hms(0, 0, 0),
+            kwh,
+            cost,
+            hours,
+        }
+    };
+    let date = day.date();
+    let next_day = date.add(Duration::days(1));
+    assert_eq!(
+        service::hourly_consumption::get_consumption_summaries(
+            &pool,
+            &ConsumptionPeriod::Day,
+            &date,
+            &next_day,
+        )
+        .await
+        .expect("Failed to get summaries"),
+        vec![
+            summary(date, next_day, 3.5, 5.5, 2),
+            summary(next_day, next_day.add(Duration::days(1)), 0.5, 0.25, 1),
+        ]
+    );
+    assert_eq!(
+        service::hourly_consumption::get_consumption_summaries(
+            &pool,
+            &ConsumptionPeriod::Week,
+            &date,
+            &next_day,
+        )
+        .await
+        .expect("Failed to get summaries"),
+        vec![
+            summary(date.sub(Duration::days(6)), next_day, 3.5, 5.5, 2),
+            summary(next_day, next_day.add(Duration::weeks(1)), 0.5, 0.25, 1),
+        ]
+    );
+    assert_eq!(
+        service::hourly_consumption::get_consumption_summaries(
+            &pool,
+            &ConsumptionPeriod::Month,
+            &date,
+            &date,
+        )
+        .await
+        .expect("Failed to get summaries"),
+        vec![summary(
+            NaiveDate::from_ymd(2023, 1, 1),
+            NaiveDate::from_ymd(2023, 2, 1),
+            4.0,
+            5.75,
+            3
+        )]
+    );
+}
+
+#[tokio::test]
+async fn month_report() {
+    let docker = Cli::default();
+
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = test_config.db_config.pool;
+    let month = NaiveDate::from_ymd(2023, 2, 1).and_hms(0, 0, 0);
+    // The last hour of January is left out
+    let hours = [(-1, 5.0, 1.0), (0, 3.0, 1.0), (1, 1.0, 3.0)];
+    let consumption: Vec<Consumption> = hours
+        .iter()
+        .map(|(hour, kwh, price)| Consumption {
+            from: month.add(Duration::hours(*hour)),
+            to: month.add(Duration::hours(hour + 1)),
+            kwh: Some(*kwh),
+            cost: kwh * price,
+        })
+        .collect();
+    let prices: Vec<PriceInfo> = hours
+        .iter()
+        .map(|(hour, _, price)| PriceInfo {
+            amount: *price,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: month.add(Duration::hours(*hour)),
+            duration_minutes: 60,
+        })
+        .collect();
+    db::consumption::upsert_consumption(&pool, &consumption)
+        .await
+        .expect("Failed to insert consumption");
+    db::prices::insert_prices(&pool, &prices)
+        .await
+        .expect("Failed to insert prices");
+
+    let report =
+        service::reports::get_month_report(&pool, &NaiveDate::from_ymd(2023, 2, 14))
+            .await
+            .expect("Failed to get month report");
+    assert_eq!(report.from, month);
+    assert_eq!(report.to, NaiveDate::from_ymd(2023, 3, 1).and_hms(0, 0, 0));
+    assert_eq!(report.month_to_date.kwh, 4.0);
+    assert_eq!(report.month_to_date.cost, 6.0);
+    assert_eq!(report.month_to_date.weighted_spot_price, Some(1.5));
+    assert_eq!(report.month_to_date.average_spot_price, Some(2.0));
+    assert_eq!(report.projected_kwh, Some(4.0 * 28.0 * 12.0));
+    assert_eq!(report.days.len(), 1);
+}
+
+#[tokio::test]
+async fn reads_plug_meters() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = &test_config.db_config.pool;
+    let mock_server = MockServer::start().await;
+    let shelly_client = ShellyClient::new_with_port(mock_server.address().port());
+
+    create_room(pool).await;
+    let rooms = rooms::get_rooms(pool).await.expect("Can't get rooms");
+    let plug = Plug::new(
+        "heater",
+        &mock_server.address().ip().to_string(),
+        "admin",
+        "password",
+        &rooms[0].id,
+        &true,
+    )
+    .expect("Couldnt create plug");
+    db::plugs::create_plug(pool, &plug)
+        .await
+        .expect("Couldnt insert plug");
+
+    let now = NaiveDate::from_ymd(2023, 1, 2).and_hms(10, 0, 0);
+    // Watt-minutes, the plug restarts before the last reading
+    for (minutes, total) in [(0, 60_000), (30, 90_000), (60, 120_000), (90, 6_000)] {
+        mock_server.reset().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "power": 1000.0,
+                "overpower": 0.0,
+                "is_valid": true,
+                "timestamp": 0,
+                "counters": [],
+                "total": total
+            })))
+            .mount(&mock_server)
+            .await;
+        let time = now + Duration::minutes(minutes);
+        let read = service::plug_energy::read_meters(pool, &shelly_client, &LiveEvents::default(), &time)
+            .await
+            .expect("Failed to read meters");
+        assert_eq!(read, 1);
+    }
+
+    let day = now.date();
+    let plug_energy = service::plug_energy::get_plug_energy(pool, &ConsumptionPeriod::Day, &day, &day)
+        .await
+        .expect("Failed to get plug energy");
+    assert_eq!(plug_energy.len(), 1);
+    assert_eq!(plug_energy[0].plug_id, plug.id);
+    assert_eq!(plug_energy[0].from, day.and_hms(0, 0, 0));
+    assert_eq!(plug_energy[0].kwh, 1.1);
+
+    let room_energy = service::plug_energy::get_room_energy(pool, &ConsumptionPeriod::Month, &day, &day)
+        .await
+        .expect("Failed to get room energy");
+    assert_eq!(room_energy.len(), 1);
+    assert_eq!(room_energy[0].room_id, rooms[0].id);
+    assert_eq!(room_energy[0].from, NaiveDate::from_ymd(2023, 1, 1).and_hms(0, 0, 0));
+    assert_eq!(room_energy[0].kwh, 1.1);
+
+    let prices: Vec<PriceInfo> = [(10, 1.0), (11, 2.0)]
+        .iter()
+        .map(|(hour, amount)| PriceInfo {
+            amount: *amount,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at: day.and_hms(*hour, 0, 0),
+            duration_minutes: 60,
+        })
+        .collect();
+    db::prices::insert_prices(pool, &prices)
+        .await
+        .expect("Couldnt insert prices");
+    let room_costs = service::heating_costs::get_room_costs(pool, &ConsumptionPeriod::Day, &day, &day)
+        .await
+        .expect("Failed to get room costs");
+    assert_eq!(room_costs.len(), 1);
+    assert_eq!(room_costs[0].room_id, rooms[0].id);
+    assert_eq!(room_costs[0].kwh, 1.1);
+    assert_eq!(room_costs[0].spot_cost, 1.2);
+    assert_eq!(room_costs[0].total_cost, None);
+    assert_eq!(room_costs[0].plugs[0].plug_id, plug.id);
+}
@@ -0,0 +1,475 @@
+use std::sync::Arc;
+
+use chrono::Timelike;
+use serde_json::json;
+use testcontainers::clients::Cli;
+use wiremock::matchers::{body_string_contains, header, method, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use rust_home::clients::entsoe_client::EntsoeClient;
+use rust_home::clients::price_provider::{FallbackPriceProvider, PriceProvider};
+use rust_home::clients::tibber_client::TibberClient;
+use rust_home::configuration::PriceLevelStrategy;
+use rust_home::db;
+use rust_home::domain::{GridTariff, PriceInfo, PriceLevel, PriceSettings};
+use rust_home::{now, service};
+
+use crate::configuration::DatabaseTestConfig;
+
+mod configuration;
+
+fn tibber_price(total: f64, level: &str, starts_at: &str) -> serde_json::Value {
+    json!({
+        "total": total,
+        "currency": "NOK",
+        "level": level,
+        "startsAt": starts_at,
+    })
+}
+
+fn tibber_response(price_info: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "data": {
+            "viewer": {
+                "homes": [
+                    {
+                        "id": "other-home",
+                        "currentSubscription": { "priceInfo": {} }
+                    },
+                    {
+                        "id": "my-home",
+                        "currentSubscription": { "priceInfo": price_info }
+                    }
+                ]
+            }
+        }
+    }))
+}
+
+async fn tibber_mock() -> MockServer {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(header("Authorization", "Bearer token"))
+        .and(body_string_contains("today"))
+        .respond_with(tibber_response(json!({
+            "today": [
+                tibber_price(1.0, "CHEAP", "2023-01-02T00:00:00.000+01:00"),
+                tibber_price(2.0, "EXPENSIVE", "2023-01-02T01:00:00.000+01:00"),
+            ],
+            "tomorrow": [],
+        })))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(body_string_contains("current {"))
+        .respond_with(tibber_response(json!({
+            "current": tibber_price(1.5, "VERY_EXPENSIVE", "2023-01-02T00:00:00.000+01:00"),
+        })))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(body_string_contains("DAILY"))
+        .respond_with(tibber_response(json!({
+            "range": { "nodes": [{ "total": 1.2, "startsAt": "2023-01-01T00:00:00.000+01:00" }] },
+        })))
+        .mount(&mock_server)
+        .await;
+
+    mock_server
+}
+
+fn tibber_client(mock_server: &MockServer) -> TibberClient {
+    TibberClient::new_with_base_url(
+        "token".to_string(),
+        mock_server.uri(),
+        Some("my-home".to_string()),
+    )
+}
+
+#[tokio::test]
+async fn tibber_provider_reads_prices_for_configured_home() {
+    let mock_server = tibber_mock().await;
+    let provider: Arc<dyn PriceProvider> = Arc::new(tibber_client(&mock_server));
+
+    let prices = provider.get_prices().await.expect("Failed to get prices");
+    assert_eq!(prices.len(), 2);
+    assert_eq!(prices[0].amount, 1.0);
+    assert_eq!(prices[0].ext_price_level, Some(PriceLevel::Cheap));
+    assert_eq!(prices[1].ext_price_level, Some(PriceLevel::Expensive));
+
+    let current = provider
+        .get_current_price()
+        .await
+        .expect("Failed to get current price");
+    assert_eq!(current.ext_price_level, Some(PriceLevel::VeryExpensive));
+
+    let daily = provider
+        .get_daily_prices()
+        .await
+        .expect("Failed to get daily prices");
+    assert_eq!(daily.len(), 1);
+    assert_eq!(daily[0].total, 1.2);
+}
+
+#[tokio::test]
+async fn tibber_client_reads_consumption_for_configured_home() {
+    let mock_server = MockServer::start().await;
+    let hour = |from: &str, to: &str, consumption: Option<f64>, cost: Option<f64>| {
+        json!({ "from": from, "to": to, "consumption": consumption, "cost": cost })
+    };
+    Mock::given(method("POST"))
+        .and(body_string_contains("consumption(resolution: HOURLY, last: 3)"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": {
+                "viewer": {
+                    "homes": [
+                        { "id": "other-home", "consumption": { "nodes": [] } },
+                        {
+                            "id": "my-home",
+                            "consumption": {
+                                "nodes": [
+                                    hour(
+                                        "2023-01-02T00:00:00.000+01:00",
+                                        "2023-01-02T01:00:00.000+01:00",
+                                        Some(2.0),
+                                        Some(3.0)
+                                    ),
+                                    hour(
+                                        "2023-01-02T01:00:00.000+01:00",
+                                        "2023-01-02T02:00:00.000+01:00",
+                                        None,
+                                        Some(0.0)
+                                    ),
+                                    hour(
+                                        "2023-01-02T02:00:00.000+01:00",
+                                        "2023-01-02T03:00:00.000+01:00",
+                                        None,
+                                        None
+                                    ),
+                                ]
+                            }
+                        }
+                    ]
+                }
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let consumption = tibber_client(&mock_server)
+        .get_consumption(3)
+        .await
+        .expect("Failed to get consumption");
+    assert_eq!(consumption.len(), 2);
+    assert_eq!(consumption[0].kwh, Some(2.0));
+    assert_eq!(consumption[0].cost, 3.0);
+    assert_eq!(consumption[1].kwh, None);
+}
+
+#[tokio::test]
+async fn fallback_provider_stores_prices_from_working_provider() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let mock_server = tibber_mock().await;
+    let failing_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&failing_server)
+        .await;
+
+    let provider = FallbackPriceProvider::new(vec![
+        Arc::new(tibber_client(&failing_server)),
+        Arc::new(tibber_client(&mock_server)),
+    ]);
+
+    service::prices::fetch_and_store_prices(
+        &provider,
+        &test_config.db_config.pool,
+        &PriceLevelStrategy::default(),
+    )
+    .await
+    .expect("Failed to fetch and store prices");
+
+    let stored = db::prices::get_price(
+        &test_config.db_config.pool,
+        &chrono::NaiveDate::from_ymd(2023, 1, 2).and_hms(1, 30, 0),
+    )
+    .await
+    .expect("Failed to get price")
+    .expect("Missing price");
+    assert_eq!(stored.amount, 2.0);
+}
+
+#[tokio::test]
+async fn stored_prices_include_total_price_and_subsidy() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = &test_config.db_config.pool;
+    let mock_server = tibber_mock().await;
+
+    db::price_settings::upsert_price_settings(
+        pool,
+        &PriceSettings {
+            electricity_tax: 0.25,
+            vat_percent: 25.0,
+            use_total_price: true,
+            subsidy_threshold: Some(1.5),
+            subsidy_percent: 50.0,
+            fallback_price_level: None,
+            negative_price_mode: false,
+            negative_price_threshold: 0.0,
+        },
+    )
+    .await
+    .expect("Failed to upsert price settings");
+    let night = GridTariff::new(
+        "night",
+        0.25,
+        vec![],
+        vec![],
+        (
+            chrono::NaiveTime::from_hms(22, 0, 0),
+            chrono::NaiveTime::from_hms(1, 0, 0),
+        ),
+    )
+    .expect("Failed to create tariff");
+    db::price_settings::create_grid_tariff(pool, &night)
+        .await
+        .expect("Failed to create tariff");
+
+    service::prices::fetch_and_store_prices(
+        &tibber_client(&mock_server),
+        pool,
+        &PriceLevelStrategy::default(),
+    )
+    .await
+    .expect("Failed to fetch and store prices");
+
+    let day = chrono::NaiveDate::from_ymd(2023, 1, 2);
+    let night_price = db::prices::get_price(pool, &day.and_hms(0, 0, 0))
+        .await
+        .expect("Failed to get price")
+        .expect("Missing price");
+    assert_eq!(night_price.amount, 1.0);
+    assert_eq!(night_price.total, Some(1.875));
+    assert_eq!(night_price.subsidy, Some(0.0));
+
+    let day_price = db::prices::get_price(pool, &day.and_hms(1, 0, 0))
+        .await
+        .expect("Failed to get price")
+        .expect("Missing price");
+    assert_eq!(day_price.total, Some(2.8125));
+    assert_eq!(day_price.subsidy, Some(0.25));
+    let price_settings = db::price_settings::get_price_settings(pool)
+        .await
+        .expect("Failed to get price settings");
+    assert_eq!(day_price.cost_price(price_settings.as_ref()), 2.5);
+}
+
+#[tokio::test]
+async fn backfills_gaps_in_stored_prices() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = &test_config.db_config.pool;
+    let mock_server = tibber_mock().await;
+    let yesterday = now().date() - chrono::Duration::days(1);
+    let starts_at = |hour: u32| format!("{}T{:02}:00:00.000+00:00", yesterday, hour);
+    Mock::given(method("POST"))
+        .and(body_string_contains("HOURLY"))
+        .respond_with(tibber_response(json!({
+            "hourly": { "nodes": [
+                tibber_price(3.0, "NORMAL", &starts_at(9)),
+                tibber_price(4.0, "NORMAL", &starts_at(10)),
+                tibber_price(5.0, "NORMAL", &starts_at(11)),
+            ] },
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let from = (now().date() - chrono::Duration::days(7)).and_hms(0, 0, 0);
+    let gap = [yesterday.and_hms(10, 0, 0), yesterday.and_hms(11, 0, 0)];
+    let stored: Vec<PriceInfo> = (0..8 * 24)
+        .map(|hour| from + chrono::Duration::hours(hour))
+        .filter(|hour| !gap.contains(hour))
+        .map(|starts_at| PriceInfo {
+            amount: 1.0,
+            total: None,
+            subsidy: None,
+            currency: "NOK".to_string(),
+            ext_price_level: Some(PriceLevel::Normal),
+            price_level: None,
+            starts_at,
+            duration_minutes: 60,
+        })
+        .collect();
+    db::prices::insert_prices(pool, &stored)
+        .await
+        .expect("Failed to insert prices");
+    let coverage = service::price_fetching::get_price_coverage(pool)
+        .await
+        .expect("Failed to get coverage");
+    assert_eq!(coverage.gaps.len(), 1);
+    assert!(!coverage.has_tomorrow);
+
+    let backfilled = service::price_fetching::backfill_gaps(
+        &tibber_client(&mock_server),
+        pool,
+        &PriceLevelStrategy::default(),
+    )
+    .await
+    .expect("Failed to backfill prices");
+    assert_eq!(backfilled, 2);
+
+    let coverage = service::price_fetching::get_price_coverage(pool)
+        .await
+        .expect("Failed to get coverage");
+    assert!(coverage.gaps.is_empty());
+    let price = db::prices::get_price(pool, &yesterday.and_hms(11, 30, 0))
+        .await
+        .expect("Failed to get price")
+        .expect("Missing price");
+    assert_eq!(price.amount, 5.0);
+}
+
+#[tokio::test]
+async fn falls_back_to_price_level_without_price() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = &test_config.db_config.pool;
+    let failing_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&failing_server)
+        .await;
+    let provider = tibber_client(&failing_server);
+
+    let current = service::prices::get_current_price_or_fallback(&provider, pool).await;
+    assert!(current.degraded);
+    assert_eq!(current.price.level(), PriceLevel::Normal);
+
+    let time = now() - chrono::Duration::days(1);
+    let yesterday = PriceInfo {
+        amount: 2.0,
+        total: None,
+        subsidy: None,
+        currency: "NOK".to_string(),
+        ext_price_level: Some(PriceLevel::Normal),
+        price_level: Some(PriceLevel::VeryExpensive),
+        starts_at: time.date().and_hms(time.hour(), 0, 0),
+        duration_minutes: 60,
+    };
+    db::prices::insert_prices(pool, &vec![yesterday.clone()])
+        .await
+        .expect("Failed to insert prices");
+    let current = service::prices::get_current_price_or_fallback(&provider, pool).await;
+    assert!(current.degraded);
+    assert_eq!(current.price, yesterday);
+
+    db::price_settings::upsert_price_settings(
+        pool,
+        &PriceSettings {
+            fallback_price_level: Some(PriceLevel::Cheap),
+            negative_price_mode: false,
+            negative_price_threshold: 0.0,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Failed to upsert price settings");
+    let current = service::prices::get_current_price_or_fallback(&provider, pool).await;
+    assert!(current.degraded);
+    assert_eq!(current.price.level(), PriceLevel::Cheap);
+}
+
+const ENTSOE_DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Publication_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-3:publicationdocument:7:3">
+  <TimeSeries>
+    <currency_Unit.name>EUR</currency_Unit.name>
+    <price_Measure_Unit.name>MWH</price_Measure_Unit.name>
+    <Period>
+      <timeInterval>
+        <start>2023-01-01T23:00Z</start>
+        <end>2023-01-02T02:00Z</end>
+      </timeInterval>
+      <resolution>PT60M</resolution>
+      <Point><position>1</position><price.amount>100.00</price.amount></Point>
+      <Point><position>2</position><price.amount>200.00</price.amount></Point>
+    </Period>
+  </TimeSeries>
+</Publication_MarketDocument>"#;
+
+#[tokio::test]
+async fn entsoe_provider_stores_day_ahead_prices() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(query_param("securityToken", "token"))
+        .and(query_param("documentType", "A44"))
+        .and(query_param("in_Domain", "10YNO-1--------2"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(ENTSOE_DOCUMENT))
+        .mount(&mock_server)
+        .await;
+
+    let provider = EntsoeClient::new(
+        "token".to_string(),
+        mock_server.uri(),
+        "NO1",
+        Some("NOK".to_string()),
+        Some(10.0),
+    )
+    .expect("Failed to create client");
+
+    let prices = provider.get_prices().await.expect("Failed to get prices");
+    assert_eq!(prices.len(), 3);
+    assert_eq!(prices[0].currency, "NOK");
+    assert_eq!(prices[0].ext_price_level, None);
+    assert_eq!(prices[1].ext_price_level, None);
+
+    service::prices::fetch_and_store_prices(
+        &provider,
+        &test_config.db_config.pool,
+        &PriceLevelStrategy::default(),
+    )
+    .await
+    .expect("Failed to fetch and store prices");
+
+    let pool = &test_config.db_config.pool;
+    let day = chrono::NaiveDate::from_ymd(2023, 1, 2);
+    let stored = db::prices::get_price(pool, &day.and_hms(0, 0, 0))
+        .await
+        .expect("Failed to get price")
+        .expect("Missing price");
+    assert_eq!(stored.amount, 1.0);
+    assert_eq!(stored.ext_price_level, None);
+
+    // The last position is left out, and repeats the previous price
+    let stored = db::prices::get_price(pool, &day.and_hms(2, 0, 0))
+        .await
+        .expect("Failed to get price")
+        .expect("Missing price");
+    assert_eq!(stored.amount, 2.0);
+}
+
+#[tokio::test]
+async fn entsoe_provider_returns_no_prices_before_publication() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(400).set_body_string(
+            r#"<Acknowledgement_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-1:acknowledgementdocument:7:0">
+  <Reason><code>999</code><text>No matching data found</text></Reason>
+</Acknowledgement_MarketDocument>"#,
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let provider = EntsoeClient::new("token".to_string(), mock_server.uri(), "NO1", None, None)
+        .expect("Failed to create client");
+
+    let prices = provider.get_prices().await.expect("Failed to get prices");
+    assert!(prices.is_empty());
+    assert!(EntsoeClient::new("token".to_string(), mock_server.uri(), "XX", None, None).is_err());
+}
@@ -1,10 +1,16 @@
 use std::collections::HashMap;
 
 use chrono::{NaiveTime, Weekday};
+use sqlx::PgPool;
 
+use rust_home::db;
 use rust_home::domain::{PriceLevel, Room, Schedule};
+use rust_home::now;
 
-pub fn schedule(rooms: Vec<&Room>) -> Schedule {
+pub async fn schedule(pool: &PgPool, rooms: Vec<&Room>) -> Schedule {
+    let profile_id = db::schedule_profiles::get_active_profile_id(pool, &now())
+        .await
+        .expect("Couldn't get active schedule profile");
     Schedule::new(
         HashMap::from([
             (PriceLevel::VeryCheap, 18.0),
@@ -16,6 +22,7 @@ pub fn schedule(rooms: Vec<&Room>) -> Schedule {
             NaiveTime::from_hms(12, 0, 0),
         )],
         vec![rooms[0].id],
+        profile_id,
     )
     .expect("Couldn't create schedule")
 }
@@ -12,9 +12,12 @@ use rust_home::clients::tibber_client::TibberClient;
 use rust_home::db;
 use rust_home::db::DbConfig;
 use rust_home::domain::{
-    ActionType, Button, Plug, PriceInfo, PriceLevel, Room, TempAction, TempActionType,
-    TemperatureLog, WorkMessage,
+    ActionType, Button, Plug, PlugOverride, PriceInfo, PriceLevel, PriceSettings,
+    RecurringTempAction, Room, TempAction, TempActionEndReason, TempActionType, TemperatureLog,
+    WorkMessage,
 };
+use rust_home::service::live_events::{LiveEvent, LiveEvents};
+use rust_home::service::prices::CurrentPrice;
 use rust_home::work_handler::WorkHandler;
 
 use crate::configuration::DatabaseTestConfig;
@@ -22,10 +25,26 @@ use crate::configuration::DatabaseTestConfig;
 mod configuration;
 mod setup;
 
+fn current(price: &PriceInfo) -> CurrentPrice {
+    CurrentPrice {
+        price: price.clone(),
+        degraded: false,
+    }
+}
+
 async fn setup(
     db_config: &DbConfig,
     num_rooms: u32,
     shelly_port: Option<u16>,
+) -> (WorkHandler, Vec<Room>) {
+    setup_with_events(db_config, num_rooms, shelly_port, LiveEvents::default()).await
+}
+
+async fn setup_with_events(
+    db_config: &DbConfig,
+    num_rooms: u32,
+    shelly_port: Option<u16>,
+    live_events: LiveEvents,
 ) -> (WorkHandler, Vec<Room>) {
     let shelly_client = if let Some(shelly_port) = shelly_port {
         ShellyClient::new_with_port(shelly_port)
@@ -42,9 +61,10 @@ async fn setup(
         sender.clone(),
         receiver,
         Arc::new(db_config.pool.clone()),
+        live_events,
     );
     for i in 0..num_rooms {
-        db::rooms::create_room(&db_config.pool, &format!("test_room_{}", i), &None)
+        db::rooms::create_room(&db_config.pool, &format!("test_room_{}", i), &None, &None)
             .await
             .expect("Failed to create room");
     }
@@ -63,10 +83,13 @@ async fn starts() {
 
     let price_info = PriceInfo {
         amount: 0.0,
+        total: None,
+        subsidy: None,
         currency: "NOK".to_string(),
-        ext_price_level: PriceLevel::Cheap,
+        ext_price_level: Some(PriceLevel::Cheap),
         price_level: None,
         starts_at: Utc::now().naive_local(),
+        duration_minutes: 60,
     };
 
     let now = NaiveDateTime::new(
@@ -74,7 +97,7 @@ async fn starts() {
         NaiveTime::from_hms(0, 0, 0),
     );
 
-    let result = handler.main_handler(&price_info, &now).await;
+    let result = handler.main_handler(&current(&price_info), &now).await;
     assert!(result.is_ok())
 }
 
@@ -97,6 +120,52 @@ async fn handles_temp_log() {
     assert_eq!(temp_logs[0].room_id, room_id);
 }
 
+#[tokio::test]
+async fn publishes_live_events() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let live_events = LiveEvents::default();
+    let mut receiver = live_events.subscribe();
+    let (handler, rooms) = setup_with_events(&test_config.db_config, 1, None, live_events).await;
+    let room_id = rooms[0].id;
+
+    handler
+        .temperature_handler(&room_id, &20.0)
+        .await
+        .expect("Temp handler failed");
+    match receiver.try_recv() {
+        Ok(LiveEvent::Temperature { room_id: id, temp, .. }) => {
+            assert_eq!(id, room_id);
+            assert_eq!(temp, 20.0);
+        }
+        event => panic!("Expected a temperature event, got {:?}", event),
+    }
+
+    let price_info = PriceInfo {
+        amount: 1.0,
+        total: None,
+        subsidy: None,
+        currency: "NOK".to_string(),
+        ext_price_level: Some(PriceLevel::Normal),
+        price_level: None,
+        starts_at: Utc::now().naive_local(),
+        duration_minutes: 60,
+    };
+    let now = Utc::now().naive_local();
+    handler
+        .main_handler(&current(&price_info), &now)
+        .await
+        .expect("Main handler failed");
+    assert_eq!(
+        receiver.try_recv(),
+        Ok(LiveEvent::RoomDecision {
+            room_id,
+            action: ActionType::OFF,
+            time: now,
+        })
+    );
+}
+
 #[tokio::test]
 async fn temp_actions_work() {
     let docker = Cli::default();
@@ -134,7 +203,7 @@ async fn temp_actions_work() {
         .await
         .expect("Couldnt insert plug");
 
-    let schedule = setup::schedule(vec![&rooms[0]]);
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
 
     db::schedules::create_schedule(&test_config.db_config.pool, schedule)
         .await
@@ -142,13 +211,16 @@ async fn temp_actions_work() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -174,13 +246,16 @@ async fn temp_actions_work() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -190,6 +265,39 @@ async fn temp_actions_work() {
     assert_eq!(received_requests.len(), 1);
     let query_param = received_requests[0].url.query().expect("Missing query");
     assert_eq!(query_param, "turn=off");
+
+    let later = now.add(Duration::hours(2));
+    handler
+        .main_handler(
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
+                amount: 20.0,
+                total: None,
+                subsidy: None,
+                currency: "USD".to_string(),
+                starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
+                price_level: None,
+            }),
+            &later,
+        )
+        .await
+        .expect("Handler failed");
+
+    let actions = db::temp_actions::get_temp_actions(&test_config.db_config.pool)
+        .await
+        .expect("Failed to get temp actions");
+    assert!(actions.is_empty());
+    let history = db::temp_actions::get_temp_action_history(
+        &test_config.db_config.pool,
+        &now,
+        &later.add(Duration::seconds(1)),
+    )
+    .await
+    .expect("Failed to get temp action history");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].end_reason, TempActionEndReason::EXPIRED);
+    assert_eq!(history[0].ended_at, later);
 }
 
 #[tokio::test]
@@ -229,7 +337,7 @@ async fn temp_actions_with_start_time_work() {
         .await
         .expect("Couldnt insert plug");
 
-    let schedule = setup::schedule(vec![&rooms[0]]);
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
 
     db::schedules::create_schedule(&test_config.db_config.pool, schedule)
         .await
@@ -249,13 +357,16 @@ async fn temp_actions_with_start_time_work() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -269,13 +380,16 @@ async fn temp_actions_with_start_time_work() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now.add(Duration::minutes(5)),
         )
         .await
@@ -287,6 +401,348 @@ async fn temp_actions_with_start_time_work() {
     assert_eq!(query_param, "turn=off");
 }
 
+#[tokio::test]
+async fn recurring_temp_actions_work() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let mock_server = MockServer::start().await;
+
+    let mock_ip = mock_server.address().ip().to_string();
+    let mock_port = mock_server.address().port();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_server)
+        .await;
+
+    let (handler, rooms) = setup(&test_config.db_config, 1, Some(mock_port)).await;
+    let now = NaiveDateTime::new(
+        NaiveDate::from_weekday_of_month(2020, 1, Weekday::Mon, 1),
+        NaiveTime::from_hms(1, 0, 0),
+    );
+
+    db::temperature_logs::create_temp_log(
+        &test_config.db_config.pool,
+        TemperatureLog {
+            room_id: rooms[0].id,
+            temp: 18.5,
+            time: now.sub(Duration::minutes(30)),
+        },
+    )
+    .await
+    .expect("Failed to create temp log");
+
+    let new_plug = Plug::new("test", &mock_ip, "admin", "password", &rooms[0].id, &true)
+        .expect("Couldnt create plug");
+    db::plugs::create_plug(&test_config.db_config.pool, &new_plug)
+        .await
+        .expect("Couldnt insert plug");
+
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
+
+    db::schedules::create_schedule(&test_config.db_config.pool, schedule)
+        .await
+        .expect("Could insert schedule");
+
+    // Started on Sunday evening, so this occurrence spans midnight
+    db::temp_actions::create_recurring_temp_action(
+        &test_config.db_config.pool,
+        &RecurringTempAction::new(
+            vec![rooms[0].id],
+            &TempActionType::OFF,
+            vec![Weekday::Sun],
+            (NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(1, 30, 0)),
+            None,
+        )
+        .expect("Failed to create recurring temp action"),
+    )
+    .await
+    .expect("Failed to insert recurring temp action");
+
+    let price = PriceInfo {
+        ext_price_level: Some(PriceLevel::Normal),
+        amount: 20.0,
+        total: None,
+        subsidy: None,
+        currency: "USD".to_string(),
+        starts_at: Utc::now().naive_local(),
+        duration_minutes: 60,
+        price_level: None,
+    };
+
+    handler
+        .main_handler(&current(&price), &now)
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 1);
+    let query_param = received_requests[0].url.query().expect("Missing query");
+    assert_eq!(query_param, "turn=off");
+    mock_server.reset().await;
+
+    handler
+        .main_handler(&current(&price), &now.add(Duration::minutes(45)))
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 1);
+    let query_param = received_requests[0].url.query().expect("Missing query");
+    assert_eq!(query_param, "turn=on");
+}
+
+#[tokio::test]
+async fn temp_action_priority_decides_overlaps() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let mock_server = MockServer::start().await;
+
+    let mock_ip = mock_server.address().ip().to_string();
+    let mock_port = mock_server.address().port();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_server)
+        .await;
+
+    let (handler, rooms) = setup(&test_config.db_config, 2, Some(mock_port)).await;
+    let now = NaiveDateTime::new(
+        NaiveDate::from_weekday_of_month(2020, 1, Weekday::Mon, 1),
+        NaiveTime::from_hms(1, 0, 0),
+    );
+
+    db::temperature_logs::create_temp_log(
+        &test_config.db_config.pool,
+        TemperatureLog {
+            room_id: rooms[0].id,
+            temp: 18.5,
+            time: now.sub(Duration::minutes(30)),
+        },
+    )
+    .await
+    .expect("Failed to create temp log");
+
+    let new_plug = Plug::new("test", &mock_ip, "admin", "password", &rooms[0].id, &true)
+        .expect("Couldnt create plug");
+    db::plugs::create_plug(&test_config.db_config.pool, &new_plug)
+        .await
+        .expect("Couldnt insert plug");
+
+    // The whole house is turned on first, then a single room is turned off for longer
+    let house_on = TempAction::new(
+        &None,
+        &now.add(Duration::hours(1)),
+        &TempActionType::ON(None),
+        vec![rooms[0].id, rooms[1].id],
+    );
+    let mut room_off = TempAction::new(
+        &None,
+        &now.add(Duration::hours(2)),
+        &TempActionType::OFF,
+        vec![rooms[0].id],
+    );
+    room_off.created_at = house_on.created_at.add(Duration::seconds(1));
+    db::temp_actions::create_temp_action(&test_config.db_config.pool, house_on.clone())
+        .await
+        .expect("Failed to insert temp action");
+    db::temp_actions::create_temp_action(&test_config.db_config.pool, room_off)
+        .await
+        .expect("Failed to insert temp action");
+
+    let price = PriceInfo {
+        ext_price_level: Some(PriceLevel::Normal),
+        amount: 20.0,
+        total: None,
+        subsidy: None,
+        currency: "USD".to_string(),
+        starts_at: Utc::now().naive_local(),
+        duration_minutes: 60,
+        price_level: None,
+    };
+
+    handler
+        .main_handler(&current(&price), &now)
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 1);
+    let query_param = received_requests[0].url.query().expect("Missing query");
+    assert_eq!(query_param, "turn=off");
+    mock_server.reset().await;
+
+    db::temp_actions::replace_temp_action(
+        &test_config.db_config.pool,
+        TempAction {
+            priority: 1,
+            ..house_on
+        },
+        &now,
+    )
+    .await
+    .expect("Failed to replace temp action");
+
+    handler
+        .main_handler(&current(&price), &now)
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 1);
+    let query_param = received_requests[0].url.query().expect("Missing query");
+    assert_eq!(query_param, "turn=on");
+}
+
+#[tokio::test]
+async fn plug_overrides_take_precedence_over_room() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let mock_server = MockServer::start().await;
+
+    let mock_ip = mock_server.address().ip().to_string();
+    let mock_port = mock_server.address().port();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_server)
+        .await;
+
+    let (handler, rooms) = setup(&test_config.db_config, 1, Some(mock_port)).await;
+    let now = NaiveDateTime::new(
+        NaiveDate::from_weekday_of_month(2020, 1, Weekday::Mon, 1),
+        NaiveTime::from_hms(1, 0, 0),
+    );
+
+    db::temperature_logs::create_temp_log(
+        &test_config.db_config.pool,
+        TemperatureLog {
+            room_id: rooms[0].id,
+            temp: 18.5,
+            time: now.sub(Duration::minutes(30)),
+        },
+    )
+    .await
+    .expect("Failed to create temp log");
+
+    // Plug IPs are unique, so vary the prefix length to reach the same mock server
+    let panel_heater = Plug::new("panel", &mock_ip, "admin", "password", &rooms[0].id, &true)
+        .expect("Couldnt create plug");
+    let floor_heating = Plug::new(
+        "floor",
+        &format!("{}/31", mock_ip),
+        "admin",
+        "password",
+        &rooms[0].id,
+        &true,
+    )
+    .expect("Couldnt create plug");
+    let unscheduled = Plug::new(
+        "fan",
+        &format!("{}/30", mock_ip),
+        "admin",
+        "password",
+        &rooms[0].id,
+        &false,
+    )
+    .expect("Couldnt create plug");
+    for plug in [&panel_heater, &floor_heating, &unscheduled] {
+        db::plugs::create_plug(&test_config.db_config.pool, plug)
+            .await
+            .expect("Couldnt insert plug");
+    }
+
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
+
+    db::schedules::create_schedule(&test_config.db_config.pool, schedule)
+        .await
+        .expect("Could insert schedule");
+
+    db::plug_overrides::create_plug_override(
+        &test_config.db_config.pool,
+        &PlugOverride::new(
+            &None,
+            &now.add(Duration::hours(1)),
+            &ActionType::OFF,
+            vec![panel_heater.id],
+        )
+        .expect("Failed to create plug override"),
+    )
+    .await
+    .expect("Failed to insert plug override");
+
+    db::plug_overrides::create_plug_override(
+        &test_config.db_config.pool,
+        &PlugOverride::new(
+            &None,
+            &now.add(Duration::hours(1)),
+            &ActionType::ON,
+            vec![unscheduled.id],
+        )
+        .expect("Failed to create plug override"),
+    )
+    .await
+    .expect("Failed to insert plug override");
+
+    let price = PriceInfo {
+        ext_price_level: Some(PriceLevel::Normal),
+        amount: 20.0,
+        total: None,
+        subsidy: None,
+        currency: "USD".to_string(),
+        starts_at: Utc::now().naive_local(),
+        duration_minutes: 60,
+        price_level: None,
+    };
+
+    handler
+        .main_handler(&current(&price), &now)
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    let queries: Vec<&str> = received_requests
+        .iter()
+        .map(|r| r.url.query().expect("Missing query"))
+        .collect();
+    assert_eq!(queries.len(), 3);
+    assert_eq!(queries.iter().filter(|q| **q == "turn=on").count(), 2);
+    assert_eq!(queries.iter().filter(|q| **q == "turn=off").count(), 1);
+    mock_server.reset().await;
+
+    handler
+        .main_handler(&current(&price), &now.add(Duration::hours(2)))
+        .await
+        .expect("Handler failed");
+
+    // The unscheduled plug is turned off when its override expires
+    let received_requests = mock_server.received_requests().await.unwrap();
+    let queries: Vec<&str> = received_requests
+        .iter()
+        .map(|r| r.url.query().expect("Missing query"))
+        .collect();
+    assert_eq!(queries.len(), 3);
+    assert_eq!(queries.iter().filter(|q| **q == "turn=on").count(), 2);
+    assert_eq!(queries.iter().filter(|q| **q == "turn=off").count(), 1);
+    let remaining = db::plug_overrides::get_plug_overrides(&test_config.db_config.pool)
+        .await
+        .expect("Failed to get plug overrides");
+    assert!(remaining.is_empty());
+    mock_server.reset().await;
+
+    // And left alone after that
+    handler
+        .main_handler(&current(&price), &now.add(Duration::hours(3)))
+        .await
+        .expect("Handler failed");
+
+    let received_requests = mock_server.received_requests().await.unwrap();
+    assert_eq!(received_requests.len(), 2);
+    assert!(received_requests
+        .iter()
+        .all(|r| r.url.query() == Some("turn=on")));
+}
+
 #[tokio::test]
 async fn temp_actions_override_existing_schedule_temp() {
     let docker = Cli::default();
@@ -324,7 +780,7 @@ async fn temp_actions_override_existing_schedule_temp() {
         .await
         .expect("Couldnt insert plug");
 
-    let schedule = setup::schedule(vec![&rooms[0]]);
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
 
     db::schedules::create_schedule(&test_config.db_config.pool, schedule)
         .await
@@ -344,13 +800,16 @@ async fn temp_actions_override_existing_schedule_temp() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -364,9 +823,14 @@ async fn temp_actions_override_existing_schedule_temp() {
     let actions = db::temp_actions::get_temp_actions(&test_config.db_config.pool)
         .await
         .expect("Failed to get temp actions");
-    db::temp_actions::delete_temp_action(&test_config.db_config.pool, &actions[0].id)
-        .await
-        .expect("Failed to delete temp action");
+    db::temp_actions::archive_temp_action(
+        &test_config.db_config.pool,
+        &actions[0].id,
+        &now,
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .expect("Failed to archive temp action");
 
     mock_server.reset().await;
 
@@ -384,13 +848,16 @@ async fn temp_actions_override_existing_schedule_temp() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -445,6 +912,7 @@ async fn min_temp_overrides_temp_action() {
             id: rooms[0].id,
             name: rooms[0].name.clone(),
             min_temp: Some(22.0),
+            max_temp: None,
         },
     )
     .await
@@ -452,13 +920,16 @@ async fn min_temp_overrides_temp_action() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -484,13 +955,16 @@ async fn min_temp_overrides_temp_action() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -503,7 +977,7 @@ async fn min_temp_overrides_temp_action() {
 
     mock_server.reset().await;
 
-    let schedule = setup::schedule(vec![&rooms[0]]);
+    let schedule = setup::schedule(&test_config.db_config.pool, vec![&rooms[0]]).await;
 
     db::schedules::create_schedule(&test_config.db_config.pool, schedule)
         .await
@@ -522,13 +996,16 @@ async fn min_temp_overrides_temp_action() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -599,13 +1076,16 @@ async fn button_handler() {
 
     handler
         .main_handler(
-            &PriceInfo {
-                ext_price_level: PriceLevel::Normal,
+            &current(&PriceInfo {
+                ext_price_level: Some(PriceLevel::Normal),
                 amount: 20.0,
+                total: None,
+                subsidy: None,
                 currency: "USD".to_string(),
                 starts_at: Utc::now().naive_local(),
+                duration_minutes: 60,
                 price_level: None,
-            },
+            }),
             &now,
         )
         .await
@@ -614,3 +1094,184 @@ async fn button_handler() {
     let received_requests = mock_server.received_requests().await.unwrap();
     assert_eq!(received_requests.len(), 0);
 }
+
+#[tokio::test]
+async fn negative_price_mode_heats_to_max_temp() {
+    let docker = Cli::default();
+    let test_config = DatabaseTestConfig::new(&docker).await;
+    let pool = &test_config.db_config.pool;
+    let mock_server = MockServer::start().await;
+
+    let mock_ip = mock_server.address().ip().to_string();
+    let mock_port = mock_server.address().port();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_server)
+        .await;
+
+    let (handler, rooms) = setup(&test_config.db_config, 1, Some(mock_port)).await;
+    let now = NaiveDateTime::new(
+        NaiveDate::from_weekday_of_month(2020, 1, Weekday::Mon, 1),
+        NaiveTime::from_hms(1, 0, 0),
+    );
+
+    db::rooms::update_room(
+        pool,
+        &Room {
+            id: rooms[0].id,
+            name: rooms[0].name.clone(),
+            min_temp: None,
+            max_temp: Some(24.0),
+        },
+    )
+    .await
+    .expect("Failed to update room");
+    db::price_settings::upsert_price_settings(
+        pool,
+        &PriceSettings {
+            negative_price_mode: true,
+            negative_price_threshold: 0.0,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Failed to upsert price settings");
+
+    let heater = Plug::new("heater", &mock_ip, "admin", "password", &rooms[0].id, &true)
+        .expect("Couldnt create plug");
+    db::plugs::create_plug(pool, &heater)
+        .await
+        .expect("Couldnt insert plug");
+
+    db::temperature_logs::create_temp_log(
+        pool,
+        TemperatureLog {
+            room_id: rooms[0].id,
+            temp: 22.0,
+            time: now.sub(Duration::minutes(30)),
+        },
+    )
+    .await
+    .expect("Failed to create temp log");
+
+    let price = |amount: f64, level: Option<PriceLevel>| PriceInfo {
+        ext_price_level: Some(PriceLevel::Normal),
+        amount,
+        total: None,
+        subsidy: None,
+        currency: "NOK".to_string(),
+        starts_at: now,
+        duration_minutes: 60,
+        price_level: level,
+    };
+    let queries = |requests: Vec<wiremock::Request>| {
+        requests
+            .iter()
+            .map(|request| request.url.query().expect("Missing query").to_string())
+            .collect::<Vec<_>>()
+    };
+
+    handler
+        .main_handler(&current(&price(-0.5, Some(PriceLevel::VeryCheap))), &now)
+        .await
+        .expect("Handler failed");
+    assert_eq!(queries(mock_server.received_requests().await.unwrap()), vec!["turn=on"]);
+    mock_server.reset().await;
+
+    // Without a schedule the room is off outside negative price mode
+    handler
+        .main_handler(&current(&price(1.0, Some(PriceLevel::Normal))), &now)
+        .await
+        .expect("Handler failed");
+    assert_eq!(queries(mock_server.received_requests().await.unwrap()), vec!["turn=off"]);
+    mock_server.reset().await;
+
+    db::plugs::delete_plug(pool, &heater.id)
+        .await
+        .expect("Couldnt delete plug");
+    let water_heater = Plug {
+        opportunistic: true,
+        ..Plug::new("water heater", &mock_ip, "admin", "password", &rooms[0].id, &false)
+            .expect("Couldnt create plug")
+    };
+    db::plugs::create_plug(pool, &water_heater)
+        .await
+        .expect("Couldnt insert plug");
+
+    let fallback = |price: PriceInfo| CurrentPrice {
+        price,
+        degraded: true,
+    };
+    let later = |price: PriceInfo, hours: i64| PriceInfo {
+        starts_at: price.starts_at.add(Duration::hours(hours)),
+        ..price
+    };
+    db::prices::insert_prices(pool, &vec![price(-0.5, Some(PriceLevel::Normal))])
+        .await
+        .expect("Failed to insert prices");
+    // Negative price mode ending is read from the stored prices, so it survives a restart
+    let (restarted, _) = setup(&test_config.db_config, 0, Some(mock_port)).await;
+    for (handler, price, expected) in [
+        // The level is not used, since it is only updated when prices are fetched
+        (&handler, current(&price(-0.5, Some(PriceLevel::Normal))), vec!["turn=on"]),
+        // Turned off during the interval after the negative price, and then left alone
+        (&restarted, current(&later(price(1.0, None), 1)), vec!["turn=off"]),
+        (&restarted, current(&later(price(1.0, None), 2)), vec![]),
+        // Fallback prices are not negative
+        (
+            &restarted,
+            fallback(later(price(-0.5, Some(PriceLevel::VeryCheap)), 2)),
+            vec![],
+        ),
+    ] {
+        handler
+            .main_handler(&price, &now)
+            .await
+            .expect("Handler failed");
+        assert_eq!(queries(mock_server.received_requests().await.unwrap()), expected);
+        mock_server.reset().await;
+    }
+
+    // Turning the room off wins over negative price mode
+    let room_off = TempAction::new(
+        &None,
+        &now.add(Duration::hours(1)),
+        &TempActionType::OFF,
+        vec![rooms[0].id],
+    );
+    db::temp_actions::create_temp_action(pool, room_off.clone())
+        .await
+        .expect("Failed to create temp action");
+    handler
+        .main_handler(&current(&price(-0.5, Some(PriceLevel::VeryCheap))), &now)
+        .await
+        .expect("Handler failed");
+    assert_eq!(queries(mock_server.received_requests().await.unwrap()), vec!["turn=off"]);
+    mock_server.reset().await;
+    db::temp_actions::archive_temp_action(
+        pool,
+        &room_off.id,
+        &now,
+        &TempActionEndReason::CANCELLED,
+    )
+    .await
+    .expect("Failed to archive temp action");
+
+    db::temperature_logs::create_temp_log(
+        pool,
+        TemperatureLog {
+            room_id: rooms[0].id,
+            temp: 24.0,
+            time: now.sub(Duration::minutes(20)),
+        },
+    )
+    .await
+    .expect("Failed to create temp log");
+
+    handler
+        .main_handler(&current(&price(-0.5, Some(PriceLevel::VeryCheap))), &now)
+        .await
+        .expect("Handler failed");
+    assert_eq!(queries(mock_server.received_requests().await.unwrap()), vec!["turn=off"]);
+}